@@ -0,0 +1,70 @@
+use std::{env, fs, process};
+
+use bitvm::op_code_circuits::create_op_code_circuit;
+use bitvm::{hash160_circuit, ripemd160_circuit, sha256_circuit, BinaryCircuit};
+
+const USAGE: &str = "usage: circuit_stats [--optimize] <circuit>...
+
+A circuit is a Bristol file, a JSON circuit file (*.json), an opcode such
+as `op:OP_ADD`, or a hash over a message of N bits: `sha256:N`,
+`ripemd160:N` or `hash160:N`.";
+
+/// Prints the analysis of each circuit given on the command line.
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let optimize = match args.iter().position(|arg| arg == "--optimize") {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    };
+    if args.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for spec in &args {
+        match report(spec, optimize) {
+            Ok(report) => println!("== {}\n{}\n", spec, report),
+            Err(error) => {
+                eprintln!("error: {}: {}", spec, error);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn report(spec: &str, optimize: bool) -> Result<String, String> {
+    let mut circuit = load(spec)?;
+    let mut report = String::new();
+    if optimize {
+        let (optimized, stats) = circuit.optimize().map_err(|e| e.to_string())?;
+        report = format!("optimized: {}\n", stats);
+        circuit = optimized;
+    }
+    let analysis = circuit.analyze().map_err(|e| e.to_string())?;
+    Ok(report + &analysis.to_string())
+}
+
+fn load(spec: &str) -> Result<BinaryCircuit, String> {
+    let message_bits = |bits: &str| bits.parse::<usize>().map_err(|_| format!("invalid bit count {:?}", bits));
+    match spec.split_once(':') {
+        Some(("op", op_code)) => create_op_code_circuit(op_code),
+        Some(("sha256", bits)) => Ok(sha256_circuit(message_bits(bits)?)),
+        Some(("ripemd160", bits)) => Ok(ripemd160_circuit(message_bits(bits)?)),
+        Some(("hash160", bits)) => Ok(hash160_circuit(message_bits(bits)?)),
+        _ => {
+            let source = fs::read_to_string(spec).map_err(|e| e.to_string())?;
+            if spec.ends_with(".json") {
+                BinaryCircuit::from_json(&source).map_err(|e| e.to_string())
+            } else {
+                BinaryCircuit::from_bristol_format(&source).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
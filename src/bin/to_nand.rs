@@ -0,0 +1,34 @@
+use std::{env, fs, process};
+
+use bitvm::BinaryCircuit;
+
+/// Converts a Bristol circuit into NAND gates and writes it back as Bristol.
+///
+/// Usage: `to_nand <circuit.txt> [<output.txt>]`; without an output path the
+/// converted circuit is printed. The gate-count report goes to stderr.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.len() > 2 {
+        eprintln!("usage: to_nand <circuit.txt> [<output.txt>]");
+        process::exit(2);
+    }
+
+    if let Err(error) = run(&args[0], args.get(1)) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run(input: &str, output: Option<&String>) -> Result<(), String> {
+    let source = fs::read_to_string(input).map_err(|e| format!("cannot read {}: {}", input, e))?;
+    let circuit = BinaryCircuit::from_bristol_format(&source).map_err(|e| format!("{}: {}", input, e))?;
+    let conversion = circuit.to_nand_only().map_err(|e| e.to_string())?;
+    let bristol = conversion.circuit.to_bristol_format().map_err(|e| e.to_string())?;
+
+    match output {
+        Some(path) => fs::write(path, bristol).map_err(|e| format!("cannot write {}: {}", path, e))?,
+        None => print!("{}", bristol),
+    }
+    eprintln!("{}", conversion);
+    Ok(())
+}
@@ -0,0 +1,325 @@
+//! Bisection game narrowing a disputed evaluation down to a single gate.
+//!
+//! Gates are numbered in topological order. The state after step `m` is
+//! made of the input values followed by the outputs of the first `m` gates.
+//! Both parties agree on the state at step 0, because the inputs are public.
+//! The prover claims a digest of the final state. While the last agreed step
+//! `lo` and the first disputed step `hi` are more than one step apart, the
+//! challenger asks for the digest at the midpoint and moves `lo` or `hi`
+//! depending on whether it matches its own. Once `hi = lo + 1`, the prover
+//! opens the gate of step `lo`. The challenger checks that opening against
+//! the agreed state, the disputed digest and the gate's truth table, which
+//! takes about `log2(gates)` queries.
+
+use std::fmt;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+
+use crate::binary_circuit::{BinaryCircuit, EvalError};
+use crate::logic_gate::GateId;
+
+/// Error raised by a party of the bisection game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BisectionError {
+    /// A trace has a value count different from the circuit's wire count.
+    TraceLength { expected: usize, found: usize },
+    Eval(EvalError),
+    /// The message does not fit the current phase of the game.
+    UnexpectedMessage,
+    /// The message refers to a step the circuit does not have.
+    StepOutOfRange(usize),
+}
+
+impl fmt::Display for BisectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BisectionError::TraceLength { expected, found } => {
+                write!(f, "expected a trace of {} wires, found {}", expected, found)
+            }
+            BisectionError::Eval(error) => write!(f, "cannot evaluate circuit: {}", error),
+            BisectionError::UnexpectedMessage => write!(f, "message does not fit the state of the game"),
+            BisectionError::StepOutOfRange(step) => write!(f, "step {} is out of range", step),
+        }
+    }
+}
+
+impl std::error::Error for BisectionError {}
+
+impl From<EvalError> for BisectionError {
+    fn from(error: EvalError) -> Self {
+        BisectionError::Eval(error)
+    }
+}
+
+/// Values of all wires of a circuit, as claimed by one party.
+#[derive(Debug, Clone)]
+pub struct ExecutionTrace<'a> {
+    circuit: &'a BinaryCircuit,
+    order: Vec<GateId>,
+    values: Vec<bool>,
+}
+
+impl<'a> ExecutionTrace<'a> {
+    /// Wraps wire values indexed by wire, such as the result of `BinaryCircuit::evaluate`.
+    pub fn new(circuit: &'a BinaryCircuit, values: Vec<bool>) -> Result<Self, BisectionError> {
+        if values.len() != circuit.wire_count() {
+            return Err(BisectionError::TraceLength { expected: circuit.wire_count(), found: values.len() });
+        }
+        Ok(ExecutionTrace { circuit, order: circuit.topological_order()?, values })
+    }
+
+    /// Honest trace of `circuit` on `inputs`.
+    pub fn evaluate(circuit: &'a BinaryCircuit, inputs: &[bool]) -> Result<Self, BisectionError> {
+        ExecutionTrace::new(circuit, circuit.evaluate(inputs)?)
+    }
+
+    pub fn circuit(&self) -> &'a BinaryCircuit {
+        self.circuit
+    }
+
+    pub fn values(&self) -> &[bool] {
+        &self.values
+    }
+
+    /// Number of steps, one per gate.
+    pub fn steps(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Gate evaluated at `step`.
+    pub fn gate_at(&self, step: usize) -> Option<GateId> {
+        self.order.get(step).copied()
+    }
+
+    /// Values making up the state after `step` gates.
+    pub fn state(&self, step: usize) -> Vec<bool> {
+        let inputs = self.circuit.inputs().iter();
+        let outputs = self.order[..step].iter().map(|&id| &self.circuit.gate(id).output);
+        inputs.chain(outputs).map(|wire| self.values[wire.0]).collect()
+    }
+
+    pub fn state_digest(&self, step: usize) -> sha256::Hash {
+        state_digest(step, &self.state(step))
+    }
+
+    /// Operand and output values of the gate at `step`.
+    pub fn opening(&self, step: usize) -> Option<(Vec<bool>, bool)> {
+        let gate = self.circuit.gate(self.gate_at(step)?);
+        let inputs = gate.inputs.iter().map(|wire| self.values[wire.0]).collect();
+        Some((inputs, self.values[gate.output.0]))
+    }
+}
+
+/// SHA-256 of the step number followed by one byte per state value.
+pub fn state_digest(step: usize, state: &[bool]) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+    engine.input(&(step as u64).to_be_bytes());
+    for &value in state {
+        engine.input(&[value as u8]);
+    }
+    sha256::Hash::from_engine(engine)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChallengerMessage {
+    /// Asks for the digest of the state after `step` gates.
+    Query { step: usize },
+    /// Asks to open the gate evaluated at `step`.
+    Open { step: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProverMessage {
+    /// Digest of the final state, opening the game.
+    Claim { digest: sha256::Hash },
+    Digest { step: usize, digest: sha256::Hash },
+    /// Operand and output values of the gate evaluated at `step`.
+    Opening { step: usize, inputs: Vec<bool>, output: bool },
+}
+
+/// How a game ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The prover's claim matches the challenger's evaluation.
+    Agreed,
+    /// The opened gate does not compute its opened output.
+    FaultyGate { step: usize, gate: GateId },
+    /// The opening contradicts the agreed state or the prover's own digest.
+    InconsistentOpening { step: usize, gate: GateId },
+    /// The opened gate is correct, so the challenger's own trace was wrong.
+    GateHolds { step: usize, gate: GateId },
+    /// The claim differs from the state of a circuit without gates, which is public.
+    InvalidClaim,
+}
+
+impl Outcome {
+    /// Whether the challenger showed the prover's claim to be wrong.
+    pub fn prover_caught(&self) -> bool {
+        matches!(self, Outcome::FaultyGate { .. } | Outcome::InconsistentOpening { .. } | Outcome::InvalidClaim)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Phase {
+    AwaitingClaim,
+    AwaitingDigest { step: usize },
+    AwaitingOpening { step: usize },
+    Done(Outcome),
+}
+
+/// Challenger holding the honest trace.
+#[derive(Debug, Clone)]
+pub struct Challenger<'a> {
+    trace: ExecutionTrace<'a>,
+    /// Last step with agreed state and first step with a disputed one.
+    lo: usize,
+    hi: usize,
+    /// The prover's digest of the state at `hi`.
+    disputed: Option<sha256::Hash>,
+    phase: Phase,
+}
+
+impl<'a> Challenger<'a> {
+    pub fn new(trace: ExecutionTrace<'a>) -> Self {
+        let hi = trace.steps();
+        Challenger { trace, lo: 0, hi, disputed: None, phase: Phase::AwaitingClaim }
+    }
+
+    /// Steps still in dispute, as `(last agreed, first disputed)`.
+    pub fn range(&self) -> (usize, usize) {
+        (self.lo, self.hi)
+    }
+
+    pub fn outcome(&self) -> Option<&Outcome> {
+        match &self.phase {
+            Phase::Done(outcome) => Some(outcome),
+            _ => None,
+        }
+    }
+
+    /// Processes a prover message and returns the next query, or `None` once the game is over.
+    pub fn receive(&mut self, message: &ProverMessage) -> Result<Option<ChallengerMessage>, BisectionError> {
+        match (&self.phase, message) {
+            (Phase::AwaitingClaim, ProverMessage::Claim { digest }) => {
+                if *digest == self.trace.state_digest(self.hi) {
+                    return Ok(self.finish(Outcome::Agreed));
+                }
+                if self.hi == 0 {
+                    return Ok(self.finish(Outcome::InvalidClaim));
+                }
+                self.disputed = Some(*digest);
+            }
+            (&Phase::AwaitingDigest { step }, &ProverMessage::Digest { step: answered, digest }) if answered == step => {
+                if digest == self.trace.state_digest(step) {
+                    self.lo = step;
+                } else {
+                    self.hi = step;
+                    self.disputed = Some(digest);
+                }
+            }
+            (&Phase::AwaitingOpening { step }, ProverMessage::Opening { step: answered, inputs, output }) if *answered == step => {
+                let outcome = self.judge(step, inputs, *output);
+                return Ok(self.finish(outcome));
+            }
+            _ => return Err(BisectionError::UnexpectedMessage),
+        }
+
+        let next = if self.hi - self.lo == 1 {
+            self.phase = Phase::AwaitingOpening { step: self.lo };
+            ChallengerMessage::Open { step: self.lo }
+        } else {
+            let step = self.lo + (self.hi - self.lo) / 2;
+            self.phase = Phase::AwaitingDigest { step };
+            ChallengerMessage::Query { step }
+        };
+        Ok(Some(next))
+    }
+
+    fn judge(&self, step: usize, inputs: &[bool], output: bool) -> Outcome {
+        let gate = self.trace.gate_at(step).expect("opened step is in range");
+        let (agreed_inputs, _) = self.trace.opening(step).expect("opened step is in range");
+        let mut state = self.trace.state(step);
+        state.push(output);
+        if inputs != &agreed_inputs[..] || Some(state_digest(step + 1, &state)) != self.disputed {
+            return Outcome::InconsistentOpening { step, gate };
+        }
+        match self.trace.circuit().gate(gate).evaluate(inputs) {
+            Some(value) if value == output => Outcome::GateHolds { step, gate },
+            _ => Outcome::FaultyGate { step, gate },
+        }
+    }
+
+    fn finish(&mut self, outcome: Outcome) -> Option<ChallengerMessage> {
+        self.phase = Phase::Done(outcome);
+        None
+    }
+}
+
+/// Prover answering from the trace it claims.
+#[derive(Debug, Clone)]
+pub struct Prover<'a> {
+    trace: ExecutionTrace<'a>,
+}
+
+impl<'a> Prover<'a> {
+    pub fn new(trace: ExecutionTrace<'a>) -> Self {
+        Prover { trace }
+    }
+
+    pub fn claim(&self) -> ProverMessage {
+        ProverMessage::Claim { digest: self.trace.state_digest(self.trace.steps()) }
+    }
+
+    pub fn respond(&self, message: &ChallengerMessage) -> Result<ProverMessage, BisectionError> {
+        match *message {
+            ChallengerMessage::Query { step } if step <= self.trace.steps() => {
+                Ok(ProverMessage::Digest { step, digest: self.trace.state_digest(step) })
+            }
+            ChallengerMessage::Open { step } => {
+                let (inputs, output) = self.trace.opening(step).ok_or(BisectionError::StepOutOfRange(step))?;
+                Ok(ProverMessage::Opening { step, inputs, output })
+            }
+            ChallengerMessage::Query { step } => Err(BisectionError::StepOutOfRange(step)),
+        }
+    }
+}
+
+/// A message sent during a simulated game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Prover(ProverMessage),
+    Challenger(ChallengerMessage),
+}
+
+/// Record of a simulated game.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub messages: Vec<Message>,
+    pub outcome: Outcome,
+}
+
+impl Transcript {
+    /// Number of challenger messages.
+    pub fn rounds(&self) -> usize {
+        self.messages.iter().filter(|message| matches!(message, Message::Challenger(_))).count()
+    }
+}
+
+/// Plays a whole game between the two parties.
+pub fn simulate(prover: &Prover, challenger: &mut Challenger) -> Result<Transcript, BisectionError> {
+    let mut messages = Vec::new();
+    let mut answer = prover.claim();
+    loop {
+        let query = challenger.receive(&answer)?;
+        messages.push(Message::Prover(answer));
+        match query {
+            Some(query) => {
+                answer = prover.respond(&query)?;
+                messages.push(Message::Challenger(query));
+            }
+            None => break,
+        }
+    }
+    let outcome = challenger.outcome().expect("game is over").clone();
+    Ok(Transcript { messages, outcome })
+}
@@ -0,0 +1,129 @@
+//! Block-height deadlines for the rounds of a dispute.
+//!
+//! A round starts when one party's message is confirmed at some height and
+//! ends `timeout` blocks later. The other party has to answer before then.
+//! Otherwise the waiting party can claim the funds through a leaf of
+//! `scripts::timelocks::relative_timeout_script` with the same timeout.
+
+use std::fmt;
+
+use bitcoin::Sequence;
+
+/// Blocks a party has to answer in, unless configured otherwise (about a day).
+pub const DEFAULT_ROUND_TIMEOUT: u16 = 144;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline {
+    /// Height the round started at.
+    pub start: u32,
+    /// Length of the round in blocks.
+    pub timeout: u16,
+}
+
+impl Deadline {
+    pub fn new(start: u32, timeout: u16) -> Self {
+        Deadline { start, timeout }
+    }
+
+    /// First height at which the round is over.
+    pub fn height(&self) -> u32 {
+        self.start.saturating_add(self.timeout as u32)
+    }
+
+    pub fn expired(&self, height: u32) -> bool {
+        height >= self.height()
+    }
+
+    /// Sequence of an input that spends the round's output through its timeout leaf.
+    pub fn sequence(&self) -> Sequence {
+        Sequence::from_height(self.timeout)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Party {
+    Prover,
+    Challenger,
+}
+
+impl Party {
+    pub fn other(self) -> Party {
+        match self {
+            Party::Prover => Party::Challenger,
+            Party::Challenger => Party::Prover,
+        }
+    }
+}
+
+/// Error raised when a move breaks the turn order or the deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockError {
+    NotYourTurn(Party),
+    /// The move came at or after the deadline of the round.
+    Late { party: Party, deadline: u32, height: u32 },
+    /// A party already won by timeout.
+    Over(Party),
+}
+
+impl fmt::Display for ClockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClockError::NotYourTurn(party) => write!(f, "it is not the {:?}'s turn", party),
+            ClockError::Late { party, deadline, height } => {
+                write!(f, "{:?} moved at height {}, the deadline was {}", party, height, deadline)
+            }
+            ClockError::Over(winner) => write!(f, "the {:?} already won by timeout", winner),
+        }
+    }
+}
+
+impl std::error::Error for ClockError {}
+
+/// Alternating turns with a deadline per round.
+#[derive(Debug, Clone)]
+pub struct DisputeClock {
+    timeout: u16,
+    turn: Party,
+    deadline: Deadline,
+    winner: Option<Party>,
+}
+
+impl DisputeClock {
+    /// Starts the first round at `height`, waiting for `first`.
+    pub fn new(first: Party, height: u32, timeout: u16) -> Self {
+        DisputeClock { timeout, turn: first, deadline: Deadline::new(height, timeout), winner: None }
+    }
+
+    /// Party the current round waits for.
+    pub fn turn(&self) -> Party {
+        self.turn
+    }
+
+    pub fn deadline(&self) -> Deadline {
+        self.deadline
+    }
+
+    /// Records a move confirmed at `height` and starts the other party's round.
+    pub fn record_move(&mut self, party: Party, height: u32) -> Result<(), ClockError> {
+        if let Some(winner) = self.winner {
+            return Err(ClockError::Over(winner));
+        }
+        if party != self.turn {
+            return Err(ClockError::NotYourTurn(party));
+        }
+        if self.deadline.expired(height) {
+            return Err(ClockError::Late { party, deadline: self.deadline.height(), height });
+        }
+        self.turn = party.other();
+        self.deadline = Deadline::new(height, self.timeout);
+        Ok(())
+    }
+
+    /// Party that wins because the other one missed its deadline by `height`.
+    pub fn check_timeout(&mut self, height: u32) -> Option<Party> {
+        if self.winner.is_none() && self.deadline.expired(height) {
+            self.winner = Some(self.turn.other());
+        }
+        self.winner
+    }
+}
@@ -0,0 +1,135 @@
+//! SHA-256 Merkle commitment to the wire values of an evaluation.
+//!
+//! Leaf `i` is `SHA256(v)` with `v` the single byte 0 or 1 holding the value
+//! of wire `i`. Inner nodes are `SHA256(left || right)`, so a proof can be
+//! checked in Script with `OP_CAT` and `OP_SHA256`. The leaves are padded to
+//! a power of two with `SHA256("")`, which no wire value hashes to.
+
+use std::convert::TryFrom;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+
+use crate::binary_circuit::BinaryCircuit;
+use crate::logic_gate::{GateId, WireId};
+
+/// Most siblings a proof can have: one less than the bits of a wire index.
+pub const MAX_DEPTH: usize = usize::BITS as usize - 1;
+
+pub fn leaf_hash(value: bool) -> sha256::Hash {
+    sha256::Hash::hash(&[value as u8])
+}
+
+fn padding_hash() -> sha256::Hash {
+    sha256::Hash::hash(&[])
+}
+
+fn node_hash(left: &sha256::Hash, right: &sha256::Hash) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+    engine.input(left.as_byte_array());
+    engine.input(right.as_byte_array());
+    sha256::Hash::from_engine(engine)
+}
+
+/// Merkle tree over a trace, indexed by wire.
+#[derive(Debug, Clone)]
+pub struct TraceTree {
+    values: Vec<bool>,
+    /// Tree levels from the leaves up to the root.
+    levels: Vec<Vec<sha256::Hash>>,
+}
+
+impl TraceTree {
+    pub fn new(values: &[bool]) -> Self {
+        let width = values.len().next_power_of_two();
+        let mut level: Vec<sha256::Hash> = values.iter().map(|&value| leaf_hash(value)).collect();
+        level.resize(width, padding_hash());
+        let mut levels = vec![level];
+        while levels.last().expect("leaf level").len() > 1 {
+            let parents = levels.last().expect("leaf level").chunks(2).map(|pair| node_hash(&pair[0], &pair[1])).collect();
+            levels.push(parents);
+        }
+        TraceTree { values: values.to_vec(), levels }
+    }
+
+    pub fn root(&self) -> sha256::Hash {
+        self.levels.last().expect("root level")[0]
+    }
+
+    pub fn values(&self) -> &[bool] {
+        &self.values
+    }
+
+    /// Proof of the value of `wire`, or `None` if the trace has no such wire.
+    pub fn prove(&self, wire: WireId) -> Option<MerkleProof> {
+        let value = *self.values.get(wire.0)?;
+        let siblings = self.levels[..self.levels.len() - 1].iter().enumerate()
+            .map(|(height, level)| level[(wire.0 >> height) ^ 1])
+            .collect();
+        Some(MerkleProof { wire, value, siblings })
+    }
+
+    /// Proofs of the operands and output of a gate of `circuit`.
+    pub fn prove_gate(&self, circuit: &BinaryCircuit, gate: GateId) -> Option<GateProof> {
+        let logic_gate = circuit.gates().get(gate.0)?;
+        let inputs = logic_gate.inputs.iter().map(|&wire| self.prove(wire)).collect::<Option<_>>()?;
+        Some(GateProof { gate, inputs, output: self.prove(logic_gate.output)? })
+    }
+}
+
+/// Inclusion proof of one wire value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub wire: WireId,
+    pub value: bool,
+    /// Sibling hashes from the leaf level up.
+    pub siblings: Vec<sha256::Hash>,
+}
+
+impl MerkleProof {
+    /// Root the proof leads to. Siblings past the width of a wire index
+    /// hash the node as a left child.
+    pub fn root(&self) -> sha256::Hash {
+        self.siblings.iter().enumerate().fold(leaf_hash(self.value), |node, (height, sibling)| {
+            let shifted = u32::try_from(height).ok().and_then(|height| self.wire.0.checked_shr(height));
+            if shifted.unwrap_or(0) & 1 == 0 {
+                node_hash(&node, sibling)
+            } else {
+                node_hash(sibling, &node)
+            }
+        })
+    }
+
+    /// Whether the proof leads to `root`. Proofs with more than `MAX_DEPTH`
+    /// siblings belong to no tree that can be built.
+    pub fn verify(&self, root: &sha256::Hash) -> bool {
+        self.siblings.len() <= MAX_DEPTH && self.wire.0 >> self.siblings.len() == 0 && self.root() == *root
+    }
+}
+
+/// Values around one gate, each with its inclusion proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GateProof {
+    pub gate: GateId,
+    pub inputs: Vec<MerkleProof>,
+    pub output: MerkleProof,
+}
+
+impl GateProof {
+    /// Whether the proofs are for the wires of the gate in `circuit` and lead to `root`.
+    pub fn verify(&self, circuit: &BinaryCircuit, root: &sha256::Hash) -> bool {
+        let logic_gate = match circuit.gates().get(self.gate.0) {
+            Some(logic_gate) => logic_gate,
+            None => return false,
+        };
+        let wires_match = self.inputs.len() == logic_gate.inputs.len()
+            && self.inputs.iter().zip(&logic_gate.inputs).all(|(proof, &wire)| proof.wire == wire)
+            && self.output.wire == logic_gate.output;
+        wires_match && self.inputs.iter().chain(Some(&self.output)).all(|proof| proof.verify(root))
+    }
+
+    /// Whether the proven output is what the gate computes from the proven operands.
+    pub fn gate_holds(&self, circuit: &BinaryCircuit) -> bool {
+        let inputs: Vec<bool> = self.inputs.iter().map(|proof| proof.value).collect();
+        circuit.gate(self.gate).evaluate(&inputs) == Some(self.output.value)
+    }
+}
@@ -0,0 +1,451 @@
+//! Versioned wire format for the challenge-response messages.
+//!
+//! Every message is an `Envelope`: the format version, the id of the dispute
+//! session, a nonce and a payload. Envelopes encode to JSON or to a compact
+//! binary form. Decoding is strict: unknown versions, types or fields,
+//! non-canonical values and trailing bytes are rejected. A receiver passes
+//! decoded envelopes through a `ReplayGuard`, which accepts only the
+//! session's messages with strictly increasing nonces.
+//!
+//! JSON:
+//!
+//! ```text
+//! {"version": 1, "session": "<32 hex digits>", "nonce": 7,
+//!  "payload": {"type": "challenge", "gate_indices": [3, 17]}}
+//! ```
+//!
+//! Binary, with integers big-endian: version (1 byte), session (16 bytes),
+//! nonce (8 bytes), payload type (1 byte), then the payload. Counts and
+//! indices take 4 bytes, and booleans take one byte holding 0 or 1.
+
+use std::convert::TryInto;
+use std::fmt;
+
+use bitcoin::hashes::{sha256, Hash};
+use serde_json::{json, Map, Value};
+
+use crate::challenge_response::merkle::{GateProof, MerkleProof, MAX_DEPTH};
+use crate::challenge_response::protocol::{Challenge, Response};
+use crate::circuit_logic::{GateId, WireId};
+
+pub const FORMAT_VERSION: u8 = 1;
+
+const COMMITMENT: u8 = 0;
+const CHALLENGE: u8 = 1;
+const RESPONSE: u8 = 2;
+
+/// Error raised while decoding or accepting a message.
+#[derive(Debug)]
+pub enum MessageError {
+    /// The document is not valid JSON.
+    Syntax(serde_json::Error),
+    UnsupportedVersion(u64),
+    UnknownType(String),
+    /// A JSON field is missing, unknown or has an invalid value.
+    InvalidField { field: String, reason: String },
+    /// The binary message ends early.
+    Truncated,
+    /// The binary message has bytes after its end.
+    TrailingBytes(usize),
+    /// A binary boolean is neither 0 nor 1.
+    InvalidBool(u8),
+    /// An index or count does not fit in the 4 bytes of the binary form.
+    IndexOutOfRange(usize),
+    /// The message belongs to another session.
+    WrongSession(SessionId),
+    /// The nonce is not above the last one accepted.
+    Replayed { nonce: u64, last: u64 },
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageError::Syntax(e) => write!(f, "invalid JSON: {}", e),
+            MessageError::UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            MessageError::UnknownType(kind) => write!(f, "unknown message type {}", kind),
+            MessageError::InvalidField { field, reason } => write!(f, "field {}: {}", field, reason),
+            MessageError::Truncated => write!(f, "message ends early"),
+            MessageError::TrailingBytes(count) => write!(f, "{} bytes after the end of the message", count),
+            MessageError::InvalidBool(byte) => write!(f, "invalid boolean byte {}", byte),
+            MessageError::IndexOutOfRange(index) => write!(f, "index {} does not fit in 32 bits", index),
+            MessageError::WrongSession(session) => write!(f, "message for session {}", session),
+            MessageError::Replayed { nonce, last } => write!(f, "nonce {} is not above {}", nonce, last),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+impl From<serde_json::Error> for MessageError {
+    fn from(e: serde_json::Error) -> Self {
+        MessageError::Syntax(e)
+    }
+}
+
+/// Identifier of one dispute between a prover and a challenger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionId(pub [u8; 16]);
+
+impl SessionId {
+    pub fn random() -> Self {
+        SessionId(rand::random())
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    /// Merkle root of the prover's trace, see `merkle::TraceTree`.
+    Commitment { root: sha256::Hash },
+    Challenge(Challenge),
+    Response(Response),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub session: SessionId,
+    pub nonce: u64,
+    pub payload: Payload,
+}
+
+/// Sending side of a session, numbering messages from 1.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: SessionId,
+    next_nonce: u64,
+}
+
+impl Session {
+    pub fn new(id: SessionId) -> Self {
+        Session { id, next_nonce: 1 }
+    }
+
+    pub fn seal(&mut self, payload: Payload) -> Envelope {
+        let nonce = self.next_nonce;
+        self.next_nonce += 1;
+        Envelope { session: self.id, nonce, payload }
+    }
+}
+
+/// Receiving side of a session, rejecting foreign and replayed messages.
+#[derive(Debug, Clone)]
+pub struct ReplayGuard {
+    session: SessionId,
+    last_nonce: u64,
+}
+
+impl ReplayGuard {
+    pub fn new(session: SessionId) -> Self {
+        ReplayGuard { session, last_nonce: 0 }
+    }
+
+    pub fn accept(&mut self, envelope: Envelope) -> Result<Payload, MessageError> {
+        if envelope.session != self.session {
+            return Err(MessageError::WrongSession(envelope.session));
+        }
+        if envelope.nonce <= self.last_nonce {
+            return Err(MessageError::Replayed { nonce: envelope.nonce, last: self.last_nonce });
+        }
+        self.last_nonce = envelope.nonce;
+        Ok(envelope.payload)
+    }
+}
+
+impl Envelope {
+    pub fn to_json(&self) -> String {
+        let payload = match &self.payload {
+            Payload::Commitment { root } => json!({"type": "commitment", "root": root.to_string()}),
+            Payload::Challenge(challenge) => json!({
+                "type": "challenge",
+                "gate_indices": challenge.gate_indices.iter().map(|gate| gate.0).collect::<Vec<_>>(),
+            }),
+            Payload::Response(response) => json!({
+                "type": "response",
+                "gate_outputs": response.gate_outputs,
+                "proofs": response.proofs.iter().map(gate_proof_json).collect::<Vec<_>>(),
+            }),
+        };
+        json!({
+            "version": FORMAT_VERSION,
+            "session": self.session.to_string(),
+            "nonce": self.nonce,
+            "payload": payload,
+        })
+        .to_string()
+    }
+
+    pub fn from_json(source: &str) -> Result<Envelope, MessageError> {
+        let value: Value = serde_json::from_str(source)?;
+        let envelope = object(&value, "message", &["version", "session", "nonce", "payload"])?;
+        let version = number(&envelope["version"], "version")?;
+        if version != FORMAT_VERSION as u64 {
+            return Err(MessageError::UnsupportedVersion(version));
+        }
+        let session = session(&envelope["session"])?;
+        let nonce = number(&envelope["nonce"], "nonce")?;
+
+        let kind = envelope["payload"].get("type").and_then(Value::as_str).ok_or_else(|| invalid("payload.type", "expected a string"))?;
+        let payload = match kind {
+            "commitment" => {
+                let fields = object(&envelope["payload"], "payload", &["type", "root"])?;
+                Payload::Commitment { root: hash(&fields["root"], "payload.root")? }
+            }
+            "challenge" => {
+                let fields = object(&envelope["payload"], "payload", &["type", "gate_indices"])?;
+                let gate_indices = array(&fields["gate_indices"], "payload.gate_indices")?.iter()
+                    .map(|index| index_of(index, "payload.gate_indices").map(GateId))
+                    .collect::<Result<_, _>>()?;
+                Payload::Challenge(Challenge { gate_indices })
+            }
+            "response" => {
+                let fields = object(&envelope["payload"], "payload", &["type", "gate_outputs", "proofs"])?;
+                let gate_outputs = array(&fields["gate_outputs"], "payload.gate_outputs")?.iter()
+                    .map(|output| boolean(output, "payload.gate_outputs"))
+                    .collect::<Result<_, _>>()?;
+                let proofs = array(&fields["proofs"], "payload.proofs")?.iter()
+                    .map(gate_proof_from_json)
+                    .collect::<Result<_, _>>()?;
+                Payload::Response(Response { gate_outputs, proofs })
+            }
+            other => return Err(MessageError::UnknownType(other.to_string())),
+        };
+        Ok(Envelope { session, nonce, payload })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MessageError> {
+        let mut bytes = vec![FORMAT_VERSION];
+        bytes.extend_from_slice(&self.session.0);
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+        match &self.payload {
+            Payload::Commitment { root } => {
+                bytes.push(COMMITMENT);
+                bytes.extend_from_slice(root.as_byte_array());
+            }
+            Payload::Challenge(challenge) => {
+                bytes.push(CHALLENGE);
+                put_index(&mut bytes, challenge.gate_indices.len())?;
+                for gate in &challenge.gate_indices {
+                    put_index(&mut bytes, gate.0)?;
+                }
+            }
+            Payload::Response(response) => {
+                bytes.push(RESPONSE);
+                put_index(&mut bytes, response.gate_outputs.len())?;
+                bytes.extend(response.gate_outputs.iter().map(|&output| output as u8));
+                put_index(&mut bytes, response.proofs.len())?;
+                for proof in &response.proofs {
+                    put_index(&mut bytes, proof.gate.0)?;
+                    put_index(&mut bytes, proof.inputs.len())?;
+                    for wire in proof.inputs.iter().chain(Some(&proof.output)) {
+                        put_merkle_proof(&mut bytes, wire)?;
+                    }
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Envelope, MessageError> {
+        let mut reader = Reader { bytes };
+        let version = reader.byte()?;
+        if version != FORMAT_VERSION {
+            return Err(MessageError::UnsupportedVersion(version as u64));
+        }
+        let session = SessionId(reader.take(16)?.try_into().expect("16 bytes"));
+        let nonce = u64::from_be_bytes(reader.take(8)?.try_into().expect("8 bytes"));
+        let payload = match reader.byte()? {
+            COMMITMENT => Payload::Commitment { root: reader.hash()? },
+            CHALLENGE => {
+                let count = reader.index()?;
+                let gate_indices = (0..count).map(|_| reader.index().map(GateId)).collect::<Result<_, _>>()?;
+                Payload::Challenge(Challenge { gate_indices })
+            }
+            RESPONSE => {
+                let count = reader.index()?;
+                let gate_outputs = (0..count).map(|_| reader.boolean()).collect::<Result<_, _>>()?;
+                let count = reader.index()?;
+                let proofs = (0..count)
+                    .map(|_| {
+                        let gate = GateId(reader.index()?);
+                        let operands = reader.index()?;
+                        let inputs = (0..operands).map(|_| reader.merkle_proof()).collect::<Result<_, _>>()?;
+                        Ok(GateProof { gate, inputs, output: reader.merkle_proof()? })
+                    })
+                    .collect::<Result<_, MessageError>>()?;
+                Payload::Response(Response { gate_outputs, proofs })
+            }
+            other => return Err(MessageError::UnknownType(other.to_string())),
+        };
+        if !reader.bytes.is_empty() {
+            return Err(MessageError::TrailingBytes(reader.bytes.len()));
+        }
+        Ok(Envelope { session, nonce, payload })
+    }
+}
+
+fn invalid(field: &str, reason: &str) -> MessageError {
+    MessageError::InvalidField { field: field.to_string(), reason: reason.to_string() }
+}
+
+/// The object in `value`, which must have exactly `fields`.
+fn object<'a>(value: &'a Value, field: &str, fields: &[&str]) -> Result<&'a Map<String, Value>, MessageError> {
+    let object = value.as_object().ok_or_else(|| invalid(field, "expected an object"))?;
+    if let Some(unknown) = object.keys().find(|key| !fields.contains(&key.as_str())) {
+        return Err(invalid(&format!("{}.{}", field, unknown), "unknown field"));
+    }
+    if let Some(missing) = fields.iter().find(|&&name| !object.contains_key(name)) {
+        return Err(invalid(&format!("{}.{}", field, missing), "missing field"));
+    }
+    Ok(object)
+}
+
+fn number(value: &Value, field: &str) -> Result<u64, MessageError> {
+    value.as_u64().ok_or_else(|| invalid(field, "expected a non-negative integer"))
+}
+
+fn index_of(value: &Value, field: &str) -> Result<usize, MessageError> {
+    number(value, field)?.try_into().ok().filter(|&index: &usize| index <= u32::MAX as usize)
+        .ok_or_else(|| invalid(field, "index out of range"))
+}
+
+fn string<'a>(value: &'a Value, field: &str) -> Result<&'a str, MessageError> {
+    value.as_str().ok_or_else(|| invalid(field, "expected a string"))
+}
+
+fn boolean(value: &Value, field: &str) -> Result<bool, MessageError> {
+    value.as_bool().ok_or_else(|| invalid(field, "expected a boolean"))
+}
+
+fn array<'a>(value: &'a Value, field: &str) -> Result<&'a Vec<Value>, MessageError> {
+    value.as_array().ok_or_else(|| invalid(field, "expected an array"))
+}
+
+/// A session id in lower-case hex, as written by `to_json`.
+fn session(value: &Value) -> Result<SessionId, MessageError> {
+    let text = string(value, "session")?;
+    hex::decode(text).ok()
+        .filter(|bytes| hex::encode(bytes) == text)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(SessionId)
+        .ok_or_else(|| invalid("session", "expected 16 bytes in lower-case hex"))
+}
+
+/// A hash in lower-case hex, as written by `to_json`.
+fn hash(value: &Value, field: &str) -> Result<sha256::Hash, MessageError> {
+    let text = string(value, field)?;
+    hex::decode(text).ok()
+        .filter(|bytes| bytes.len() == 32 && hex::encode(bytes) == text)
+        .and_then(|bytes| sha256::Hash::from_slice(&bytes).ok())
+        .ok_or_else(|| invalid(field, "expected 32 bytes in lower-case hex"))
+}
+
+fn merkle_proof_json(proof: &MerkleProof) -> Value {
+    json!({
+        "wire": proof.wire.0,
+        "value": proof.value,
+        "siblings": proof.siblings.iter().map(|sibling| sibling.to_string()).collect::<Vec<_>>(),
+    })
+}
+
+fn gate_proof_json(proof: &GateProof) -> Value {
+    json!({
+        "gate": proof.gate.0,
+        "inputs": proof.inputs.iter().map(merkle_proof_json).collect::<Vec<_>>(),
+        "output": merkle_proof_json(&proof.output),
+    })
+}
+
+fn too_many_siblings() -> MessageError {
+    invalid("proof.siblings", &format!("more than {} siblings", MAX_DEPTH))
+}
+
+fn merkle_proof_from_json(value: &Value) -> Result<MerkleProof, MessageError> {
+    let fields = object(value, "proof", &["wire", "value", "siblings"])?;
+    let siblings = array(&fields["siblings"], "proof.siblings")?;
+    if siblings.len() > MAX_DEPTH {
+        return Err(too_many_siblings());
+    }
+    Ok(MerkleProof {
+        wire: WireId(index_of(&fields["wire"], "proof.wire")?),
+        value: boolean(&fields["value"], "proof.value")?,
+        siblings: siblings.iter().map(|sibling| hash(sibling, "proof.siblings")).collect::<Result<_, _>>()?,
+    })
+}
+
+fn gate_proof_from_json(value: &Value) -> Result<GateProof, MessageError> {
+    let fields = object(value, "gate proof", &["gate", "inputs", "output"])?;
+    Ok(GateProof {
+        gate: GateId(index_of(&fields["gate"], "gate proof.gate")?),
+        inputs: array(&fields["inputs"], "gate proof.inputs")?.iter()
+            .map(merkle_proof_from_json)
+            .collect::<Result<_, _>>()?,
+        output: merkle_proof_from_json(&fields["output"])?,
+    })
+}
+
+fn put_index(bytes: &mut Vec<u8>, index: usize) -> Result<(), MessageError> {
+    let index: u32 = index.try_into().map_err(|_| MessageError::IndexOutOfRange(index))?;
+    bytes.extend_from_slice(&index.to_be_bytes());
+    Ok(())
+}
+
+fn put_merkle_proof(bytes: &mut Vec<u8>, proof: &MerkleProof) -> Result<(), MessageError> {
+    put_index(bytes, proof.wire.0)?;
+    bytes.push(proof.value as u8);
+    put_index(bytes, proof.siblings.len())?;
+    for sibling in &proof.siblings {
+        bytes.extend_from_slice(sibling.as_byte_array());
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], MessageError> {
+        if self.bytes.len() < count {
+            return Err(MessageError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, MessageError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn boolean(&mut self) -> Result<bool, MessageError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(MessageError::InvalidBool(other)),
+        }
+    }
+
+    fn index(&mut self) -> Result<usize, MessageError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().expect("4 bytes")) as usize)
+    }
+
+    fn hash(&mut self) -> Result<sha256::Hash, MessageError> {
+        Ok(sha256::Hash::from_slice(self.take(32)?).expect("32 bytes"))
+    }
+
+    fn merkle_proof(&mut self) -> Result<MerkleProof, MessageError> {
+        let wire = WireId(self.index()?);
+        let value = self.boolean()?;
+        let count = self.index()?;
+        if count > MAX_DEPTH {
+            return Err(too_many_siblings());
+        }
+        let siblings = (0..count).map(|_| self.hash()).collect::<Result<_, _>>()?;
+        Ok(MerkleProof { wire, value, siblings })
+    }
+}
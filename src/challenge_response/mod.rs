@@ -1 +1,6 @@
 pub mod protocol;
+pub mod bisection;
+pub mod merkle;
+pub mod messages;
+pub mod deadlines;
+pub mod on_chain_dispute;
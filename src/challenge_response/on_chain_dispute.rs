@@ -1,39 +1,161 @@
-// Assume we have a module that handles dispute resolution.
-pub mod dispute_resolution {
-    // ... Other necessary imports and definitions
+//! Resolution of a dispute over one gate leaf, as the chain would settle it.
+//!
+//! The prover defends the leaf with its openings. The leaf has to run to
+//! success within standardness and resource limits, or the challenger wins.
+//! The challenger may bring openings of its own. Openings are hashlock
+//! preimages that only the prover can produce, so a challenger witness that
+//! opens every commitment and leaves a single false item on the stack shows
+//! that the prover revealed values breaking the gate.
 
-    // This function is called when a dispute arises and the proof needs to be verified on-chain.
-    pub fn on_chain_dispute_verification(proof: &Proof, disputed_computation: &Computation) -> Result<(), DisputeError> {
-        // Check if the proof size is within the block size limit.
-        if proof.size() > BITCOIN_MAX_BLOCK_SIZE {
-            return Err(DisputeError::ProofTooLarge);
+use std::fmt;
+
+use bitcoin::script::{self, ScriptBuf};
+
+use crate::execute_btc_script::{ExecCtx, ExecError, ExecStats, Options, TxTemplate};
+use crate::scripts::run::{leaf_template, run_script};
+
+/// Policy and resource limits a spend of the leaf has to respect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisputeLimits {
+    /// Largest leaf script in bytes.
+    pub max_script_size: usize,
+    /// Largest witness stack item, `MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE` in Bitcoin Core.
+    pub max_witness_item_size: usize,
+    /// Most items on the stack and altstack at any time during execution.
+    pub max_stack_items: usize,
+    /// Most bytes on the stack and altstack at any time during execution.
+    pub max_stack_bytes: usize,
+}
+
+impl Default for DisputeLimits {
+    fn default() -> Self {
+        DisputeLimits {
+            // A standard transaction weighs at most 400 000 units, a witness byte counts one.
+            max_script_size: 400_000,
+            max_witness_item_size: 80,
+            max_stack_items: 1_000,
+            max_stack_bytes: 520 * 1_000,
         }
+    }
+}
 
-        // Execute the disputed computation on-chain.
-        // This is a simplified example. Actual implementation would depend on the specifics of the proof and computation.
-        match execute_bitcoin_script(&proof) {
-            Ok(_) => Ok(()), // The proof is valid, and the dispute is resolved.
-            Err(error) => Err(DisputeError::InvalidProof(error)), // The proof is invalid, and the prover loses the dispute.
+/// Why the challenger won.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChallengerReason {
+    /// The leaf could never be mined.
+    ScriptTooLarge { size: usize, limit: usize },
+    InvalidScript,
+    /// An item of the prover's witness is above the standard size.
+    WitnessItemTooLarge { index: usize, size: usize, limit: usize },
+    /// Executing the prover's witness needed more stack than allowed.
+    StackLimit(ExecStats),
+    /// The leaf fails on the prover's witness.
+    ScriptFailed(ExecError),
+    /// The prover's own openings break the gate.
+    GateViolated,
+    /// The challenger's openings, which only the prover can have revealed, break the gate.
+    Contradicted,
+}
+
+impl fmt::Display for ChallengerReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChallengerReason::ScriptTooLarge { size, limit } => write!(f, "leaf has {} bytes, the limit is {}", size, limit),
+            ChallengerReason::InvalidScript => write!(f, "leaf is not a valid script"),
+            ChallengerReason::WitnessItemTooLarge { index, size, limit } => {
+                write!(f, "witness item {} has {} bytes, the limit is {}", index, size, limit)
+            }
+            ChallengerReason::StackLimit(stats) => {
+                write!(f, "execution used {} stack items and {} bytes", stats.max_nb_stack_items, stats.max_stack_bytes)
+            }
+            ChallengerReason::ScriptFailed(error) => write!(f, "leaf fails on the prover's witness: {:?}", error),
+            ChallengerReason::GateViolated => write!(f, "the prover's openings break the gate"),
+            ChallengerReason::Contradicted => write!(f, "the challenger's openings break the gate"),
         }
     }
+}
 
-    // Function to execute a bitcoin script on-chain (a placeholder for actual implementation).
-    fn execute_bitcoin_script(proof: &Proof) -> Result<(), BitcoinScriptError> {
-        // Implementation of bitcoin script execution. This should be designed to fit within a single block.
-        // ...
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    ProverWins,
+    ChallengerWins(ChallengerReason),
+}
+
+/// Settles gate disputes with the `execute_btc_script` interpreter.
+#[derive(Debug, Clone, Default)]
+pub struct DisputeEngine {
+    pub limits: DisputeLimits,
+    pub options: Options,
+}
+
+impl DisputeEngine {
+    pub fn new(limits: DisputeLimits) -> Self {
+        DisputeEngine { limits, options: Options::default() }
+    }
+
+    /// Resolves a dispute over `leaf` run as a Tapscript outside of any real spend.
+    pub fn resolve(&self, leaf: &ScriptBuf, prover_witness: &[Vec<u8>], challenger_witness: Option<&[Vec<u8>]>) -> Verdict {
+        self.resolve_in(|| leaf_template(leaf), leaf, prover_witness, challenger_witness)
     }
 
-    // Custom error types for dispute resolution.
-    #[derive(Debug)]
-    pub enum DisputeError {
-        ProofTooLarge,
-        InvalidProof(BitcoinScriptError),
-        // Other error types...
+    /// Resolves a dispute with each execution in a context made by `template`.
+    pub fn resolve_in(
+        &self,
+        template: impl Fn() -> TxTemplate,
+        leaf: &ScriptBuf,
+        prover_witness: &[Vec<u8>],
+        challenger_witness: Option<&[Vec<u8>]>,
+    ) -> Verdict {
+        match self.judge(&template, leaf, prover_witness, challenger_witness) {
+            Ok(()) => Verdict::ProverWins,
+            Err(reason) => Verdict::ChallengerWins(reason),
+        }
     }
 
-    #[derive(Debug)]
-    pub enum BitcoinScriptError {
-        ExecutionFailed,
-        // Other error types...
+    fn judge(
+        &self,
+        template: &dyn Fn() -> TxTemplate,
+        leaf: &ScriptBuf,
+        prover_witness: &[Vec<u8>],
+        challenger_witness: Option<&[Vec<u8>]>,
+    ) -> Result<(), ChallengerReason> {
+        if leaf.len() > self.limits.max_script_size {
+            return Err(ChallengerReason::ScriptTooLarge { size: leaf.len(), limit: self.limits.max_script_size });
+        }
+        let limit = self.limits.max_witness_item_size;
+        if let Some((index, item)) = prover_witness.iter().enumerate().find(|(_, item)| item.len() > limit) {
+            return Err(ChallengerReason::WitnessItemTooLarge { index, size: item.len(), limit });
+        }
+
+        let run = |witness: &[Vec<u8>]| {
+            run_script(ExecCtx::Tapscript, self.options.clone(), template(), leaf.clone(), witness.to_vec())
+                .map_err(|_| ChallengerReason::InvalidScript)
+        };
+        let defence = run(prover_witness)?;
+        if defence.stats.max_nb_stack_items > self.limits.max_stack_items
+            || defence.stats.max_stack_bytes > self.limits.max_stack_bytes
+        {
+            return Err(ChallengerReason::StackLimit(defence.stats));
+        }
+        if let Some(error) = defence.result.error {
+            return Err(ChallengerReason::ScriptFailed(error));
+        }
+        if !defence.success() {
+            return Err(ChallengerReason::GateViolated);
+        }
+
+        // Witnesses that fail to open the commitments, or leave anything but
+        // the gate's false result, prove nothing.
+        if let Some(witness) = challenger_witness {
+            let attack = run(witness)?;
+            let contradicts = match attack.final_stack() {
+                [result] => !script::read_scriptbool(result),
+                _ => false,
+            };
+            if attack.result.error.is_none() && contradicts {
+                return Err(ChallengerReason::Contradicted);
+            }
+        }
+        Ok(())
     }
 }
@@ -1,33 +1,95 @@
-use crate::circuit_logic::BinaryCircuit;
+use bitcoin::hashes::sha256;
 
+use crate::challenge_response::deadlines::{Deadline, DEFAULT_ROUND_TIMEOUT};
+use crate::challenge_response::merkle::{GateProof, TraceTree};
+use crate::circuit_logic::{BinaryCircuit, GateId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Challenge {
     // Challenge a specific part of the circuit, such as a gate or a set of gates
-    pub gate_indices: Vec<usize>,
+    pub gate_indices: Vec<GateId>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Response {
     // Response containing the outputs for the challenged gates
     pub gate_outputs: Vec<bool>,
+    // Operands and output of each challenged gate, proven against the committed trace
+    pub proofs: Vec<GateProof>,
+}
+
+impl Response {
+    // Answer `challenge` from the prover's committed trace; `None` if a gate does not exist
+    pub fn new(challenge: &Challenge, circuit: &BinaryCircuit, tree: &TraceTree) -> Option<Response> {
+        let proofs: Vec<GateProof> = challenge.gate_indices.iter()
+            .map(|&gate| tree.prove_gate(circuit, gate))
+            .collect::<Option<_>>()?;
+        Some(Response {
+            gate_outputs: proofs.iter().map(|proof| proof.output.value).collect(),
+            proofs,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolState {
+    // No challenge with a deadline is outstanding
+    Idle,
+    // The prover has to answer before the deadline
+    AwaitingResponse(Deadline),
+    // The prover answered the challenge in time
+    Answered,
+    // The prover missed the deadline, the challenger wins
+    TimedOut,
 }
 
 pub struct ChallengeResponseProtocol {
     // Additional properties for managing and tracking challenges
     current_challenge: Option<Challenge>,
+    // Merkle root of the prover's wire values, see `merkle::TraceTree`
+    trace_root: Option<sha256::Hash>,
+    // Blocks the prover has to answer a challenge in
+    response_timeout: u16,
+    state: ProtocolState,
+}
+
+impl Default for ChallengeResponseProtocol {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ChallengeResponseProtocol {
     pub fn new() -> Self {
         ChallengeResponseProtocol {
             current_challenge: None,
+            trace_root: None,
+            response_timeout: DEFAULT_ROUND_TIMEOUT,
+            state: ProtocolState::Idle,
         }
     }
 
+    pub fn with_response_timeout(response_timeout: u16) -> Self {
+        ChallengeResponseProtocol { response_timeout, ..Self::new() }
+    }
+
+    pub fn state(&self) -> ProtocolState {
+        self.state
+    }
+
+    // Record the prover's commitment to its trace; responses are checked against it
+    pub fn commit_trace(&mut self, root: sha256::Hash) {
+        self.trace_root = Some(root);
+    }
+
     // Select a subset of gates in the circuit to challenge
     pub fn issue_challenge(&mut self, circuit: &BinaryCircuit, num_gates_to_challenge: usize) -> Challenge {
         let mut gate_indices = Vec::new();
-        for _ in 0..num_gates_to_challenge {
-            let gate_index = rand::random::<usize>() % circuit.gates.len();
-            gate_indices.push(gate_index);
+        if !circuit.gates().is_empty() {
+            for _ in 0..num_gates_to_challenge {
+                let gate_index = rand::random::<usize>() % circuit.gates().len();
+                gate_indices.push(GateId(gate_index));
+            }
         }
 
         let challenge = Challenge { gate_indices };
@@ -35,18 +97,52 @@ impl ChallengeResponseProtocol {
         challenge
     }
 
-    // Verify the response against the current challenge
+    // Verify the response against the current challenge and the committed trace
     pub fn process_response(&self, response: &Response, circuit: &BinaryCircuit) -> bool {
-        match &self.current_challenge {
-            Some(challenge) => {
-                for (&gate_index, &response_output) in challenge.gate_indices.iter().zip(response.gate_outputs.iter()) {
-                    if circuit.gates[gate_index].evaluate() != response_output {
-                        return false; // Mismatch found, response is invalid
-                    }
-                }
-                true // All responses matched, valid response
-            },
-            None => false, // No challenge issued, response is invalid
+        let (challenge, root) = match (&self.current_challenge, &self.trace_root) {
+            (Some(challenge), Some(root)) => (challenge, root),
+            _ => return false, // Nothing to check the response against
+        };
+        if challenge.gate_indices.len() != response.gate_outputs.len() || challenge.gate_indices.len() != response.proofs.len() {
+            return false; // Every challenged gate needs an answer
+        }
+        for ((&gate_index, &response_output), proof) in challenge.gate_indices.iter().zip(&response.gate_outputs).zip(&response.proofs) {
+            if proof.gate != gate_index || proof.output.value != response_output || !proof.verify(circuit, root) {
+                return false; // The answer is not backed by the committed trace
+            }
+            if !proof.gate_holds(circuit) {
+                return false; // The committed trace breaks the gate
+            }
+        }
+        true // All responses are proven and consistent, valid response
+    }
+
+    // Issue a challenge confirmed at `height`; the prover has to answer within the response timeout
+    pub fn issue_challenge_at(&mut self, circuit: &BinaryCircuit, num_gates_to_challenge: usize, height: u32) -> Challenge {
+        let challenge = self.issue_challenge(circuit, num_gates_to_challenge);
+        self.state = ProtocolState::AwaitingResponse(Deadline::new(height, self.response_timeout));
+        challenge
+    }
+
+    // Process a response confirmed at `height`; a late response times the prover out
+    pub fn process_response_at(&mut self, response: &Response, circuit: &BinaryCircuit, height: u32) -> bool {
+        if !matches!(self.state, ProtocolState::AwaitingResponse(_)) || self.check_timeout(height) {
+            return false; // No deadline running, or the response came too late
+        }
+        if !self.process_response(response, circuit) {
+            return false; // Invalid responses leave the clock running
+        }
+        self.state = ProtocolState::Answered;
+        true
+    }
+
+    // Move to `TimedOut` once `height` reaches the deadline; returns whether the prover timed out
+    pub fn check_timeout(&mut self, height: u32) -> bool {
+        if let ProtocolState::AwaitingResponse(deadline) = self.state {
+            if deadline.expired(height) {
+                self.state = ProtocolState::TimedOut;
+            }
         }
+        self.state == ProtocolState::TimedOut
     }
 }
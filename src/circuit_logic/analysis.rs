@@ -0,0 +1,130 @@
+//! Cost figures of a circuit: gate mix, depth and fan-out.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::binary_circuit::{BinaryCircuit, EvalError};
+use crate::logic_gate::{GateId, GateType};
+
+/// Summary of a circuit's size and shape, see `BinaryCircuit::analyze`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitAnalysis {
+    pub gate_count: usize,
+    /// Gates per type, ordered by `GateType::code`.
+    pub gates_by_type: Vec<(GateType, usize)>,
+    /// Gates that are non-linear over GF(2), such as AND, OR, NAND and MUX.
+    pub and_gates: usize,
+    /// Linear gates (XOR, NOT, constants, copies) that are free to garble.
+    pub free_gates: usize,
+    pub input_wires: usize,
+    pub output_wires: usize,
+    pub wire_count: usize,
+    /// Gates on the longest path from an input to an output.
+    pub depth: usize,
+    /// Non-linear gates on the path with the most of them.
+    pub multiplicative_depth: usize,
+    /// Number of wires read by a given number of gate operands.
+    pub fan_out: BTreeMap<usize, usize>,
+    /// Gates along one longest path, first gate first.
+    pub critical_path: Vec<GateId>,
+}
+
+impl CircuitAnalysis {
+    pub fn max_fan_out(&self) -> usize {
+        self.fan_out.keys().next_back().copied().unwrap_or(0)
+    }
+}
+
+/// Whether the gate is linear over GF(2) and thus free under free-XOR garbling.
+pub fn is_free(gate_type: GateType) -> bool {
+    matches!(
+        gate_type,
+        GateType::Constant(_)
+            | GateType::PushConstant(_)
+            | GateType::XOR
+            | GateType::NOT
+            | GateType::Copy
+            | GateType::SHA256Rotate
+            | GateType::RIPEMD160Rotate
+    )
+}
+
+impl BinaryCircuit {
+    /// Computes gate counts, depths and fan-out of the circuit.
+    pub fn analyze(&self) -> Result<CircuitAnalysis, EvalError> {
+        let order = self.topological_order()?;
+        let drivers = self.drivers()?;
+
+        let mut by_type: BTreeMap<u8, (GateType, usize)> = BTreeMap::new();
+        for gate in self.gates() {
+            by_type.entry(gate.gate_type.code()).or_insert((gate.gate_type, 0)).1 += 1;
+        }
+        let and_gates = self.gates().iter().filter(|gate| !is_free(gate.gate_type)).count();
+
+        // Depths of the value on each wire, counting all gates and non-linear gates.
+        let mut depth = vec![0; self.wire_count()];
+        let mut and_depth = vec![0; self.wire_count()];
+        for id in order {
+            let gate = self.gate(id);
+            let deepest = gate.inputs.iter().map(|input| depth[input.0]).max().unwrap_or(0);
+            let deepest_and = gate.inputs.iter().map(|input| and_depth[input.0]).max().unwrap_or(0);
+            depth[gate.output.0] = deepest + 1;
+            and_depth[gate.output.0] = deepest_and + !is_free(gate.gate_type) as usize;
+        }
+
+        let deepest_output = self.outputs().iter().copied().max_by_key(|output| depth[output.0]);
+        let mut critical_path = Vec::new();
+        let mut wire = deepest_output;
+        while let Some(id) = wire.and_then(|wire| drivers[wire.0]) {
+            critical_path.push(id);
+            wire = self.gate(id).inputs.iter().copied().max_by_key(|input| depth[input.0]);
+        }
+        critical_path.reverse();
+
+        let mut reads = vec![0; self.wire_count()];
+        for gate in self.gates() {
+            for input in &gate.inputs {
+                reads[input.0] += 1;
+            }
+        }
+        let mut fan_out = BTreeMap::new();
+        let driven = self.inputs().iter().copied().chain(self.gates().iter().map(|gate| gate.output));
+        for wire in driven {
+            *fan_out.entry(reads[wire.0]).or_insert(0) += 1;
+        }
+
+        Ok(CircuitAnalysis {
+            gate_count: self.gates().len(),
+            gates_by_type: by_type.into_values().collect(),
+            and_gates,
+            free_gates: self.gates().len() - and_gates,
+            input_wires: self.inputs().len(),
+            output_wires: self.outputs().len(),
+            wire_count: self.wire_count(),
+            depth: self.outputs().iter().map(|output| depth[output.0]).max().unwrap_or(0),
+            multiplicative_depth: self.outputs().iter().map(|output| and_depth[output.0]).max().unwrap_or(0),
+            fan_out,
+            critical_path,
+        })
+    }
+}
+
+impl fmt::Display for CircuitAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "gates: {} ({} AND-like, {} free)", self.gate_count, self.and_gates, self.free_gates)?;
+        let types: Vec<String> = self.gates_by_type.iter()
+            .map(|(gate_type, count)| format!("{:?} {}", gate_type, count))
+            .collect();
+        writeln!(f, "gate types: {}", types.join(", "))?;
+        writeln!(f, "wires: {} ({} inputs, {} outputs)", self.wire_count, self.input_wires, self.output_wires)?;
+        writeln!(f, "depth: {} (multiplicative depth {})", self.depth, self.multiplicative_depth)?;
+        let fan_out: Vec<String> = self.fan_out.iter().map(|(reads, wires)| format!("{}:{}", reads, wires)).collect();
+        writeln!(f, "fan-out (reads:wires): {} (max {})", fan_out.join(" "), self.max_fan_out())?;
+        match (self.critical_path.first(), self.critical_path.last()) {
+            (Some(first), Some(last)) => {
+                write!(f, "critical path: {} gates, {} to {}", self.critical_path.len(), first, last)
+            }
+            _ => write!(f, "critical path: empty"),
+        }
+    }
+}
@@ -1,77 +1,253 @@
-use serde_json::Value;
-use crate::logic_gate::{LogicGate, GateType};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
 
+use crate::logic_gate::{LogicGate, GateType, GateId, WireId};
+
+/// Error raised while evaluating a circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    /// The number of supplied values differs from the number of input wires.
+    InputCountMismatch { expected: usize, found: usize },
+    /// A gate reads a wire that is neither an input nor driven by any gate.
+    DanglingWire { gate: GateId, wire: WireId },
+    /// A wire is driven by more than one gate, or by a gate and the circuit inputs.
+    MultipleDrivers(WireId),
+    /// An output wire is neither an input nor driven by any gate.
+    UndrivenOutput(WireId),
+    /// The gates listed depend on each other and cannot be ordered.
+    Cycle(Vec<GateId>),
+    /// The gate cannot be evaluated on the operands wired into it.
+    InvalidGate { gate: GateId, gate_type: GateType, operands: usize },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::InputCountMismatch { expected, found } => {
+                write!(f, "expected {} input values, got {}", expected, found)
+            }
+            EvalError::DanglingWire { gate, wire } => {
+                write!(f, "gate {} reads from undriven wire {}", gate, wire)
+            }
+            EvalError::MultipleDrivers(wire) => write!(f, "wire {} has more than one driver", wire),
+            EvalError::UndrivenOutput(wire) => write!(f, "output wire {} is never driven", wire),
+            EvalError::Cycle(gates) => write!(f, "gates {:?} form a cycle", gates),
+            EvalError::InvalidGate { gate, gate_type, operands } => {
+                write!(f, "gate {} ({:?}) cannot be evaluated with {} operands", gate, gate_type, operands)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// A boolean circuit over explicit wires.
+///
+/// Input wires are set from the values passed to `evaluate`, every other wire
+/// is driven by exactly one gate. Use `CircuitBuilder` to construct circuits
+/// without handing out wire numbers manually.
+#[derive(Debug, Clone, Default)]
 pub struct BinaryCircuit {
+    wire_count: usize,
+    inputs: Vec<WireId>, // Wires set from the circuit inputs, in order
     gates: Vec<LogicGate>,
-    connections: Vec<(usize, usize)>, // (output_gate_index, input_gate_index)
+    outputs: Vec<WireId>, // Wires that form the circuit result
+    input_widths: Vec<usize>, // Bits per input value, as declared by Bristol headers
+    output_widths: Vec<usize>, // Bits per output value
 }
 
 impl BinaryCircuit {
     pub fn new() -> Self {
         BinaryCircuit {
+            wire_count: 0,
+            inputs: Vec::new(),
             gates: Vec::new(),
-            connections: Vec::new(),
+            outputs: Vec::new(),
+            input_widths: Vec::new(),
+            output_widths: Vec::new(),
         }
     }
 
-    pub fn from_json(json_data: &str) -> Result<Self, serde_json::Error> {
-        let json: Value = serde_json::from_str(json_data)?;
-        let mut circuit = BinaryCircuit::new();
+    /// Allocates a wire that nothing drives yet.
+    pub fn new_wire(&mut self) -> WireId {
+        self.wire_count += 1;
+        WireId(self.wire_count - 1)
+    }
 
-        if let Some(components) = json.as_array() {
-            for component in components {
-                // Parse each component and add to the circuit
+    /// Allocates a wire and declares it as the next circuit input.
+    pub fn add_input(&mut self) -> WireId {
+        let wire = self.new_wire();
+        self.inputs.push(wire);
+        wire
+    }
 
-                // Handle AND gates
-                if let Some(gate_type) = component["type"].as_str() {
-                    if gate_type == "AND" {
-                        let inputs = component["inputs"].as_array()
-                            .unwrap_or(&vec![])
-                            .iter()
-                            .filter_map(|v| v.as_u64())
-                            .map(|v| v as usize)
-                            .collect();
+    /// Adds a logic gate to the circuit.
+    ///
+    /// Wires referenced by the gate that were not allocated yet are allocated
+    /// implicitly; consistency is checked when the circuit is evaluated.
+    pub fn add_gate(&mut self, gate: LogicGate) -> GateId {
+        let highest = gate.inputs.iter().chain(Some(&gate.output)).map(|wire| wire.0 + 1).max();
+        self.wire_count = self.wire_count.max(highest.unwrap_or(0));
+        self.gates.push(gate);
+        GateId(self.gates.len() - 1)
+    }
 
-                        let gate = LogicGate::new(GateType::AND, inputs);
-                        circuit.gates.push(gate);
-                    }
-                }
+    /// Copy of the circuit with the same wires, inputs and outputs but no gates.
+    pub(crate) fn without_gates(&self) -> Self {
+        BinaryCircuit {
+            gates: Vec::new(),
+            ..self.clone()
+        }
+    }
 
-                // Handle XOR gates
-                if let Some(gate_type) = component["type"].as_str() {
-                    if gate_type == "XOR" {
-                        let inputs = component["inputs"].as_array()
-                            .unwrap_or(&vec![])
-                            .iter()
-                            .filter_map(|v| v.as_u64())
-                            .map(|v| v as usize)
-                            .collect();
-
-                        let gate = LogicGate::new(GateType::XOR, inputs);
-                        circuit.gates.push(gate);
-                    }
-                }
+    /// Marks `wire` as the next circuit output.
+    pub fn add_output(&mut self, wire: WireId) {
+        self.wire_count = self.wire_count.max(wire.0 + 1);
+        self.outputs.push(wire);
+    }
+
+    pub fn wire_count(&self) -> usize {
+        self.wire_count
+    }
+
+    pub fn inputs(&self) -> &[WireId] {
+        &self.inputs
+    }
+
+    pub fn gates(&self) -> &[LogicGate] {
+        &self.gates
+    }
+
+    pub fn gate(&self, id: GateId) -> &LogicGate {
+        &self.gates[id.0]
+    }
+
+    pub fn outputs(&self) -> &[WireId] {
+        &self.outputs
+    }
+
+    /// Splits the inputs into values of the given bit widths.
+    pub fn set_input_widths(&mut self, widths: Vec<usize>) {
+        self.input_widths = widths;
+    }
+
+    pub fn input_widths(&self) -> &[usize] {
+        &self.input_widths
+    }
+
+    /// Splits the outputs into values of the given bit widths.
+    pub fn set_output_widths(&mut self, widths: Vec<usize>) {
+        self.output_widths = widths;
+    }
+
+    pub fn output_widths(&self) -> &[usize] {
+        &self.output_widths
+    }
+
+    /// Returns the gate driving each wire; input wires and undriven wires map to `None`.
+    pub fn drivers(&self) -> Result<Vec<Option<GateId>>, EvalError> {
+        let mut is_input = vec![false; self.wire_count];
+        for &wire in &self.inputs {
+            if is_input[wire.0] {
+                return Err(EvalError::MultipleDrivers(wire));
+            }
+            is_input[wire.0] = true;
+        }
+
+        let mut drivers = vec![None; self.wire_count];
+        for (index, gate) in self.gates.iter().enumerate() {
+            if is_input[gate.output.0] || drivers[gate.output.0].is_some() {
+                return Err(EvalError::MultipleDrivers(gate.output));
+            }
+            drivers[gate.output.0] = Some(GateId(index));
+        }
+        Ok(drivers)
+    }
 
-                // Handle NOT gates
-                if let Some(gate_type) = component["type"].as_str() {
-                    if gate_type == "NOT" {
-                        let inputs = component["inputs"].as_array()
-                            .unwrap_or(&vec![])
-                            .iter()
-                            .filter_map(|v| v.as_u64())
-                            .map(|v| v as usize)
-                            .collect();
-
-                        let gate = LogicGate::new(GateType::NOT, inputs);
-                        circuit.gates.push(gate);
+    /// Orders the gates so that every gate comes after the gates driving its inputs.
+    ///
+    /// Ties are broken by gate index, so an already sorted circuit keeps its order.
+    pub fn topological_order(&self) -> Result<Vec<GateId>, EvalError> {
+        let drivers = self.drivers()?;
+        let mut is_input = vec![false; self.wire_count];
+        for &wire in &self.inputs {
+            is_input[wire.0] = true;
+        }
+
+        let mut pending = vec![0usize; self.gates.len()];
+        let mut readers = vec![Vec::new(); self.gates.len()];
+        for (index, (gate, count)) in self.gates.iter().zip(pending.iter_mut()).enumerate() {
+            for &wire in &gate.inputs {
+                match drivers[wire.0] {
+                    Some(driver) => {
+                        *count += 1;
+                        readers[driver.0].push(index);
                     }
+                    None if is_input[wire.0] => {}
+                    None => return Err(EvalError::DanglingWire { gate: GateId(index), wire }),
                 }
+            }
+        }
 
-                // Add logic for connections (if defined in JSON)
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..self.gates.len())
+            .filter(|&i| pending[i] == 0)
+            .map(Reverse)
+            .collect();
+        let mut order = Vec::with_capacity(self.gates.len());
+        while let Some(Reverse(index)) = ready.pop() {
+            order.push(GateId(index));
+            for &reader in &readers[index] {
+                pending[reader] -= 1;
+                if pending[reader] == 0 {
+                    ready.push(Reverse(reader));
+                }
             }
         }
 
-        Ok(circuit)
+        if order.len() != self.gates.len() {
+            let cycle = (0..self.gates.len()).filter(|&i| pending[i] > 0).map(GateId).collect();
+            return Err(EvalError::Cycle(cycle));
+        }
+        Ok(order)
+    }
+
+    /// Runs the circuit and returns the value of every wire, indexed by `WireId`.
+    ///
+    /// `inputs` are assigned to the input wires in the order they were declared.
+    /// The returned trace is what prover and challenger compare during a dispute.
+    pub fn evaluate(&self, inputs: &[bool]) -> Result<Vec<bool>, EvalError> {
+        if inputs.len() != self.inputs.len() {
+            return Err(EvalError::InputCountMismatch { expected: self.inputs.len(), found: inputs.len() });
+        }
+        let order = self.topological_order()?;
+
+        let mut driven = vec![false; self.wire_count];
+        let mut wires = vec![false; self.wire_count];
+        for (&wire, &value) in self.inputs.iter().zip(inputs) {
+            wires[wire.0] = value;
+            driven[wire.0] = true;
+        }
+        for id in order {
+            let gate = &self.gates[id.0];
+            let operands: Vec<bool> = gate.inputs.iter().map(|wire| wires[wire.0]).collect();
+            wires[gate.output.0] = gate.evaluate(&operands).ok_or(EvalError::InvalidGate {
+                gate: id,
+                gate_type: gate.gate_type,
+                operands: operands.len(),
+            })?;
+            driven[gate.output.0] = true;
+        }
+
+        if let Some(&wire) = self.outputs.iter().find(|wire| !driven[wire.0]) {
+            return Err(EvalError::UndrivenOutput(wire));
+        }
+        Ok(wires)
+    }
+
+    /// Picks the circuit outputs out of a trace returned by `evaluate`.
+    pub fn output_values(&self, trace: &[bool]) -> Vec<bool> {
+        self.outputs.iter().map(|wire| trace[wire.0]).collect()
     }
 
     // Additional methods for circuit manipulation
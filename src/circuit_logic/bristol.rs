@@ -0,0 +1,362 @@
+//! Reader and writer for the Bristol Fashion circuit format.
+//!
+//! A Bristol Fashion file starts with three header lines: the gate and wire
+//! counts, the number of input values followed by their bit widths, and the
+//! same for the outputs. Input wires come first and output wires are the last
+//! wires of the circuit. Every following line describes one gate as
+//! `<inputs> <outputs> <input wires...> <output wires...> <name>`.
+//!
+//! Besides the `AND`, `XOR`, `INV`, `EQ`, `EQW` and `MAND` gates of the format
+//! the reader and writer also accept `NAND`, which our NAND-only circuits use.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::binary_circuit::{BinaryCircuit, EvalError};
+use crate::builder::CircuitBuilder;
+use crate::logic_gate::{GateId, GateType, WireId};
+
+/// Error raised while reading or writing a Bristol Fashion circuit.
+///
+/// Line numbers start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BristolError {
+    /// The file ends before the three header lines.
+    MissingHeader,
+    /// A line does not follow the format.
+    Malformed { line: usize, reason: String },
+    /// The gate name is not part of the supported gate set.
+    UnknownGate { line: usize, name: String },
+    /// A gate reads a wire that has not been assigned yet.
+    UndefinedWire { line: usize, wire: usize },
+    /// A gate assigns a wire that already carries a value.
+    RedefinedWire { line: usize, wire: usize },
+    /// The header announces a different number of gates than the file holds.
+    GateCountMismatch { declared: usize, found: usize },
+    /// The circuit contains a gate that has no Bristol Fashion encoding.
+    UnsupportedGate { gate: GateId, gate_type: GateType, operands: usize },
+    /// The circuit cannot be ordered for writing.
+    Eval(EvalError),
+}
+
+impl fmt::Display for BristolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BristolError::MissingHeader => write!(f, "missing Bristol header"),
+            BristolError::Malformed { line, reason } => write!(f, "line {}: {}", line, reason),
+            BristolError::UnknownGate { line, name } => write!(f, "line {}: unknown gate {}", line, name),
+            BristolError::UndefinedWire { line, wire } => {
+                write!(f, "line {}: wire {} is read before it is assigned", line, wire)
+            }
+            BristolError::RedefinedWire { line, wire } => {
+                write!(f, "line {}: wire {} is assigned twice", line, wire)
+            }
+            BristolError::GateCountMismatch { declared, found } => {
+                write!(f, "header declares {} gates, found {}", declared, found)
+            }
+            BristolError::UnsupportedGate { gate, gate_type, operands } => {
+                write!(f, "gate {} ({:?} with {} operands) has no Bristol encoding", gate, gate_type, operands)
+            }
+            BristolError::Eval(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BristolError {}
+
+impl From<EvalError> for BristolError {
+    fn from(e: EvalError) -> Self {
+        BristolError::Eval(e)
+    }
+}
+
+fn malformed(line: usize, reason: &str) -> BristolError {
+    BristolError::Malformed { line, reason: reason.to_string() }
+}
+
+fn parse_numbers(line: usize, tokens: &[&str]) -> Result<Vec<usize>, BristolError> {
+    tokens.iter()
+        .map(|token| token.parse::<usize>().map_err(|_| malformed(line, &format!("expected a number, got {}", token))))
+        .collect()
+}
+
+/// Parses a `<count> <width...>` header line.
+fn parse_widths(line: usize, tokens: &[&str]) -> Result<Vec<usize>, BristolError> {
+    let numbers = parse_numbers(line, tokens)?;
+    match numbers.split_first() {
+        Some((&count, widths)) if count == widths.len() => Ok(widths.to_vec()),
+        _ => Err(malformed(line, "value count does not match the listed widths")),
+    }
+}
+
+/// Tracks which circuit wire carries each Bristol wire while reading a file.
+///
+/// Only assigned wires are stored, so a header announcing a huge wire count
+/// costs nothing until gates actually assign those wires.
+struct WireMap {
+    wire_count: usize,
+    wires: HashMap<usize, WireId>,
+}
+
+impl WireMap {
+    fn check_range(&self, line: usize, wire: usize) -> Result<(), BristolError> {
+        if wire < self.wire_count {
+            Ok(())
+        } else {
+            Err(malformed(line, &format!("wire {} is out of range", wire)))
+        }
+    }
+
+    fn read(&self, line: usize, wire: usize) -> Result<WireId, BristolError> {
+        self.check_range(line, wire)?;
+        self.wires.get(&wire).copied().ok_or(BristolError::UndefinedWire { line, wire })
+    }
+
+    fn assign(&mut self, line: usize, wire: usize, id: WireId) -> Result<(), BristolError> {
+        self.check_range(line, wire)?;
+        if self.wires.insert(wire, id).is_some() {
+            return Err(BristolError::RedefinedWire { line, wire });
+        }
+        Ok(())
+    }
+}
+
+impl BinaryCircuit {
+    /// Parses a circuit from a string in the Bristol Fashion format.
+    ///
+    /// Input and output wires keep their order and value widths. `EQW` does not
+    /// create a gate; the copy simply refers to the wire it copies.
+    pub fn from_bristol_format(data: &str) -> Result<Self, BristolError> {
+        let mut lines = data.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.split_whitespace().collect::<Vec<_>>()))
+            .filter(|(_, tokens)| !tokens.is_empty());
+
+        let (line, counts) = lines.next().ok_or(BristolError::MissingHeader)?;
+        let counts = parse_numbers(line, &counts)?;
+        let (declared_gates, wire_count) = match counts[..] {
+            [gates, wires] => (gates, wires),
+            _ => return Err(malformed(line, "expected gate and wire counts")),
+        };
+        let (input_line, tokens) = lines.next().ok_or(BristolError::MissingHeader)?;
+        let input_widths = parse_widths(input_line, &tokens)?;
+        let (line, tokens) = lines.next().ok_or(BristolError::MissingHeader)?;
+        let output_widths = parse_widths(line, &tokens)?;
+
+        let sum = |widths: &[usize]| widths.iter().try_fold(0usize, |total, &width| total.checked_add(width));
+        let too_many = |line| malformed(line, "more input and output wires than wires in the circuit");
+        let input_count = sum(&input_widths).ok_or_else(|| too_many(input_line))?;
+        let output_count = sum(&output_widths).ok_or_else(|| too_many(line))?;
+        if input_count.checked_add(output_count).is_none_or(|count| count > wire_count) {
+            return Err(too_many(line));
+        }
+
+        // Input wires are created up front, so bound them by what the gates
+        // can read before allocating anything.
+        let gate_lines: Vec<_> = lines.collect();
+        let referenced: usize = gate_lines.iter().map(|(_, tokens)| tokens.len().saturating_sub(3)).sum();
+        if input_count > referenced {
+            return Err(malformed(input_line, "more input wires than the gates read"));
+        }
+
+        let mut builder = CircuitBuilder::new();
+        let mut wires = WireMap { wire_count, wires: HashMap::new() };
+        for (wire, id) in input_widths.iter().flat_map(|&width| builder.inputs(width)).enumerate() {
+            wires.wires.insert(wire, id);
+        }
+
+        let mut found_gates = 0;
+        for (line, tokens) in gate_lines {
+            found_gates += 1;
+            let (name, tokens) = tokens.split_last().expect("blank lines are skipped");
+            let numbers = parse_numbers(line, tokens)?;
+            let (in_count, out_count, wire_list) = match numbers[..] {
+                [in_count, out_count, ref rest @ ..] if in_count.checked_add(out_count) == Some(rest.len()) => {
+                    (in_count, out_count, rest)
+                }
+                _ => return Err(malformed(line, "wire list does not match the gate arity")),
+            };
+            let (in_wires, out_wires) = wire_list.split_at(in_count);
+
+            let expect_arity = |ins: usize, outs: usize| {
+                if (in_count, out_count) == (ins, outs) {
+                    Ok(())
+                } else {
+                    Err(malformed(line, &format!("{} takes {} inputs and {} outputs", name, ins, outs)))
+                }
+            };
+
+            match *name {
+                "AND" | "XOR" | "NAND" => {
+                    expect_arity(2, 1)?;
+                    let gate_type = match *name {
+                        "AND" => GateType::AND,
+                        "XOR" => GateType::XOR,
+                        _ => GateType::NAND,
+                    };
+                    let operands = [wires.read(line, in_wires[0])?, wires.read(line, in_wires[1])?];
+                    let output = builder.gate(gate_type, &operands);
+                    wires.assign(line, out_wires[0], output)?;
+                }
+                "INV" => {
+                    expect_arity(1, 1)?;
+                    let operand = wires.read(line, in_wires[0])?;
+                    let output = builder.not(operand);
+                    wires.assign(line, out_wires[0], output)?;
+                }
+                "EQ" => {
+                    expect_arity(1, 1)?;
+                    let value = match in_wires[0] {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(malformed(line, "EQ assigns the constant 0 or 1")),
+                    };
+                    let output = builder.constant(value);
+                    wires.assign(line, out_wires[0], output)?;
+                }
+                "EQW" => {
+                    expect_arity(1, 1)?;
+                    let source = wires.read(line, in_wires[0])?;
+                    wires.assign(line, out_wires[0], source)?;
+                }
+                "MAND" => {
+                    if in_count != 2 * out_count || out_count == 0 {
+                        return Err(malformed(line, "MAND takes 2k inputs and k outputs"));
+                    }
+                    let (left, right) = in_wires.split_at(out_count);
+                    let mut pending = Vec::with_capacity(out_count);
+                    for (&a, &b) in left.iter().zip(right) {
+                        pending.push((wires.read(line, a)?, wires.read(line, b)?));
+                    }
+                    for ((a, b), &out) in pending.into_iter().zip(out_wires) {
+                        let output = builder.and(a, b);
+                        wires.assign(line, out, output)?;
+                    }
+                }
+                _ => return Err(BristolError::UnknownGate { line, name: name.to_string() }),
+            }
+        }
+
+        if found_gates != declared_gates {
+            return Err(BristolError::GateCountMismatch { declared: declared_gates, found: found_gates });
+        }
+
+        let last_line = data.lines().count();
+        let mut next_output = wire_count - output_count;
+        for &width in &output_widths {
+            let value = (next_output..next_output + width)
+                .map(|wire| wires.read(last_line, wire))
+                .collect::<Result<Vec<_>, _>>()?;
+            builder.outputs(&value);
+            next_output += width;
+        }
+        Ok(builder.build())
+    }
+
+    /// Writes the circuit in the Bristol Fashion format.
+    ///
+    /// Wires are renumbered: inputs first, then gate outputs in topological
+    /// order, with the circuit outputs as the last wires. Outputs that are input
+    /// wires, or that repeat an earlier output, are produced with `EQW` copies.
+    /// Writing the result of `from_bristol_format` on our own output reproduces
+    /// it byte for byte.
+    pub fn to_bristol_format(&self) -> Result<String, BristolError> {
+        let drivers = self.drivers()?;
+        let order = self.topological_order()?;
+        let input_count = self.inputs().len();
+        let output_count = self.outputs().len();
+
+        let mut number: Vec<Option<usize>> = vec![None; self.wire_count()];
+        for (index, wire) in self.inputs().iter().enumerate() {
+            number[wire.0] = Some(index);
+        }
+
+        // Outputs driven by a gate directly take the output wire as that gate's wire.
+        let mut output_slot = vec![None; self.wire_count()];
+        let mut copies = Vec::new();
+        for (slot, &wire) in self.outputs().iter().enumerate() {
+            if drivers[wire.0].is_some() && output_slot[wire.0].is_none() {
+                output_slot[wire.0] = Some(slot);
+            } else if drivers[wire.0].is_some() || number[wire.0].is_some() {
+                copies.push((wire, slot));
+            } else {
+                return Err(EvalError::UndrivenOutput(wire).into());
+            }
+        }
+
+        let direct_count = output_slot.iter().filter(|slot| slot.is_some()).count();
+        let wire_count = input_count + self.gates().len() - direct_count + output_count;
+        let first_output = wire_count - output_count;
+
+        let mut next_internal = input_count;
+        let mut body = Vec::new();
+        for id in order {
+            let gate = self.gate(id);
+            let wire = match output_slot[gate.output.0] {
+                Some(slot) => first_output + slot,
+                None => {
+                    next_internal += 1;
+                    next_internal - 1
+                }
+            };
+            number[gate.output.0] = Some(wire);
+
+            let operands: Vec<usize> = gate.inputs.iter()
+                .map(|input| number[input.0].expect("operands are numbered before their readers"))
+                .collect();
+            let line = match (gate.gate_type, &operands[..]) {
+                (GateType::AND, &[a, b]) => format!("2 1 {} {} {} AND", a, b, wire),
+                (GateType::XOR, &[a, b]) => format!("2 1 {} {} {} XOR", a, b, wire),
+                (GateType::NAND, &[a, b]) => format!("2 1 {} {} {} NAND", a, b, wire),
+                (GateType::NOT, &[a]) => format!("1 1 {} {} INV", a, wire),
+                (GateType::Constant(value), &[]) => format!("1 1 {} {} EQ", value as u8, wire),
+                (gate_type, _) => {
+                    return Err(BristolError::UnsupportedGate {
+                        gate: id,
+                        gate_type,
+                        operands: operands.len(),
+                    })
+                }
+            };
+            body.push(line);
+        }
+        for (wire, slot) in copies {
+            let source = number[wire.0].expect("copied wires are numbered");
+            body.push(format!("1 1 {} {} EQW", source, first_output + slot));
+        }
+
+        let input_widths = widths_or_default(self.input_widths(), input_count);
+        let output_widths = widths_or_default(self.output_widths(), output_count);
+
+        let mut out = String::new();
+        out.push_str(&format!("{} {}\n", body.len(), wire_count));
+        out.push_str(&format_widths(&input_widths));
+        out.push_str(&format_widths(&output_widths));
+        out.push('\n');
+        for line in body {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+/// Uses the declared widths if they cover all wires, otherwise one value holding every wire.
+fn widths_or_default(widths: &[usize], count: usize) -> Vec<usize> {
+    if !widths.is_empty() && widths.iter().sum::<usize>() == count {
+        widths.to_vec()
+    } else if count == 0 {
+        Vec::new()
+    } else {
+        vec![count]
+    }
+}
+
+fn format_widths(widths: &[usize]) -> String {
+    let mut line = widths.len().to_string();
+    for width in widths {
+        line.push_str(&format!(" {}", width));
+    }
+    line.push('\n');
+    line
+}
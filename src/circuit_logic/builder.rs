@@ -0,0 +1,93 @@
+use crate::binary_circuit::BinaryCircuit;
+use crate::logic_gate::{GateType, LogicGate, WireId};
+
+/// Builds a `BinaryCircuit` gate by gate.
+///
+/// Every gate gets a freshly allocated output wire, so a wire can never end up
+/// with two drivers. Inputs and outputs are declared as values of one or more
+/// bits, which become the circuit's Bristol input and output widths.
+#[derive(Debug, Default)]
+pub struct CircuitBuilder {
+    circuit: BinaryCircuit,
+    input_widths: Vec<usize>,
+    output_widths: Vec<usize>,
+}
+
+impl CircuitBuilder {
+    pub fn new() -> Self {
+        CircuitBuilder {
+            circuit: BinaryCircuit::new(),
+            input_widths: Vec::new(),
+            output_widths: Vec::new(),
+        }
+    }
+
+    /// Declares a single-bit input value.
+    pub fn input(&mut self) -> WireId {
+        self.inputs(1)[0]
+    }
+
+    /// Declares an input value of `width` bits.
+    pub fn inputs(&mut self, width: usize) -> Vec<WireId> {
+        self.input_widths.push(width);
+        (0..width).map(|_| self.circuit.add_input()).collect()
+    }
+
+    pub fn constant(&mut self, value: bool) -> WireId {
+        self.gate(GateType::Constant(value), &[])
+    }
+
+    /// Adds a gate reading `inputs` and returns its output wire.
+    pub fn gate(&mut self, gate_type: GateType, inputs: &[WireId]) -> WireId {
+        for wire in inputs {
+            assert!(wire.0 < self.circuit.wire_count(), "wire {} does not belong to this circuit", wire);
+        }
+        let output = self.circuit.new_wire();
+        self.circuit.add_gate(LogicGate::new(gate_type, inputs.to_vec(), output));
+        output
+    }
+
+    pub fn and(&mut self, a: WireId, b: WireId) -> WireId {
+        self.gate(GateType::AND, &[a, b])
+    }
+
+    pub fn or(&mut self, a: WireId, b: WireId) -> WireId {
+        self.gate(GateType::OR, &[a, b])
+    }
+
+    pub fn xor(&mut self, a: WireId, b: WireId) -> WireId {
+        self.gate(GateType::XOR, &[a, b])
+    }
+
+    pub fn nand(&mut self, a: WireId, b: WireId) -> WireId {
+        self.gate(GateType::NAND, &[a, b])
+    }
+
+    pub fn not(&mut self, a: WireId) -> WireId {
+        self.gate(GateType::NOT, &[a])
+    }
+
+    /// Declares a single-bit output value.
+    pub fn output(&mut self, wire: WireId) {
+        self.outputs(&[wire]);
+    }
+
+    /// Declares an output value made of `wires`.
+    pub fn outputs(&mut self, wires: &[WireId]) {
+        self.output_widths.push(wires.len());
+        for &wire in wires {
+            self.circuit.add_output(wire);
+        }
+    }
+
+    /// Read access to the circuit built so far.
+    pub fn circuit(&self) -> &BinaryCircuit {
+        &self.circuit
+    }
+
+    pub fn build(mut self) -> BinaryCircuit {
+        self.circuit.set_input_widths(self.input_widths);
+        self.circuit.set_output_widths(self.output_widths);
+        self.circuit
+    }
+}
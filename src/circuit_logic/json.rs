@@ -0,0 +1,132 @@
+//! JSON encoding of Bristol Fashion circuits, as used by `bitcoin_compatible_circuit.json`.
+//!
+//! The document is an array with one object per Bristol line. `type` holds the
+//! first field of the line as a string and `parameters` the remaining fields,
+//! ending with the gate name for gate lines:
+//!
+//! ```text
+//! [{"type": "154", "parameters": [219]}, {"type": "2", "parameters": [33, 33]},
+//!  {"type": "1", "parameters": [33]}, {"type": "2", "parameters": [2, 32, 64, 218, "XOR"]}, ...]
+//! ```
+//!
+//! Numeric parameters count from one, so every number in `parameters` is one
+//! higher than the corresponding Bristol field.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::binary_circuit::BinaryCircuit;
+use crate::bristol::BristolError;
+
+/// Error raised while reading a JSON circuit.
+#[derive(Debug)]
+pub enum JsonError {
+    /// The document is not valid JSON.
+    Syntax(serde_json::Error),
+    /// The document is not an array of components.
+    NotAnArray,
+    /// The component at `index` does not follow the schema.
+    InvalidComponent { index: usize, reason: String },
+    /// The component at `index` describes an invalid header or gate.
+    Component { index: usize, error: BristolError },
+    /// The circuit as a whole is invalid.
+    Bristol(BristolError),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::Syntax(e) => write!(f, "invalid JSON: {}", e),
+            JsonError::NotAnArray => write!(f, "expected an array of circuit components"),
+            JsonError::InvalidComponent { index, reason } => write!(f, "component {}: {}", index, reason),
+            JsonError::Component { index, error } => write!(f, "component {}: {}", index, error),
+            JsonError::Bristol(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(e: serde_json::Error) -> Self {
+        JsonError::Syntax(e)
+    }
+}
+
+/// Points Bristol errors at the component they came from; component `i` is line `i + 1`.
+fn locate(error: BristolError) -> JsonError {
+    let line = match &error {
+        BristolError::Malformed { line, .. }
+        | BristolError::UnknownGate { line, .. }
+        | BristolError::UndefinedWire { line, .. }
+        | BristolError::RedefinedWire { line, .. } => *line,
+        _ => return JsonError::Bristol(error),
+    };
+    JsonError::Component { index: line - 1, error }
+}
+
+/// Turns one component back into its Bristol line.
+fn component_line(index: usize, component: &Value) -> Result<String, JsonError> {
+    let invalid = |reason: &str| JsonError::InvalidComponent { index, reason: reason.to_string() };
+
+    let first = component.get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("missing string \"type\""))?;
+    if first.parse::<usize>().is_err() {
+        return Err(invalid("\"type\" must hold a number"));
+    }
+    let parameters = component.get("parameters")
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid("missing \"parameters\" array"))?;
+
+    let mut line = first.to_string();
+    for (position, parameter) in parameters.iter().enumerate() {
+        let field = match parameter {
+            Value::Number(n) => match n.as_u64() {
+                Some(n) if n > 0 => (n - 1).to_string(),
+                _ => return Err(invalid("numeric parameters must be positive integers")),
+            },
+            Value::String(name) if position + 1 == parameters.len() => name.clone(),
+            Value::String(_) => return Err(invalid("only the last parameter may be a gate name")),
+            _ => return Err(invalid("parameters must be numbers or a gate name")),
+        };
+        line.push(' ');
+        line.push_str(&field);
+    }
+    Ok(line)
+}
+
+impl BinaryCircuit {
+    /// Parses a circuit from the JSON encoding of a Bristol Fashion file.
+    pub fn from_json(json_data: &str) -> Result<Self, JsonError> {
+        let json: Value = serde_json::from_str(json_data)?;
+        let components = json.as_array().ok_or(JsonError::NotAnArray)?;
+
+        let mut bristol = String::new();
+        for (index, component) in components.iter().enumerate() {
+            bristol.push_str(&component_line(index, component)?);
+            bristol.push('\n');
+        }
+        BinaryCircuit::from_bristol_format(&bristol).map_err(locate)
+    }
+
+    /// Writes the circuit in the same JSON encoding `from_json` reads.
+    pub fn to_json(&self) -> Result<String, BristolError> {
+        let bristol = self.to_bristol_format()?;
+        let components: Vec<String> = bristol.lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .filter(|fields| !fields.is_empty())
+            .map(|fields| {
+                let parameters: Vec<String> = fields[1..].iter()
+                    .map(|field| match field.parse::<usize>() {
+                        Ok(n) => (n + 1).to_string(),
+                        Err(_) => format!("\"{}\"", field),
+                    })
+                    .collect();
+                format!("{{\"type\": \"{}\", \"parameters\": [{}]}}", fields[0], parameters.join(", "))
+            })
+            .collect();
+        Ok(format!("[{}]", components.join(", ")))
+    }
+}
@@ -1,75 +1,171 @@
+use std::fmt;
+
+/// Identifies a wire of a `BinaryCircuit`; wire values are indexed by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WireId(pub usize);
+
+/// Identifies a gate of a `BinaryCircuit` by its position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GateId(pub usize);
+
+impl fmt::Display for WireId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "w{}", self.0)
+    }
+}
+
+impl fmt::Display for GateId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "g{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum GateType {
     // Existing gate types
     Constant(bool),
-    Input,
     AND,
     OR,
     XOR,
     NOT,
+    NAND,
+
+    // Script-level gate types, all of them single-bit
+    /// Forwards its only operand.
+    Copy,
+    /// Removes stack items for `OP_DROP`; it produces no bit and cannot be
+    /// evaluated.
+    Drop,
+    /// `[condition]`: set when execution has to stop, i.e. when the condition
+    /// is clear, as `OP_VERIFY` and `OP_EQUALVERIFY` use it.
+    Halt,
+    /// Set when all operands carry the same value.
+    Equal,
+    /// Number of bits of an item for `OP_SIZE`; a word-level result with no
+    /// single-bit value.
+    BitCount,
+    /// Pushes a fixed bit, like `Constant`.
+    PushConstant(bool),
+    /// `[select, a, b]`: `a` if `select` is set, `b` otherwise.
+    Mux,
 
     // New cryptographic gate types
+    /// Whole compression rounds work on words and cannot be lowered bit by bit.
     SHA256Round,
+    /// `[e, f, g]`: bit of `f` if `e` is set, bit of `g` otherwise.
     SHA256Choice,
+    /// `[a, b, c]`: majority of the three operands.
     SHA256Majority,
+    /// One bit of a rotated word; rotation is wiring, so it forwards its operand.
     SHA256Rotate,
     RIPEMD160Round,
+    /// `[x, y, z]`: bit of `y` if `x` is set, bit of `z` otherwise.
     RIPEMD160Choice,
+    /// `[x, y, z]`: majority of the three operands.
     RIPEMD160Majority,
+    /// One bit of a rotated word; forwards its operand.
     RIPEMD160Rotate,
     // ... other cryptographic gate types
 }
 
+impl GateType {
+    /// Stable numeric code used when serializing gates.
+    pub fn code(&self) -> u8 {
+        match self {
+            GateType::Constant(false) => 0,
+            GateType::Constant(true) => 1,
+            GateType::AND => 3,
+            GateType::OR => 4,
+            GateType::XOR => 5,
+            GateType::NOT => 6,
+            GateType::NAND => 7,
+            GateType::Copy => 8,
+            GateType::Drop => 9,
+            GateType::Halt => 10,
+            GateType::Equal => 11,
+            GateType::BitCount => 12,
+            GateType::PushConstant(false) => 13,
+            GateType::PushConstant(true) => 14,
+            GateType::Mux => 15,
+            GateType::SHA256Round => 16,
+            GateType::SHA256Choice => 17,
+            GateType::SHA256Majority => 18,
+            GateType::SHA256Rotate => 19,
+            GateType::RIPEMD160Round => 32,
+            GateType::RIPEMD160Choice => 33,
+            GateType::RIPEMD160Majority => 34,
+            GateType::RIPEMD160Rotate => 35,
+        }
+    }
+
+    /// Whether the gate is a constant, AND, XOR, NOT or NAND gate; all other
+    /// gate types are expanded by `BinaryCircuit::lower`.
+    pub fn is_primitive(&self) -> bool {
+        matches!(self, GateType::Constant(_) | GateType::AND | GateType::XOR | GateType::NOT | GateType::NAND)
+    }
+}
+
+/// A gate reading `inputs` and driving the single wire `output`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogicGate {
+    pub gate_type: GateType,
+    pub inputs: Vec<WireId>,
+    pub output: WireId,
+}
+
 impl LogicGate {
-    pub fn evaluate(&self, input_values: &[bool]) -> bool {
-        match self.gate_type {
-            GateType::Constant(value) => *value,
-            GateType::Input => input_values[0],
-            GateType::AND => input_values.iter().all(|v| *v),
-            GateType::OR => input_values.iter().any(|v| *v),
-            GateType::XOR => input_values.iter().fold(false, |acc, v| acc ^ *v),
-            GateType::NOT => !input_values[0],
+    pub fn new(gate_type: GateType, inputs: Vec<WireId>, output: WireId) -> Self {
+        LogicGate { gate_type, inputs, output }
+    }
 
-            // SHA-256 gate logic
-            GateType::SHA256Round => {
-                // Implement the logic for a single round of SHA-256
-            },
-            GateType::SHA256Choice => {
-                // Implement the choice function logic for SHA-256
-            },
-            GateType::SHA256Majority => {
-                // Implement the majority function logic for SHA-256
-            },
-            GateType::SHA256Rotate => {
-                // Implement the bitwise rotation logic for SHA-256
-            },
+    /// Evaluates the gate on its operand values.
+    ///
+    /// Returns `None` if the gate has no bit-level semantics or the number of
+    /// operands does not fit the gate.
+    pub fn evaluate(&self, input_values: &[bool]) -> Option<bool> {
+        match self.gate_type {
+            GateType::Constant(value) if input_values.is_empty() => Some(value),
+            GateType::NOT if input_values.len() != 1 => None,
+            GateType::NOT => Some(!input_values[0]),
+            GateType::AND | GateType::OR | GateType::XOR | GateType::NAND if input_values.is_empty() => None,
+            GateType::AND => Some(input_values.iter().all(|v| *v)),
+            GateType::OR => Some(input_values.iter().any(|v| *v)),
+            GateType::XOR => Some(input_values.iter().fold(false, |acc, v| acc ^ *v)),
+            GateType::NAND => Some(!input_values.iter().all(|v| *v)),
 
-            // RIPEMD-160 gate logic
-            GateType::RIPEMD160Round => {
-                // Implement the logic for a single round of RIPEMD-160
+            GateType::PushConstant(value) if input_values.is_empty() => Some(value),
+            GateType::Halt => match *input_values {
+                [condition] => Some(!condition),
+                _ => None,
             },
-            GateType::RIPEMD160Choice => {
-                // Implement the choice function logic for RIPEMD-160
+            GateType::Equal if input_values.is_empty() => None,
+            GateType::Equal => Some(input_values.iter().all(|v| *v == input_values[0])),
+            GateType::Copy | GateType::SHA256Rotate | GateType::RIPEMD160Rotate => match *input_values {
+                [value] => Some(value),
+                _ => None,
             },
-            GateType::RIPEMD160Majority => {
-                // Implement the majority function logic for RIPEMD-160
+            GateType::Mux | GateType::SHA256Choice | GateType::RIPEMD160Choice => match *input_values {
+                [select, a, b] => Some(if select { a } else { b }),
+                _ => None,
             },
-            GateType::RIPEMD160Rotate => {
-                // Implement the bitwise rotation logic for RIPEMD-160
+            GateType::SHA256Majority | GateType::RIPEMD160Majority => match *input_values {
+                [a, b, c] => Some((a & b) | (a & c) | (b & c)),
+                _ => None,
             },
 
-            // ... other cryptographic gate logic
+            // Round and bit count gates stand for whole words and have to be
+            // built from the gates above instead; `Drop` has no value at all.
+            _ => None,
         }
     }
-    
+
     pub fn serialize_for_risc0(&self) -> Vec<u8> {
         let mut data = Vec::new();
-        data.push(self.gate_type as u8);
-        for &input_index in &self.inputs {
-            data.extend(input_index.to_le_bytes());
+        data.push(self.gate_type.code());
+        for input in &self.inputs {
+            data.extend(input.0.to_le_bytes());
         }
+        data.extend(self.output.0.to_le_bytes());
         data
     }
 }
-
-
-
@@ -0,0 +1,270 @@
+//! Expansion of high-level gates into a small primitive basis.
+//!
+//! Lowering keeps every wire of the original circuit and its value: each gate
+//! is replaced by primitive gates that drive the same output wire, with any
+//! intermediate results on freshly allocated wires after the original ones.
+//! Constant gates are part of both bases.
+
+use std::fmt;
+
+use crate::binary_circuit::BinaryCircuit;
+use crate::logic_gate::{GateId, GateType, LogicGate, WireId};
+
+/// Gate set a lowered circuit is restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Basis {
+    /// Two-input AND and XOR, NOT and constants.
+    AndXorNot,
+    /// Two-input NAND and constants.
+    Nand,
+}
+
+impl Basis {
+    /// Whether `gate` is one of the gates this basis allows.
+    pub fn contains(&self, gate: &LogicGate) -> bool {
+        matches!(
+            (self, gate.gate_type, gate.inputs.len()),
+            (_, GateType::Constant(_), 0)
+                | (Basis::AndXorNot, GateType::AND | GateType::XOR, 2)
+                | (Basis::AndXorNot, GateType::NOT, 1)
+                | (Basis::Nand, GateType::NAND, 2)
+        )
+    }
+}
+
+/// Error raised while lowering a circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LowerError {
+    /// The gate stands for a whole round on words and has no bit-level expansion.
+    WordLevelGate { gate: GateId, gate_type: GateType },
+    /// The gate has a number of operands it cannot be evaluated with.
+    InvalidGate { gate: GateId, gate_type: GateType, operands: usize },
+}
+
+impl fmt::Display for LowerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LowerError::WordLevelGate { gate, gate_type } => {
+                write!(f, "gate {} ({:?}) works on words and cannot be lowered", gate, gate_type)
+            }
+            LowerError::InvalidGate { gate, gate_type, operands } => {
+                write!(f, "gate {} ({:?}) cannot be lowered with {} operands", gate, gate_type, operands)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LowerError {}
+
+impl BinaryCircuit {
+    /// Rewrites the circuit so it only uses gates of `basis`.
+    ///
+    /// Every wire keeps its index and carries the same value as before for
+    /// any input, so traces of the lowered circuit extend the original ones.
+    pub fn lower(&self, basis: Basis) -> Result<BinaryCircuit, LowerError> {
+        let mut lowering = Lowering { circuit: self.without_gates(), basis };
+        for (index, gate) in self.gates().iter().enumerate() {
+            lowering.gate(GateId(index), gate)?;
+        }
+        Ok(lowering.circuit)
+    }
+}
+
+/// Result of `BinaryCircuit::to_nand_only`.
+#[derive(Debug, Clone)]
+pub struct NandConversion {
+    pub circuit: BinaryCircuit,
+    pub gates_before: usize,
+    pub gates_after: usize,
+}
+
+impl NandConversion {
+    /// Factor by which the gate count grew.
+    pub fn blow_up(&self) -> f64 {
+        self.gates_after as f64 / self.gates_before.max(1) as f64
+    }
+}
+
+impl fmt::Display for NandConversion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} gates -> {} NAND gates ({:.2}x)", self.gates_before, self.gates_after, self.blow_up())
+    }
+}
+
+impl BinaryCircuit {
+    /// Rewrites the circuit into two-input NAND gates, keeping constants.
+    ///
+    /// Wire semantics are preserved as for `lower`; the lowered circuit can be
+    /// written with `to_bristol_format`.
+    pub fn to_nand_only(&self) -> Result<NandConversion, LowerError> {
+        let circuit = self.lower(Basis::Nand)?;
+        Ok(NandConversion {
+            gates_before: self.gates().len(),
+            gates_after: circuit.gates().len(),
+            circuit,
+        })
+    }
+}
+
+/// Emits primitive gates; each helper drives `out`, or a fresh wire if `None`.
+struct Lowering {
+    circuit: BinaryCircuit,
+    basis: Basis,
+}
+
+impl Lowering {
+    fn gate(&mut self, id: GateId, gate: &LogicGate) -> Result<(), LowerError> {
+        let out = Some(gate.output);
+        let invalid = || LowerError::InvalidGate { gate: id, gate_type: gate.gate_type, operands: gate.inputs.len() };
+        match (gate.gate_type, &gate.inputs[..]) {
+            (GateType::Constant(value) | GateType::PushConstant(value), []) => {
+                self.constant(value, out);
+            }
+            (GateType::NOT, &[a]) => {
+                self.not(a, out);
+            }
+            (GateType::Copy | GateType::SHA256Rotate | GateType::RIPEMD160Rotate, &[a]) => {
+                self.copy(a, out);
+            }
+            (GateType::AND, operands) if !operands.is_empty() => {
+                self.fold(operands, out, Lowering::and);
+            }
+            (GateType::OR, operands) if !operands.is_empty() => {
+                self.fold(operands, out, Lowering::or);
+            }
+            (GateType::XOR, operands) if !operands.is_empty() => {
+                self.fold(operands, out, Lowering::xor);
+            }
+            (GateType::Halt, &[condition]) => {
+                self.not(condition, out);
+            }
+            (GateType::NAND, [rest @ .., last]) => {
+                if rest.is_empty() {
+                    self.not(*last, out);
+                } else {
+                    let acc = self.fold(rest, None, Lowering::and);
+                    self.nand(acc, *last, out);
+                }
+            }
+            (GateType::Equal, [first, rest @ ..]) => {
+                if rest.is_empty() {
+                    self.constant(true, out);
+                } else {
+                    let same: Vec<WireId> = rest.iter()
+                        .map(|&other| {
+                            let diff = self.xor(*first, other, None);
+                            self.not(diff, None)
+                        })
+                        .collect();
+                    self.fold(&same, out, Lowering::and);
+                }
+            }
+            (GateType::Mux | GateType::SHA256Choice | GateType::RIPEMD160Choice, &[select, a, b]) => {
+                // b ^ (select & (a ^ b))
+                let diff = self.xor(a, b, None);
+                let picked = self.and(select, diff, None);
+                self.xor(b, picked, out);
+            }
+            (GateType::SHA256Majority | GateType::RIPEMD160Majority, &[a, b, c]) => {
+                // b ^ ((a ^ b) & (b ^ c))
+                let ab = self.xor(a, b, None);
+                let bc = self.xor(b, c, None);
+                let both = self.and(ab, bc, None);
+                self.xor(b, both, out);
+            }
+            (GateType::SHA256Round | GateType::RIPEMD160Round | GateType::BitCount, _) => {
+                return Err(LowerError::WordLevelGate { gate: id, gate_type: gate.gate_type });
+            }
+            _ => return Err(invalid()),
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, gate_type: GateType, inputs: Vec<WireId>, out: Option<WireId>) -> WireId {
+        let output = out.unwrap_or_else(|| self.circuit.new_wire());
+        self.circuit.add_gate(LogicGate::new(gate_type, inputs, output));
+        output
+    }
+
+    /// Combines `operands` left to right; only the last gate drives `out`.
+    fn fold(&mut self, operands: &[WireId], out: Option<WireId>, op: fn(&mut Self, WireId, WireId, Option<WireId>) -> WireId) -> WireId {
+        match operands {
+            [single] => self.copy(*single, out),
+            [first, middle @ .., last] => {
+                let acc = middle.iter().fold(*first, |acc, &next| op(self, acc, next, None));
+                op(self, acc, *last, out)
+            }
+            [] => unreachable!("callers check for operands"),
+        }
+    }
+
+    fn constant(&mut self, value: bool, out: Option<WireId>) -> WireId {
+        self.emit(GateType::Constant(value), vec![], out)
+    }
+
+    fn copy(&mut self, a: WireId, out: Option<WireId>) -> WireId {
+        match (self.basis, out) {
+            (_, None) => a,
+            (Basis::AndXorNot, out) => self.emit(GateType::AND, vec![a, a], out),
+            (Basis::Nand, out) => {
+                let inverted = self.not(a, None);
+                self.not(inverted, out)
+            }
+        }
+    }
+
+    fn not(&mut self, a: WireId, out: Option<WireId>) -> WireId {
+        match self.basis {
+            Basis::AndXorNot => self.emit(GateType::NOT, vec![a], out),
+            Basis::Nand => self.emit(GateType::NAND, vec![a, a], out),
+        }
+    }
+
+    fn and(&mut self, a: WireId, b: WireId, out: Option<WireId>) -> WireId {
+        match self.basis {
+            Basis::AndXorNot => self.emit(GateType::AND, vec![a, b], out),
+            Basis::Nand => {
+                let nand = self.emit(GateType::NAND, vec![a, b], None);
+                self.not(nand, out)
+            }
+        }
+    }
+
+    fn nand(&mut self, a: WireId, b: WireId, out: Option<WireId>) -> WireId {
+        match self.basis {
+            Basis::AndXorNot => {
+                let and = self.and(a, b, None);
+                self.not(and, out)
+            }
+            Basis::Nand => self.emit(GateType::NAND, vec![a, b], out),
+        }
+    }
+
+    fn xor(&mut self, a: WireId, b: WireId, out: Option<WireId>) -> WireId {
+        match self.basis {
+            Basis::AndXorNot => self.emit(GateType::XOR, vec![a, b], out),
+            Basis::Nand => {
+                let both = self.emit(GateType::NAND, vec![a, b], None);
+                let left = self.emit(GateType::NAND, vec![a, both], None);
+                let right = self.emit(GateType::NAND, vec![b, both], None);
+                self.emit(GateType::NAND, vec![left, right], out)
+            }
+        }
+    }
+
+    fn or(&mut self, a: WireId, b: WireId, out: Option<WireId>) -> WireId {
+        match self.basis {
+            // a ^ b ^ (a & b)
+            Basis::AndXorNot => {
+                let both = self.and(a, b, None);
+                let either = self.xor(a, b, None);
+                self.xor(either, both, out)
+            }
+            Basis::Nand => {
+                let not_a = self.not(a, None);
+                let not_b = self.not(b, None);
+                self.emit(GateType::NAND, vec![not_a, not_b], out)
+            }
+        }
+    }
+}
@@ -1,2 +1,24 @@
 pub mod logic_gate;
 pub mod binary_circuit;
+pub mod builder;
+pub mod word;
+pub mod sha256;
+pub mod ripemd160;
+pub mod bristol;
+pub mod json;
+pub mod lowering;
+pub mod optimizer;
+pub mod analysis;
+pub mod op_code_circuits;
+
+pub use logic_gate::{LogicGate, GateType, GateId, WireId};
+pub use binary_circuit::{BinaryCircuit, EvalError};
+pub use builder::CircuitBuilder;
+pub use word::Word;
+pub use sha256::sha256_circuit;
+pub use ripemd160::{hash160_circuit, ripemd160_circuit};
+pub use bristol::BristolError;
+pub use json::JsonError;
+pub use lowering::{Basis, LowerError, NandConversion};
+pub use optimizer::OptimizationStats;
+pub use analysis::CircuitAnalysis;
@@ -1,14 +1,22 @@
 use crate::binary_circuit::BinaryCircuit;
-use crate::logic_gate::{LogicGate, GateType};
+use crate::builder::CircuitBuilder;
+use crate::logic_gate::WireId;
+use crate::word::Word;
 
-// Imports and module declarations...
+/// Width of the script numbers our opcode circuits operate on.
+pub const WORD_BITS: usize = 32;
 
+type ScriptWord = Word<WORD_BITS>;
+
+/// Builds the circuit for `op_code`.
+///
+/// Stack items are circuit input values of `WORD_BITS` bits, bottom of the
+/// stack first, and the circuit outputs are the items the opcode leaves on the
+/// stack in the same order. Boolean results are single-bit outputs.
 pub fn create_op_code_circuit(op_code: &str) -> Result<BinaryCircuit, String> {
     match op_code {
         "OP_ADD" => op_add_circuit(),
         "OP_SUB" => op_sub_circuit(),
-        "OP_HASH160" => op_hash160_circuit(&input_data),
-        "OP_CHECKSIG" => op_checksig_circuit(),
         "OP_MULT" => op_mult_circuit(),
         "OP_IF" => op_if_else_endif_circuit(),
         "OP_VERIFY" => op_verify_circuit(),
@@ -20,499 +28,240 @@ pub fn create_op_code_circuit(op_code: &str) -> Result<BinaryCircuit, String> {
         "OP_2DROP" => op_2drop_circuit(),
         "OP_OVER" => op_over_circuit(),
         "OP_TUCK" => op_tuck_circuit(),
-        "OP_SIZE" => op_size_circuit(),
         "OP_EQUAL" => op_equal_circuit(),
+        "OP_SHA256" => op_sha256_circuit(),
+        "OP_RIPEMD160" => op_ripemd160_circuit(),
+        "OP_HASH160" => op_hash160_circuit(),
         // ... other opcodes ...
         _ => Err(format!("Unsupported opcode: {}", op_code)),
     }
 }
 
-// Implementations for each opcode circuit function...
-
-
-fn op_add_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-    // Assuming 32-bit addition for simplicity
-    let bit_length = 32;
-
-    let mut last_carry = None;
-    for i in 0..bit_length {
-        let input1 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-        let input2 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-        let carry_in = last_carry.unwrap_or_else(|| circuit.add_gate(LogicGate::new(GateType::Constant(false), vec![])));
-
-        // Full adder: sum = input1 XOR input2 XOR carry_in
-        let sum = circuit.add_gate(LogicGate::new(GateType::XOR, vec![input1, input2, carry_in]));
-
-        // Full adder: carry_out = (input1 AND input2) OR (carry_in AND (input1 XOR input2))
-        let carry_out = circuit.add_gate(LogicGate::new(
-            GateType::OR, 
-            vec![
-                circuit.add_gate(LogicGate::new(GateType::AND, vec![input1, input2])),
-                circuit.add_gate(LogicGate::new(GateType::AND, vec![input1, carry_in])),
-                circuit.add_gate(LogicGate::new(GateType::AND, vec![input2, carry_in]))
-            ]
-        ));        
-
-        last_carry = Some(carry_out);
-
-        // Connecting sum to the output
-        // ... logic to connect sum to output
-    }
-
-    Ok(circuit)
+/// Declares `count` stack items as inputs.
+fn stack_inputs(builder: &mut CircuitBuilder, count: usize) -> Vec<ScriptWord> {
+    (0..count).map(|_| builder.input_word()).collect()
 }
 
-fn create_message_schedule(circuit: &mut BinaryCircuit) -> Vec<Vec<GateId>> {
-    let mut schedule: Vec<Vec<GateId>> = vec![];
-    // Fill the first 16 words directly from the input block
-    for _ in 0..16 {
-        schedule.push((0..32).map(|i| circuit.add_gate(LogicGate::new(GateType::Input, vec![]))).collect());
+/// Declares the resulting stack items as outputs.
+fn stack_outputs(builder: &mut CircuitBuilder, items: &[ScriptWord]) {
+    for item in items {
+        builder.output_word(item);
     }
-    // Generate the remaining 48 words
-    /*for i in 16..64 {
-        let s0 = true; // Rotate and shift operations on schedule[i-15]
-        let s1 = true;// Rotate and shift operations on schedule[i-2]
-        let word = // Modular addition of s0, s1, schedule[i-16], and schedule[i-7]
-        schedule.push(word);
-    }*/
-    for i in 16..64 {
-        let s0 = sigma0(schedule[i - 15]);
-        let s1 = sigma1(schedule[i - 2]);
-        let word = add_modulo([
-            s0, s1, schedule[i - 16], schedule[i - 7]
-        ]);
-        schedule.push(word);
-    }    
-    schedule
 }
 
-fn sha256_round(circuit: &mut BinaryCircuit, working_vars: &[LogicGateId], message_schedule_word: LogicGateId) -> Vec<LogicGateId> {
-    //let (a, b, c, d, e, f, g, h) = (working_vars[0], working_vars[1], ..., working_vars[7]);
-    let (a, b, c, d, e, f, g, h) = working_vars;
-
-    /*let s1 = true;// Rotate and shift operations on 'e'
-    let ch = true;// Choice operation: (e AND f) XOR ((NOT e) AND g)
-    let temp1 = true;// Modular addition of h, s1, ch, round constant, and message_word
-    let s0 = true;// Rotate and shift operations on 'a'
-    let maj = true;// Majority operation: (a AND b) XOR (a AND c) XOR (b AND c)
-    let vec = vec![temp1 + d, a, b, c, temp1, e, f, g];
-    let temp2 = // Modular addition of s0 and maj
-
-    // Update working variables
-    vec // Modular addition where necessary
-    */
-    let s1 = sigma1(e);
-    let ch = choice(e, f, g);
-    let temp1 = add_modulo([h, s1, ch, K[i], message_schedule_word]);
-    let s0 = sigma0(a);
-    let maj = majority(a, b, c);
-    let temp2 = add_modulo([s0, maj]);
-
-    let new_h = g;
-    let new_g = f;
-    let new_f = e;
-    let new_e = add_modulo([d, temp1]);
-    let new_d = c;
-    let new_c = b;
-    let new_b = a;
-    let new_a = add_modulo([temp1, temp2]);
-
-    vec![new_a, new_b, new_c, new_d, new_e, new_f, new_g, new_h]
-
+/// A circuit that only rearranges stack items; it contains no gates.
+fn permutation_circuit(inputs: usize, order: &[usize]) -> Result<BinaryCircuit, String> {
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, inputs);
+    let result: Vec<ScriptWord> = order.iter().map(|&i| items[i]).collect();
+    stack_outputs(&mut builder, &result);
+    Ok(builder.build())
 }
 
-fn op_sha256_circuit(message: &[u8]) -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Define initial hash values as constants
-    let h0 = circuit.add_gate(LogicGate::new(GateType::Constant(0x6a09e667), vec![]));
-    let h1 = circuit.add_gate(LogicGate::new(GateType::Constant(0xbb67ae85), vec![]));
-    let h2 = circuit.add_gate(LogicGate::new(GateType::Constant(0x3c6ef372), vec![]));
-    let h3 = circuit.add_gate(LogicGate::new(GateType::Constant(0xa54ff53a), vec![]));
-    let h4 = circuit.add_gate(LogicGate::new(GateType::Constant(0x510e527f), vec![]));
-    let h5 = circuit.add_gate(LogicGate::new(GateType::Constant(0x9b05688c), vec![]));
-    let h6 = circuit.add_gate(LogicGate::new(GateType::Constant(0x1f83d9ab), vec![]));
-    let h7 = circuit.add_gate(LogicGate::new(GateType::Constant(0x5be0cd19), vec![]));
-
-    // Create the SHA-256 message schedule
-    let message_schedule = create_message_schedule(message);
-
-    // Perform SHA-256 compression
-    let mut working_vars = vec![h0, h1, h2, h3, h4, h5, h6, h7];
-    for i in 0..64 {
-        working_vars = sha256_round(&mut circuit, &working_vars, &message_schedule[i]);
-    }
-
-    // Store final hash values
-    let final_hash0 = circuit.add_gate(LogicGate::new(GateType::COPY, vec![working_vars[0]]));
-    let final_hash1 = circuit.add_gate(LogicGate::new(GateType::COPY, vec![working_vars[1]]));
-    let final_hash2 = circuit.add_gate(LogicGate::new(GateType::COPY, vec![working_vars[2]]));
-    let final_hash3 = circuit.add_gate(LogicGate::new(GateType::COPY, vec![working_vars[3]]));
-    let final_hash4 = circuit.add_gate(LogicGate::new(GateType::COPY, vec![working_vars[4]]));
-    let final_hash5 = circuit.add_gate(LogicGate::new(GateType::COPY, vec![working_vars[5]]));
-    let final_hash6 = circuit.add_gate(LogicGate::new(GateType::COPY, vec![working_vars[6]]));
-    let final_hash7 = circuit.add_gate(LogicGate::new(GateType::COPY, vec![working_vars[7]]));
-
-    // Combine final hash values into a single output
-    let output = circuit.add_gate(LogicGate::new(GateType::XOR, vec![final_hash0, final_hash1, final_hash2, final_hash3, final_hash4, final_hash5, final_hash6, final_hash7]));
-
-    Ok(circuit)
-}
-
-
-fn op_equal_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-    // Assuming comparison of 32-bit values
-    let bit_length = 32;
-    let mut comparisons = vec![];
-
-    for _ in 0..bit_length {
-        let input1 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-        let input2 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-        // XOR for comparison: if both bits are same, result is 0
-        let xor_gate = circuit.add_gate(LogicGate::new(GateType::XOR, vec![input1, input2]));
-        comparisons.push(xor_gate);
-    }
-
-    // AND gate to ensure all comparisons are 0 (true)
-    let final_and = circuit.add_gate(LogicGate::new(GateType::AND, comparisons));
-
-    Ok(circuit)
+fn op_add_circuit() -> Result<BinaryCircuit, String> {
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, 2);
+    let sum = builder.add_words(&items[0], &items[1]);
+    builder.output_word(&sum);
+    Ok(builder.build())
 }
 
 fn op_sub_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Assuming 32-bit subtraction
-    const BIT_LENGTH: usize = 32;
-
-    // Two's complement representation of the subtrahend
-    let borrow_in = circuit.add_gate(LogicGate::new(GateType::Constant(false), vec![]));
-
-    for i in 0..BIT_LENGTH {
-        let input1 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-        let input2 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-        // Full adder with borrow input
-        let sum = circuit.add_gate(LogicGate::new(GateType::XOR, vec![input1, input2, borrow_in]));
-        let borrow_out = circuit.add_gate(LogicGate::new(GateType::AND, vec![input2, borrow_in]));
-
-        // Update borrow input for the next stage
-        let borrow_in = circuit.add_gate(LogicGate::new(GateType::OR, vec![input1, borrow_out]));
-
-        // Connect gates appropriately
-        circuit.add_connection(input1, sum);
-        circuit.add_connection(input2, sum);
-        circuit.add_connection(borrow_in, sum);
-
-        circuit.add_connection(input2, borrow_out);
-        circuit.add_connection(borrow_in, borrow_out);
-    }
-
-    Ok(circuit)
-}
-
-fn ripemd160_round(circuit: &mut BinaryCircuit, working_vars: &[LogicGateId], message_schedule_word: LogicGateId) -> Vec<LogicGateId> {
-    // Extract the current working variables
-    let (a, b, c, d, e) = (working_vars[0], working_vars[1], working_vars[2], working_vars[3], working_vars[4]);
-
-    // Perform the round operations using logic gates
-    let a_next = circuit.add_gate(LogicGate::new(GateType::RIPEMD_160_ROUND_OP, vec![a, b, c, d, e, message_schedule_word]));
-
-    // Update the working variables for the next round
-    let mut updated_working_vars = Vec::new();
-    updated_working_vars.push(b);
-    updated_working_vars.push(c);
-    updated_working_vars.push(d);
-    updated_working_vars.push(e);
-    updated_working_vars.push(a_next);
-
-    updated_working_vars
-}
-
-fn op_hash160_circuit(input: &[u8]) -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Perform SHA-256 hashing
-    //let sha256_output = op_sha256_circuit(&circuit, input)?;
-    let sha256_output = op_sha256_circuit(&input)?;
-
-
-    // Perform RIPEMD-160 hashing on the SHA-256 result
-    let ripemd160_output = op_ripemd160_circuit(&circuit, &sha256_output)?;
-
-    // The result of op_hash160_circuit is the RIPEMD-160 output
-    Ok(ripemd160_output)
-}
-
-
-/* 
-
-fn op_hash160_circuit(message: &[u8]) -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Perform SHA-256 and RIPEMD-160 hashing
-    let sha256_result = op_sha256_circuit(message);
-    if let Ok(sha256_circuit) = sha256_result {
-        let message_hash = sha256_circuit.output;
-
-        // Convert SHA-256 hash into 160-bit RIPEMD-160 hash
-        let mut ripemd160_input = message_hash;
-        for i in 0..12 {
-            // Extend the 512-bit SHA-256 hash into a 640-bit message for RIPEMD-160
-            ripemd160_input.extend(&message_hash);
-        }
-
-        // Initialize RIPEMD-160 working variables
-        let mut working_vars = vec![];
-        for i in 0..80 {
-            let temp = ripemd160_input[((i / 8) * 4) + 3];
-            for j in 0..7 {
-                let value = ripemd160_input[((i / 8) * 4 + j)];
-                let result = (temp << 8) | value;
-                ripemd160_input[((i / 8) * 4 + j)] = result;
-            }
-        }
-
-        // Perform 20 rounds of RIPEMD-160 compression
-        for _ in 0..20 {
-            for i in 0..4 {
-                let temp = working_vars[i * 16 + 14];
-                for j in 0..7 {
-                    let value = working_vars[i * 16 + (j + 1) % 16];
-                    let result = (temp << 8) | value;
-                    working_vars[i * 16 + (j + 1) % 16] = result;
-                }
-            }
-        }
-
-        // Extract the final 160-bit hash value
-        let output = circuit.add_gate(LogicGate::new(GateType::XOR, vec![working_vars[0], working_vars[5], working_vars[10], working_vars[15], working_vars[20], working_vars[25], working_vars[30], working_vars[35]]));
-
-        return Ok(circuit);
-    } else {
-        return Err(sha256_result.err().unwrap());
-    }
-}
-*/
-
-fn op_checksig_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract signature components
-    let signature = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let signature_length = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Verify the signature length
-    let valid_signature_length = circuit.add_gate(LogicGate::new(GateType::Constant(true), vec![]));
-    let signature_length_equal = circuit.add_gate(LogicGate::new(GateType::EQUAL, vec![signature_length, valid_signature_length]));
-
-    // Step 2: Recover public key from signature
-    let s = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let r = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let g = circuit.add_gate(LogicGate::new(GateType::Constant(BASE_POINT_G), vec![]));
-    let p = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    let public_key = circuit.add_gate(LogicGate::new(GateType::ECDSA_PUBLIC_KEY_RECOVERY, vec![s, r, g]));
-
-    // Step 3: Verify signature using elliptic curve cryptography
-    let hash = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let verified = circuit.add_gate(LogicGate::new(GateType::ECDSA_SIGNATURE_VERIFICATION, vec![hash, public_key, s, r]));
-
-    // Check if the signature verification was successful
-    let verification_result = circuit.add_gate(LogicGate::new(GateType::AND, vec![valid_signature_length, verified]));
-
-    Ok(circuit)
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, 2);
+    let difference = builder.sub_words(&items[0], &items[1]);
+    builder.output_word(&difference);
+    Ok(builder.build())
 }
 
+/// Shift-and-add multiplication modulo `2^WORD_BITS`.
 fn op_mult_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract operands
-    let input1 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let input2 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Step 2: Perform multiplication using bit-shifting and addition
-    let output = circuit.add_gate(LogicGate::new(GateType::MULTIPLICATION, vec![input1, input2]));
-
-    Ok(circuit)
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, 2);
+    let (a, b) = (items[0], items[1]);
+
+    let mut product = builder.constant_word(0);
+    for i in 0..WORD_BITS {
+        let shifted = builder.shl_word(&a, i);
+        let added = builder.add_words(&product, &shifted);
+        product = builder.mux_words(b.bit(i), &added, &product);
+    }
+    builder.output_word(&product);
+    Ok(builder.build())
 }
 
+/// Leaves the second item if the condition is non-zero and the third otherwise.
 fn op_if_else_endif_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract condition and then/else values
-    let condition = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let then_value = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let else_value = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Step 2: Create MUX gates for conditional branching
-    let mux_output = circuit.add_gate(LogicGate::new(GateType::MUX, vec![condition, then_value, else_value]));
-
-    Ok(circuit)
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, 3);
+    let condition = builder.nonzero(&items[0]);
+    let result = builder.mux_words(condition, &items[1], &items[2]);
+    builder.output_word(&result);
+    Ok(builder.build())
 }
 
+/// Outputs whether execution continues, i.e. whether the top item is non-zero.
 fn op_verify_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top stack item
-    let stack_top = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Step 2: Check if the top stack item is true (non-zero)
-    let is_true = circuit.add_gate(LogicGate::new(GateType::EQUAL, vec![stack_top, circuit.add_gate(LogicGate::new(GateType::Constant(1), vec![]))]));
-
-    // Step 3: Halt execution if the top stack item is false
-    circuit.add_gate(LogicGate::new(GateType::HALT, vec![is_true]));
-
-    Ok(circuit)
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, 1);
+    let is_true = builder.nonzero(&items[0]);
+    builder.output(is_true);
+    Ok(builder.build())
 }
 
-fn op_dup_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top stack item
-    let stack_top = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Step 2: Add a copy of the top stack item to the stack
-    circuit.add_gate(LogicGate::new(GateType::COPY, vec![stack_top]));
-
-    Ok(circuit)
+fn op_equal_circuit() -> Result<BinaryCircuit, String> {
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, 2);
+    let equal = builder.eq_words(&items[0], &items[1]);
+    builder.output(equal);
+    Ok(builder.build())
 }
 
+/// Outputs whether execution continues, i.e. whether the top two items are equal.
 fn op_equalverify_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top two stack items
-    let stack_top1 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let stack_top2 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Step 2: Check if the top two stack items are equal
-    let is_equal = circuit.add_gate(LogicGate::new(GateType::EQUAL, vec![stack_top1, stack_top2]));
-
-    // Step 3: Halt execution if the top two stack items are not equal
-    circuit.add_gate(LogicGate::new(GateType::HALT, vec![is_equal]));
+    op_equal_circuit()
+}
 
-    Ok(circuit)
+fn op_dup_circuit() -> Result<BinaryCircuit, String> {
+    permutation_circuit(1, &[0, 0])
 }
 
 fn op_rot_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top three stack items
-    let stack_top1 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let stack_top2 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let stack_top3 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Step 2: Rotate the stack items
-    let temp = circuit.add_gate(LogicGate::new(GateType::COPY, vec![stack_top1]));
-    circuit.add_gate(LogicGate::new(GateType::COPY, vec![stack_top2]));
-    circuit.add_gate(LogicGate::new(GateType::COPY, vec![stack_top3]));
-
-    circuit.add_connection(temp, stack_top2);
-    circuit.add_connection(stack_top2, stack_top3);
-    circuit.add_connection(stack_top3, stack_top1);
-
-    Ok(circuit)
+    permutation_circuit(3, &[1, 2, 0])
 }
-fn op_swap_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top two stack items
-    let stack_top1 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let stack_top2 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
 
-    // Step 2: Swap the stack items
-    circuit.add_connection(stack_top1, stack_top2);
-    circuit.add_connection(stack_top2, stack_top1);
-
-    Ok(circuit)
+fn op_swap_circuit() -> Result<BinaryCircuit, String> {
+    permutation_circuit(2, &[1, 0])
 }
-fn op_2dup_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top two stack items
-    let stack_top1 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let stack_top2 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Step 2: Duplicate the top two stack items
-    circuit.add_gate(LogicGate::new(GateType::COPY, vec![stack_top1]));
-    circuit.add_gate(LogicGate::new(GateType::COPY, vec![stack_top2]));
 
-    // Step 3: Push the copied stack items to the top of the stack
-    circuit.add_connection(stack_top1, stack_top2);
-    circuit.add_connection(stack_top2, stack_top1);
-
-    Ok(circuit)
+fn op_2dup_circuit() -> Result<BinaryCircuit, String> {
+    permutation_circuit(2, &[0, 1, 0, 1])
 }
-fn op_2drop_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Remove the top two stack items
-    circuit.add_gate(LogicGate::new(GateType::DROP, vec![]));
-    circuit.add_gate(LogicGate::new(GateType::DROP, vec![]));
 
-    Ok(circuit)
+fn op_2drop_circuit() -> Result<BinaryCircuit, String> {
+    permutation_circuit(2, &[])
 }
-fn op_over_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top stack item and the second-to-top stack item
-    let stack_top = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let second_to_top = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
 
-    // Step 2: Copy the second-to-top stack item to the top of the stack
-    circuit.add_connection(second_to_top, stack_top);
-
-    Ok(circuit)
+fn op_over_circuit() -> Result<BinaryCircuit, String> {
+    permutation_circuit(2, &[0, 1, 0])
 }
-fn op_tuck_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top stack item
-    let stack_top = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Step 2: Create a copy of the top stack item
-    let copy_of_stack_top = circuit.add_gate(LogicGate::new(GateType::COPY, vec![stack_top]));
-
-    // Step 3: Insert the copy of the top stack item onto the second-to-top position
-    let second_to_top = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    circuit.add_connection(copy_of_stack_top, second_to_top);
 
-    Ok(circuit)
+fn op_tuck_circuit() -> Result<BinaryCircuit, String> {
+    permutation_circuit(2, &[1, 0, 1])
 }
-fn op_size_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top stack item
-    let stack_top = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-
-    // Step 2: Count the number of bits required to represent the top stack item
-    let bit_count = circuit.add_gate(LogicGate::new(GateType::BIT_COUNT, vec![stack_top]));
 
-    // Step 3: Push the bit count onto the stack
-    circuit.add_gate(LogicGate::new(GateType::PUSH_CONSTANT, vec![bit_count]));
-
-    Ok(circuit)
+/// Bits of one byte of `word`, most significant first as the hash circuits read them.
+fn byte_bits(word: &ScriptWord, byte: usize) -> Vec<WireId> {
+    word.wires()[8 * byte..8 * byte + 8].iter().rev().copied().collect()
 }
 
-fn op_equal_circuit() -> Result<BinaryCircuit, String> {
-    let mut circuit = BinaryCircuit::new();
-
-    // Step 1: Extract the top two stack items
-    let stack_top1 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
-    let stack_top2 = circuit.add_gate(LogicGate::new(GateType::Input, vec![]));
+/// Candidate encodings of `item` as Script stores it, each with a wire that is
+/// set for exactly the candidate matching the item.
+///
+/// Script numbers are minimally encoded: the little-endian magnitude without
+/// trailing zero bytes, with the sign in the top bit of the last byte and an
+/// extra byte when the magnitude already uses that bit. Zero is the empty item
+/// and a 32-bit item takes up to five bytes.
+fn minimal_encodings(builder: &mut CircuitBuilder, item: &ScriptWord) -> Vec<(WireId, Vec<WireId>)> {
+    let sign = item.bit(WORD_BITS - 1);
+    let zero = builder.constant_word(0);
+    let negated = builder.sub_words(&zero, item);
+    let magnitude = builder.mux_words(sign, &negated, item);
+
+    let byte_count = WORD_BITS / 8;
+    let mut top_bits = Vec::with_capacity(byte_count);
+    let mut highest = vec![None; byte_count];
+    let mut above = builder.constant(false);
+    for byte in (0..byte_count).rev() {
+        let bits = byte_bits(&magnitude, byte);
+        let nonzero = bits[1..].iter().fold(bits[0], |acc, &bit| builder.or(acc, bit));
+        // Byte `byte` is the most significant non-zero byte.
+        let no_higher = builder.not(above);
+        highest[byte] = Some(builder.and(nonzero, no_higher));
+        above = builder.or(above, nonzero);
+        top_bits.push(bits[0]);
+    }
+    top_bits.reverse();
+    let highest: Vec<WireId> = highest.into_iter().map(|wire| wire.expect("every byte is visited")).collect();
+
+    let is_zero = builder.not(above);
+    let mut candidates = vec![(is_zero, Vec::new())];
+    for len in 1..=byte_count + 1 {
+        // The magnitude ends in the last byte with its top bit free, or one
+        // byte earlier with the top bit taken.
+        let mut select = builder.constant(false);
+        if len <= byte_count {
+            let free = builder.not(top_bits[len - 1]);
+            let fits = builder.and(highest[len - 1], free);
+            select = builder.or(select, fits);
+        }
+        if len >= 2 {
+            let spills = builder.and(highest[len - 2], top_bits[len - 2]);
+            select = builder.or(select, spills);
+        }
 
-    // Step 2: Compare the top two stack items using XOR gates
-    let is_equal = circuit.add_gate(LogicGate::new(GateType::XOR, vec![stack_top1, stack_top2]));
+        let mut message = Vec::with_capacity(8 * len);
+        for byte in 0..len {
+            let mut bits = if byte < byte_count {
+                byte_bits(&magnitude, byte)
+            } else {
+                vec![builder.constant(false); 8]
+            };
+            if byte == len - 1 {
+                // The top bit is free in the selected candidate.
+                bits[0] = sign;
+            }
+            message.extend(bits);
+        }
+        candidates.push((select, message));
+    }
+    candidates
+}
 
-    // Step 3: Convert the XOR output to a boolean value (0 for equal, 1 for not equal)
-    let equal = circuit.add_gate(LogicGate::new(GateType::EQUAL, vec![is_equal, circuit.add_gate(LogicGate::new(GateType::Constant(0), vec![]))]));
+/// Digest of the minimal encoding of `item` under `hash`.
+///
+/// Every candidate length is hashed and the digest of the selected one is kept.
+fn item_digest(
+    builder: &mut CircuitBuilder,
+    item: &ScriptWord,
+    hash: fn(&mut CircuitBuilder, &[WireId]) -> Vec<WireId>,
+) -> Vec<WireId> {
+    let mut digest: Option<Vec<WireId>> = None;
+    for (select, message) in minimal_encodings(builder, item) {
+        let candidate: Vec<WireId> = hash(builder, &message).into_iter().map(|bit| builder.and(select, bit)).collect();
+        digest = Some(match digest {
+            Some(digest) => digest.into_iter().zip(candidate).map(|(a, b)| builder.or(a, b)).collect(),
+            None => candidate,
+        });
+    }
+    digest.expect("zero is always a candidate")
+}
 
-    Ok(circuit)
+/// SHA-256 of the top item's minimal encoding; the outputs are the 256 digest bits.
+fn op_sha256_circuit() -> Result<BinaryCircuit, String> {
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, 1);
+    let digest = item_digest(&mut builder, &items[0], CircuitBuilder::sha256);
+    builder.outputs(&digest);
+    Ok(builder.build())
 }
 
+/// RIPEMD-160 of the top item's minimal encoding; 160 digest bits out.
+fn op_ripemd160_circuit() -> Result<BinaryCircuit, String> {
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, 1);
+    let digest = item_digest(&mut builder, &items[0], CircuitBuilder::ripemd160);
+    builder.outputs(&digest);
+    Ok(builder.build())
+}
 
+/// HASH160 of the top item's minimal encoding; 160 digest bits out.
+fn op_hash160_circuit() -> Result<BinaryCircuit, String> {
+    let mut builder = CircuitBuilder::new();
+    let items = stack_inputs(&mut builder, 1);
+    let digest = item_digest(&mut builder, &items[0], CircuitBuilder::hash160);
+    builder.outputs(&digest);
+    Ok(builder.build())
+}
 
 // ... functions for other opcodes ...
@@ -0,0 +1,300 @@
+//! Gate-count optimizations over `BinaryCircuit`.
+//!
+//! Gates are rebuilt in topological order while
+//! - propagating constants and simplifying gates with constant operands,
+//! - cancelling repeated XOR operands, also across chains of XOR gates,
+//! - removing double negations and operands that repeat in AND/OR gates,
+//! - merging structurally equal gates (common subexpressions),
+//!
+//! after which gates that no output depends on are dropped. Inputs and
+//! outputs keep their order and values; internal wires are renumbered.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::binary_circuit::{BinaryCircuit, EvalError};
+use crate::logic_gate::{GateType, LogicGate, WireId};
+
+/// Gate counts of an optimization run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OptimizationStats {
+    pub gates_before: usize,
+    pub gates_after: usize,
+    /// Gates replaced by a constant or by one of their operands.
+    pub folded: usize,
+    /// Gates merged into an equal gate computed earlier.
+    pub merged: usize,
+    /// Gates removed because no output depends on them.
+    pub removed: usize,
+}
+
+impl fmt::Display for OptimizationStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} gates ({} folded, {} merged, {} removed)",
+            self.gates_before, self.gates_after, self.folded, self.merged, self.removed
+        )
+    }
+}
+
+impl BinaryCircuit {
+    /// Returns an equivalent circuit with fewer gates and what was saved.
+    pub fn optimize(&self) -> Result<(BinaryCircuit, OptimizationStats), EvalError> {
+        let order = self.topological_order()?;
+        let mut optimizer = Optimizer::default();
+        let mut signals = vec![None; self.wire_count()];
+        for &input in self.inputs() {
+            signals[input.0] = Some(Signal::Wire(optimizer.circuit.add_input()));
+        }
+
+        for id in order {
+            let gate = self.gate(id);
+            let operands: Vec<Signal> = gate.inputs.iter()
+                .map(|input| signals[input.0].ok_or(EvalError::DanglingWire { gate: id, wire: *input }))
+                .collect::<Result<_, _>>()?;
+
+            let (gates, merged) = (optimizer.circuit.gates().len(), optimizer.stats.merged);
+            let signal = optimizer.gate(gate.gate_type, operands);
+            if optimizer.circuit.gates().len() == gates && optimizer.stats.merged == merged {
+                optimizer.stats.folded += 1;
+            }
+            signals[gate.output.0] = Some(signal);
+        }
+
+        for &output in self.outputs() {
+            let signal = signals[output.0].ok_or(EvalError::UndrivenOutput(output))?;
+            let wire = optimizer.materialize(signal);
+            optimizer.circuit.add_output(wire);
+        }
+
+        let mut stats = optimizer.stats;
+        let mut circuit = remove_dead_gates(&optimizer.circuit);
+        circuit.set_input_widths(self.input_widths().to_vec());
+        circuit.set_output_widths(self.output_widths().to_vec());
+        stats.gates_before = self.gates().len();
+        stats.gates_after = circuit.gates().len();
+        stats.removed = optimizer.circuit.gates().len() - stats.gates_after;
+        Ok((circuit, stats))
+    }
+}
+
+/// Value of an original wire in the optimized circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Signal {
+    Const(bool),
+    Wire(WireId),
+}
+
+/// Operands of an AND/OR gate after simplification, or the value they force.
+enum Operands {
+    Forced(bool),
+    Wires(Vec<WireId>),
+}
+
+#[derive(Default)]
+struct Optimizer {
+    circuit: BinaryCircuit,
+    /// Gate driving each wire of `circuit`, by wire index.
+    definitions: Vec<Option<(GateType, Vec<WireId>)>>,
+    structure: HashMap<(GateType, Vec<WireId>), WireId>,
+    stats: OptimizationStats,
+}
+
+impl Optimizer {
+    fn gate(&mut self, gate_type: GateType, operands: Vec<Signal>) -> Signal {
+        match (gate_type, &operands[..]) {
+            (GateType::Constant(value), []) => Signal::Const(value),
+            (GateType::NOT, &[a]) => self.not(a),
+            (GateType::AND, [_, ..]) => match self.and_operands(&operands, false) {
+                Operands::Forced(value) => Signal::Const(value),
+                Operands::Wires(wires) => self.combine(GateType::AND, wires),
+            },
+            (GateType::NAND, [_, ..]) => match self.and_operands(&operands, false) {
+                Operands::Forced(value) => Signal::Const(!value),
+                Operands::Wires(wires) if wires.len() == 1 => self.not(Signal::Wire(wires[0])),
+                Operands::Wires(wires) => Signal::Wire(self.emit(GateType::NAND, wires)),
+            },
+            (GateType::OR, [_, ..]) => match self.and_operands(&operands, true) {
+                Operands::Forced(value) => Signal::Const(value),
+                Operands::Wires(wires) => self.combine(GateType::OR, wires),
+            },
+            (GateType::XOR, [_, ..]) => self.xor(&operands),
+            _ => self.opaque(gate_type, operands),
+        }
+    }
+
+    /// Gates without algebraic rules are only folded if all operands are constant.
+    fn opaque(&mut self, gate_type: GateType, operands: Vec<Signal>) -> Signal {
+        let constants: Option<Vec<bool>> = operands.iter()
+            .map(|signal| match signal {
+                Signal::Const(value) => Some(*value),
+                Signal::Wire(_) => None,
+            })
+            .collect();
+        if let Some(value) = constants.and_then(|values| LogicGate::new(gate_type, Vec::new(), WireId(0)).evaluate(&values)) {
+            return Signal::Const(value);
+        }
+        let wires = operands.into_iter().map(|signal| self.materialize(signal)).collect();
+        Signal::Wire(self.emit(gate_type, wires))
+    }
+
+    fn not(&mut self, a: Signal) -> Signal {
+        match a {
+            Signal::Const(value) => Signal::Const(!value),
+            Signal::Wire(wire) => match self.negation_of(wire) {
+                Some(inner) => Signal::Wire(inner),
+                None => Signal::Wire(self.emit(GateType::NOT, vec![wire])),
+            },
+        }
+    }
+
+    /// Simplifies AND operands, or OR operands if `absorbing` is `true`:
+    /// constants that do not decide the result are dropped, repeats are merged
+    /// and complementary operands force the result.
+    fn and_operands(&self, operands: &[Signal], absorbing: bool) -> Operands {
+        let mut wires = BTreeSet::new();
+        for operand in operands {
+            match *operand {
+                Signal::Const(value) if value == absorbing => return Operands::Forced(absorbing),
+                Signal::Const(_) => {}
+                Signal::Wire(wire) => {
+                    wires.insert(wire);
+                }
+            }
+        }
+        if wires.iter().any(|&wire| self.negation_of(wire).is_some_and(|inner| wires.contains(&inner))) {
+            return Operands::Forced(absorbing);
+        }
+        if wires.is_empty() {
+            return Operands::Forced(!absorbing);
+        }
+        Operands::Wires(wires.into_iter().collect())
+    }
+
+    fn combine(&mut self, gate_type: GateType, wires: Vec<WireId>) -> Signal {
+        match wires[..] {
+            [single] => Signal::Wire(single),
+            _ => Signal::Wire(self.emit(gate_type, wires)),
+        }
+    }
+
+    /// XOR with repeated operands cancelled and constants collected into a
+    /// final negation. An operand computed by another XOR gate is replaced by
+    /// that gate's operands whenever this makes the operand set smaller.
+    fn xor(&mut self, operands: &[Signal]) -> Signal {
+        let mut parity = false;
+        let mut wires = BTreeSet::new();
+        for operand in operands {
+            match *operand {
+                Signal::Const(value) => parity ^= value,
+                Signal::Wire(wire) => toggle(&mut wires, wire),
+            }
+        }
+
+        while let Some(smaller) = self.expand_xor_operand(&wires) {
+            wires = smaller;
+        }
+
+        let result = match wires.len() {
+            0 => return Signal::Const(parity),
+            1 => Signal::Wire(*wires.iter().next().expect("one operand")),
+            _ => Signal::Wire(self.emit(GateType::XOR, wires.into_iter().collect())),
+        };
+        if parity {
+            self.not(result)
+        } else {
+            result
+        }
+    }
+
+    /// Operand set with one XOR-driven operand replaced by that gate's
+    /// operands, if that cancels enough operands to shrink the set.
+    fn expand_xor_operand(&self, wires: &BTreeSet<WireId>) -> Option<BTreeSet<WireId>> {
+        wires.iter().find_map(|&wire| match self.definition(wire) {
+            Some((GateType::XOR, inner)) => {
+                let mut expanded = wires.clone();
+                toggle(&mut expanded, wire);
+                for &operand in inner {
+                    toggle(&mut expanded, operand);
+                }
+                Some(expanded).filter(|expanded| expanded.len() < wires.len())
+            }
+            _ => None,
+        })
+    }
+
+    fn definition(&self, wire: WireId) -> Option<&(GateType, Vec<WireId>)> {
+        self.definitions.get(wire.0).and_then(Option::as_ref)
+    }
+
+    fn negation_of(&self, wire: WireId) -> Option<WireId> {
+        match self.definition(wire) {
+            Some((GateType::NOT, inner)) => Some(inner[0]),
+            _ => None,
+        }
+    }
+
+    /// Wire carrying `signal`, adding a constant gate if needed.
+    fn materialize(&mut self, signal: Signal) -> WireId {
+        match signal {
+            Signal::Const(value) => self.emit(GateType::Constant(value), Vec::new()),
+            Signal::Wire(wire) => wire,
+        }
+    }
+
+    /// Adds a gate unless an equal one exists; operands of symmetric gates
+    /// are expected in sorted order.
+    fn emit(&mut self, gate_type: GateType, inputs: Vec<WireId>) -> WireId {
+        let key = (gate_type, inputs);
+        if let Some(&wire) = self.structure.get(&key) {
+            self.stats.merged += 1;
+            return wire;
+        }
+        let output = self.circuit.new_wire();
+        self.circuit.add_gate(LogicGate::new(gate_type, key.1.clone(), output));
+        self.definitions.resize(self.circuit.wire_count(), None);
+        self.definitions[output.0] = Some(key.clone());
+        self.structure.insert(key, output);
+        output
+    }
+}
+
+fn toggle(set: &mut BTreeSet<WireId>, wire: WireId) {
+    if !set.remove(&wire) {
+        set.insert(wire);
+    }
+}
+
+/// Copies the gates outputs depend on into a circuit with consecutive wires.
+///
+/// `circuit` has its gates in topological order, as built by `Optimizer`.
+fn remove_dead_gates(circuit: &BinaryCircuit) -> BinaryCircuit {
+    let mut live = vec![false; circuit.wire_count()];
+    for &output in circuit.outputs() {
+        live[output.0] = true;
+    }
+    for gate in circuit.gates().iter().rev() {
+        if live[gate.output.0] {
+            for input in &gate.inputs {
+                live[input.0] = true;
+            }
+        }
+    }
+
+    let mut compact = BinaryCircuit::new();
+    let mut renumbered = vec![None; circuit.wire_count()];
+    for &input in circuit.inputs() {
+        renumbered[input.0] = Some(compact.add_input());
+    }
+    for gate in circuit.gates().iter().filter(|gate| live[gate.output.0]) {
+        let inputs = gate.inputs.iter().map(|input| renumbered[input.0].expect("operands come first")).collect();
+        let output = compact.new_wire();
+        compact.add_gate(LogicGate::new(gate.gate_type, inputs, output));
+        renumbered[gate.output.0] = Some(output);
+    }
+    for &output in circuit.outputs() {
+        compact.add_output(renumbered[output.0].expect("outputs are live"));
+    }
+    compact
+}
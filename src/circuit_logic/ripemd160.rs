@@ -0,0 +1,176 @@
+//! Gate-level RIPEMD-160 and Bitcoin's HASH160 (`RIPEMD160(SHA256(m))`).
+//!
+//! Messages and digests use the same bit stream layout as `sha256`: bytes in
+//! order, the most significant bit of each byte first. RIPEMD-160 reads its
+//! 32-bit words from that stream in little-endian byte order.
+
+use crate::binary_circuit::BinaryCircuit;
+use crate::builder::CircuitBuilder;
+use crate::logic_gate::WireId;
+use crate::word::Word;
+
+type Word32 = Word<32>;
+
+const INITIAL_STATE: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+const K_LEFT: [u32; 5] = [0x00000000, 0x5a827999, 0x6ed9eba1, 0x8f1bbcdc, 0xa953fd4e];
+const K_RIGHT: [u32; 5] = [0x50a28be6, 0x5c4dd124, 0x6d703ef3, 0x7a6d76e9, 0x00000000];
+
+/// Message word selected in each step of the left line.
+const R_LEFT: [usize; 80] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+    7, 4, 13, 1, 10, 6, 15, 3, 12, 0, 9, 5, 2, 14, 11, 8,
+    3, 10, 14, 4, 9, 15, 8, 1, 2, 7, 0, 6, 13, 11, 5, 12,
+    1, 9, 11, 10, 0, 8, 12, 4, 13, 3, 7, 15, 14, 5, 6, 2,
+    4, 0, 5, 9, 7, 12, 2, 10, 14, 1, 3, 8, 11, 6, 15, 13,
+];
+
+/// Message word selected in each step of the right line.
+const R_RIGHT: [usize; 80] = [
+    5, 14, 7, 0, 9, 2, 11, 4, 13, 6, 15, 8, 1, 10, 3, 12,
+    6, 11, 3, 7, 0, 13, 5, 10, 14, 15, 8, 12, 4, 9, 1, 2,
+    15, 5, 1, 3, 7, 14, 6, 9, 11, 8, 12, 2, 10, 0, 4, 13,
+    8, 6, 4, 1, 3, 11, 15, 0, 5, 12, 2, 13, 9, 7, 10, 14,
+    12, 15, 10, 4, 1, 5, 8, 7, 6, 2, 13, 14, 0, 3, 9, 11,
+];
+
+/// Left rotation applied in each step of the left line.
+const S_LEFT: [usize; 80] = [
+    11, 14, 15, 12, 5, 8, 7, 9, 11, 13, 14, 15, 6, 7, 9, 8,
+    7, 6, 8, 13, 11, 9, 7, 15, 7, 12, 15, 9, 11, 7, 13, 12,
+    11, 13, 6, 7, 14, 9, 13, 15, 14, 8, 13, 6, 5, 12, 7, 5,
+    11, 12, 14, 15, 14, 15, 9, 8, 9, 14, 5, 6, 8, 6, 5, 12,
+    9, 15, 5, 11, 6, 8, 13, 12, 5, 12, 13, 14, 11, 8, 5, 6,
+];
+
+/// Left rotation applied in each step of the right line.
+const S_RIGHT: [usize; 80] = [
+    8, 9, 9, 11, 13, 15, 15, 5, 7, 7, 8, 11, 14, 14, 12, 6,
+    9, 13, 15, 7, 12, 8, 9, 11, 7, 7, 12, 7, 6, 15, 13, 11,
+    9, 7, 15, 11, 8, 6, 6, 14, 12, 13, 5, 14, 13, 13, 7, 5,
+    15, 5, 8, 11, 14, 14, 6, 14, 6, 9, 12, 9, 12, 5, 15, 8,
+    8, 5, 12, 9, 12, 5, 14, 6, 8, 13, 6, 5, 15, 13, 11, 11,
+];
+
+/// Number of 512-bit blocks a message of `message_bits` bits occupies once padded.
+pub fn ripemd160_block_count(message_bits: usize) -> usize {
+    (message_bits + 1 + 64).div_ceil(512)
+}
+
+/// Builds a circuit computing RIPEMD-160 of a message of exactly `message_bits` bits.
+///
+/// The inputs are the message bits and the 160 outputs are the digest bits.
+pub fn ripemd160_circuit(message_bits: usize) -> BinaryCircuit {
+    let mut builder = CircuitBuilder::new();
+    let message = builder.inputs(message_bits);
+    let digest = builder.ripemd160(&message);
+    builder.outputs(&digest);
+    builder.build()
+}
+
+/// Builds a circuit computing HASH160 of a message of exactly `message_bits` bits.
+pub fn hash160_circuit(message_bits: usize) -> BinaryCircuit {
+    let mut builder = CircuitBuilder::new();
+    let message = builder.inputs(message_bits);
+    let digest = builder.hash160(&message);
+    builder.outputs(&digest);
+    builder.build()
+}
+
+/// Reads a little-endian 32-bit word from the bit stream.
+fn word_from_stream(bits: &[WireId]) -> Word32 {
+    let lsb_first: Vec<WireId> = bits.chunks(8).flat_map(|byte| byte.iter().rev().copied()).collect();
+    Word::from_slice(&lsb_first)
+}
+
+/// Writes a 32-bit word to the bit stream in little-endian order.
+fn word_to_stream(word: &Word32) -> impl Iterator<Item = WireId> + '_ {
+    word.wires().chunks(8).flat_map(|byte| byte.iter().rev().copied())
+}
+
+impl CircuitBuilder {
+    /// RIPEMD-160 digest of `message`, returned as 160 wires.
+    pub fn ripemd160(&mut self, message: &[WireId]) -> Vec<WireId> {
+        let padded = self.ripemd160_pad(message);
+        let mut state = INITIAL_STATE.map(|h| self.constant_word(h as u64));
+        for block in padded.chunks(512) {
+            let words = std::array::from_fn(|i| word_from_stream(&block[32 * i..32 * (i + 1)]));
+            state = self.ripemd160_compress(&state, &words);
+        }
+        state.iter().flat_map(word_to_stream).collect()
+    }
+
+    /// HASH160 digest of `message`: RIPEMD-160 of its SHA-256 digest.
+    pub fn hash160(&mut self, message: &[WireId]) -> Vec<WireId> {
+        let sha256 = self.sha256(message);
+        self.ripemd160(&sha256)
+    }
+
+    /// Appends the `1` bit, zero fill and the 64-bit message length in
+    /// little-endian byte order.
+    fn ripemd160_pad(&mut self, message: &[WireId]) -> Vec<WireId> {
+        let one = self.constant(true);
+        let zero = self.constant(false);
+        let padded_len = 512 * ripemd160_block_count(message.len());
+        let length = message.len() as u64;
+
+        let mut padded = message.to_vec();
+        padded.push(one);
+        padded.resize(padded_len - 64, zero);
+        for byte in 0..8 {
+            padded.extend((0..8).rev().map(|i| if (length >> (8 * byte + i)) & 1 == 1 { one } else { zero }));
+        }
+        padded
+    }
+
+    /// One application of the RIPEMD-160 compression function, running the
+    /// left and right lines over the same block and combining them.
+    pub fn ripemd160_compress(&mut self, state: &[Word32; 5], block: &[Word32; 16]) -> [Word32; 5] {
+        let mut left = *state;
+        let mut right = *state;
+        for step in 0..80 {
+            let round = step / 16;
+            left = self.ripemd160_step(&left, round, &block[R_LEFT[step]], K_LEFT[round], S_LEFT[step]);
+            right = self.ripemd160_step(&right, 4 - round, &block[R_RIGHT[step]], K_RIGHT[round], S_RIGHT[step]);
+        }
+
+        let [h0, h1, h2, h3, h4] = *state;
+        [
+            self.sum_words(&[h1, left[2], right[3]]),
+            self.sum_words(&[h2, left[3], right[4]]),
+            self.sum_words(&[h3, left[4], right[0]]),
+            self.sum_words(&[h4, left[0], right[1]]),
+            self.sum_words(&[h0, left[1], right[2]]),
+        ]
+    }
+
+    /// One step on `[a, b, c, d, e]` using boolean function `function`.
+    fn ripemd160_step(&mut self, line: &[Word32; 5], function: usize, x: &Word32, k: u32, s: usize) -> [Word32; 5] {
+        let [a, b, c, d, e] = *line;
+        let f = self.ripemd160_function(function, &b, &c, &d);
+        let k = self.constant_word(k as u64);
+        let sum = self.sum_words(&[a, f, *x, k]);
+        let t = self.add_words(&sum.rotate_left(s), &e);
+        [e, t, b, c.rotate_left(10), d]
+    }
+
+    /// The five boolean functions `f1`..`f5`, indexed from zero.
+    fn ripemd160_function(&mut self, function: usize, x: &Word32, y: &Word32, z: &Word32) -> Word32 {
+        match function {
+            0 => self.xor3_words(x, y, z),
+            1 => self.choose_words(x, y, z),
+            2 => {
+                let not_y = self.not_word(y);
+                let either = self.or_words(x, &not_y);
+                self.xor_words(&either, z)
+            }
+            3 => self.choose_words(z, x, y),
+            4 => {
+                let not_z = self.not_word(z);
+                let either = self.or_words(y, &not_z);
+                self.xor_words(x, &either)
+            }
+            _ => unreachable!("RIPEMD-160 has five boolean functions"),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Gate-level SHA-256 as specified in FIPS 180-4.
+//!
+//! Messages and digests are bit streams in the order SHA-256 consumes them:
+//! the most significant bit of the first byte comes first. Use
+//! `word::bytes_to_bits` and `word::bits_to_bytes` to convert from and to bytes.
+
+use crate::binary_circuit::BinaryCircuit;
+use crate::builder::CircuitBuilder;
+use crate::logic_gate::WireId;
+use crate::word::Word;
+
+type Word32 = Word<32>;
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Number of 512-bit blocks a message of `message_bits` bits occupies once padded.
+pub fn sha256_block_count(message_bits: usize) -> usize {
+    (message_bits + 1 + 64).div_ceil(512)
+}
+
+/// Builds a circuit hashing a message of exactly `message_bits` bits.
+///
+/// The inputs are the message bits and the 256 outputs are the digest bits.
+pub fn sha256_circuit(message_bits: usize) -> BinaryCircuit {
+    let mut builder = CircuitBuilder::new();
+    let message = builder.inputs(message_bits);
+    let digest = builder.sha256(&message);
+    builder.outputs(&digest);
+    builder.build()
+}
+
+/// Reads a big-endian 32-bit word from the bit stream.
+fn word_from_stream(bits: &[WireId]) -> Word32 {
+    let lsb_first: Vec<WireId> = bits.iter().rev().copied().collect();
+    Word::from_slice(&lsb_first)
+}
+
+/// Writes a 32-bit word to the bit stream in big-endian order.
+fn word_to_stream(word: &Word32) -> impl Iterator<Item = WireId> + '_ {
+    word.wires().iter().rev().copied()
+}
+
+impl CircuitBuilder {
+    /// SHA-256 digest of `message`, returned as 256 wires.
+    ///
+    /// The message length is fixed by the circuit, so padding only adds
+    /// constant wires.
+    pub fn sha256(&mut self, message: &[WireId]) -> Vec<WireId> {
+        let padded = self.sha256_pad(message);
+        let mut state = INITIAL_STATE.map(|h| self.constant_word(h as u64));
+        for block in padded.chunks(512) {
+            let words = std::array::from_fn(|i| word_from_stream(&block[32 * i..32 * (i + 1)]));
+            state = self.sha256_compress(&state, &words);
+        }
+        state.iter().flat_map(word_to_stream).collect()
+    }
+
+    /// Appends the `1` bit, zero fill and 64-bit message length.
+    fn sha256_pad(&mut self, message: &[WireId]) -> Vec<WireId> {
+        let one = self.constant(true);
+        let zero = self.constant(false);
+        let padded_len = 512 * sha256_block_count(message.len());
+        let length = message.len() as u64;
+
+        let mut padded = message.to_vec();
+        padded.push(one);
+        padded.resize(padded_len - 64, zero);
+        padded.extend((0..64).rev().map(|i| if (length >> i) & 1 == 1 { one } else { zero }));
+        padded
+    }
+
+    /// One application of the SHA-256 compression function to a 512-bit block.
+    pub fn sha256_compress(&mut self, state: &[Word32; 8], block: &[Word32; 16]) -> [Word32; 8] {
+        let mut schedule = block.to_vec();
+        for t in 16..64 {
+            let s0 = self.sha256_small_sigma(&schedule[t - 15], 7, 18, 3);
+            let s1 = self.sha256_small_sigma(&schedule[t - 2], 17, 19, 10);
+            let next = self.sum_words(&[s1, schedule[t - 7], s0, schedule[t - 16]]);
+            schedule.push(next);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for (t, &k) in K.iter().enumerate() {
+            let s1 = self.sha256_big_sigma(&e, 6, 11, 25);
+            let ch = self.choose_words(&e, &f, &g);
+            let k = self.constant_word(k as u64);
+            let t1 = self.sum_words(&[h, s1, ch, k, schedule[t]]);
+            let s0 = self.sha256_big_sigma(&a, 2, 13, 22);
+            let maj = self.sha256_majority(&a, &b, &c);
+            let t2 = self.add_words(&s0, &maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = self.add_words(&d, &t1);
+            d = c;
+            c = b;
+            b = a;
+            a = self.add_words(&t1, &t2);
+        }
+
+        let working = [a, b, c, d, e, f, g, h];
+        std::array::from_fn(|i| self.add_words(&state[i], &working[i]))
+    }
+
+    /// `σ0`/`σ1` of the message schedule: two rotations and a shift.
+    fn sha256_small_sigma(&mut self, x: &Word32, r1: usize, r2: usize, shift: usize) -> Word32 {
+        let shifted = self.shr_word(x, shift);
+        self.xor3_words(&x.rotate_right(r1), &x.rotate_right(r2), &shifted)
+    }
+
+    /// `Σ0`/`Σ1` of the round function: three rotations.
+    fn sha256_big_sigma(&mut self, x: &Word32, r1: usize, r2: usize, r3: usize) -> Word32 {
+        self.xor3_words(&x.rotate_right(r1), &x.rotate_right(r2), &x.rotate_right(r3))
+    }
+
+    /// `Maj(a, b, c)` with one AND per bit: `b ^ ((a ^ b) & (b ^ c))`.
+    fn sha256_majority(&mut self, a: &Word32, b: &Word32, c: &Word32) -> Word32 {
+        let ab = self.xor_words(a, b);
+        let bc = self.xor_words(b, c);
+        let both = self.and_words(&ab, &bc);
+        self.xor_words(b, &both)
+    }
+}
@@ -0,0 +1,218 @@
+use crate::builder::CircuitBuilder;
+use crate::logic_gate::WireId;
+
+/// An `N`-bit value carried on circuit wires, least significant bit first.
+///
+/// Rotations only rewire bits and add no gates; arithmetic and bitwise
+/// operations are provided by `CircuitBuilder` and expand to XOR, AND and NOT
+/// gates, so the resulting circuits can be written as Bristol files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Word<const N: usize>([WireId; N]);
+
+impl<const N: usize> Word<N> {
+    pub fn new(wires: [WireId; N]) -> Self {
+        Word(wires)
+    }
+
+    /// Builds a word from exactly `N` wires.
+    pub fn from_slice(wires: &[WireId]) -> Self {
+        assert_eq!(wires.len(), N, "a {}-bit word needs {} wires", N, N);
+        Word(std::array::from_fn(|i| wires[i]))
+    }
+
+    pub fn wires(&self) -> &[WireId; N] {
+        &self.0
+    }
+
+    /// Wire of bit `i`, which has weight `2^i`.
+    pub fn bit(&self, i: usize) -> WireId {
+        self.0[i]
+    }
+
+    pub fn rotate_right(&self, n: usize) -> Self {
+        Word(std::array::from_fn(|i| self.0[(i + n) % N]))
+    }
+
+    pub fn rotate_left(&self, n: usize) -> Self {
+        self.rotate_right(N - n % N)
+    }
+}
+
+/// Splits `value` into `width` booleans, least significant bit first.
+pub fn bits_of(value: u64, width: usize) -> Vec<bool> {
+    (0..width).map(|i| i < 64 && (value >> i) & 1 == 1).collect()
+}
+
+/// Reassembles a value from booleans, least significant bit first.
+pub fn value_of(bits: &[bool]) -> u64 {
+    bits.iter().rev().fold(0, |acc, &bit| (acc << 1) | bit as u64)
+}
+
+/// Bit stream of `bytes`, most significant bit of each byte first.
+pub fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes.iter().flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1)).collect()
+}
+
+/// Packs a bit stream into bytes, most significant bit of each byte first.
+pub fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8).map(|chunk| chunk.iter().fold(0, |acc, &bit| (acc << 1) | bit as u8)).collect()
+}
+
+impl CircuitBuilder {
+    /// Declares an `N`-bit input value.
+    pub fn input_word<const N: usize>(&mut self) -> Word<N> {
+        Word::from_slice(&self.inputs(N))
+    }
+
+    /// Drives an `N`-bit word with the low bits of `value`.
+    pub fn constant_word<const N: usize>(&mut self, value: u64) -> Word<N> {
+        let mut constants = [None, None];
+        let bits = bits_of(value, N);
+        Word(std::array::from_fn(|i| {
+            *constants[bits[i] as usize].get_or_insert_with(|| self.constant(bits[i]))
+        }))
+    }
+
+    /// Declares `word` as an `N`-bit output value.
+    pub fn output_word<const N: usize>(&mut self, word: &Word<N>) {
+        self.outputs(word.wires());
+    }
+
+    pub fn and_words<const N: usize>(&mut self, a: &Word<N>, b: &Word<N>) -> Word<N> {
+        Word(std::array::from_fn(|i| self.and(a.0[i], b.0[i])))
+    }
+
+    /// Bitwise OR, built as `a ^ b ^ (a & b)` to stay within XOR/AND.
+    pub fn or_words<const N: usize>(&mut self, a: &Word<N>, b: &Word<N>) -> Word<N> {
+        Word(std::array::from_fn(|i| {
+            let both = self.and(a.0[i], b.0[i]);
+            let either = self.xor(a.0[i], b.0[i]);
+            self.xor(either, both)
+        }))
+    }
+
+    pub fn xor_words<const N: usize>(&mut self, a: &Word<N>, b: &Word<N>) -> Word<N> {
+        Word(std::array::from_fn(|i| self.xor(a.0[i], b.0[i])))
+    }
+
+    pub fn xor3_words<const N: usize>(&mut self, a: &Word<N>, b: &Word<N>, c: &Word<N>) -> Word<N> {
+        let partial = self.xor_words(a, b);
+        self.xor_words(&partial, c)
+    }
+
+    pub fn not_word<const N: usize>(&mut self, a: &Word<N>) -> Word<N> {
+        Word(std::array::from_fn(|i| self.not(a.0[i])))
+    }
+
+    /// Adds `a`, `b` and an optional carry bit modulo `2^N` with a ripple-carry adder.
+    ///
+    /// Each full adder costs one AND gate: `carry' = c ^ ((a ^ c) & (b ^ c))`.
+    pub fn add_words_with_carry<const N: usize>(&mut self, a: &Word<N>, b: &Word<N>, carry_in: Option<WireId>) -> Word<N> {
+        let mut carry = carry_in;
+        let mut sum = Vec::with_capacity(N);
+        for i in 0..N {
+            let (x, y) = (a.0[i], b.0[i]);
+            let half = self.xor(x, y);
+            let last = i + 1 == N;
+            match carry {
+                None => {
+                    sum.push(half);
+                    if !last {
+                        carry = Some(self.and(x, y));
+                    }
+                }
+                Some(c) => {
+                    sum.push(self.xor(half, c));
+                    if !last {
+                        let xc = self.xor(x, c);
+                        let yc = self.xor(y, c);
+                        let both = self.and(xc, yc);
+                        carry = Some(self.xor(both, c));
+                    }
+                }
+            }
+        }
+        Word::from_slice(&sum)
+    }
+
+    /// `a + b` modulo `2^N`.
+    pub fn add_words<const N: usize>(&mut self, a: &Word<N>, b: &Word<N>) -> Word<N> {
+        self.add_words_with_carry(a, b, None)
+    }
+
+    /// Sum of all `words` modulo `2^N`.
+    pub fn sum_words<const N: usize>(&mut self, words: &[Word<N>]) -> Word<N> {
+        let (first, rest) = words.split_first().expect("at least one word to add");
+        rest.iter().fold(*first, |acc, word| self.add_words(&acc, word))
+    }
+
+    /// `a - b` modulo `2^N`, computed as `a + !b + 1`.
+    pub fn sub_words<const N: usize>(&mut self, a: &Word<N>, b: &Word<N>) -> Word<N> {
+        let not_b = self.not_word(b);
+        let one = self.constant(true);
+        self.add_words_with_carry(a, &not_b, Some(one))
+    }
+
+    /// Shifts towards the most significant bit, filling with zeros.
+    pub fn shl_word<const N: usize>(&mut self, a: &Word<N>, n: usize) -> Word<N> {
+        if n == 0 {
+            return *a;
+        }
+        let zero = self.constant(false);
+        Word(std::array::from_fn(|i| if i >= n { a.0[i - n] } else { zero }))
+    }
+
+    /// Shifts towards the least significant bit, filling with zeros.
+    pub fn shr_word<const N: usize>(&mut self, a: &Word<N>, n: usize) -> Word<N> {
+        if n == 0 {
+            return *a;
+        }
+        let zero = self.constant(false);
+        Word(std::array::from_fn(|i| if i + n < N { a.0[i + n] } else { zero }))
+    }
+
+    /// Selects `a` if `select` is set and `b` otherwise: `b ^ (select & (a ^ b))`.
+    pub fn mux_words<const N: usize>(&mut self, select: WireId, a: &Word<N>, b: &Word<N>) -> Word<N> {
+        Word(std::array::from_fn(|i| {
+            let diff = self.xor(a.0[i], b.0[i]);
+            let picked = self.and(select, diff);
+            self.xor(b.0[i], picked)
+        }))
+    }
+
+    /// Bitwise multiplexer: bits of `a` where `select` is set and of `b` elsewhere.
+    pub fn choose_words<const N: usize>(&mut self, select: &Word<N>, a: &Word<N>, b: &Word<N>) -> Word<N> {
+        let diff = self.xor_words(a, b);
+        let picked = self.and_words(select, &diff);
+        self.xor_words(b, &picked)
+    }
+
+    /// Single wire that is set if every bit of `a` is set.
+    pub fn all_set<const N: usize>(&mut self, a: &Word<N>) -> WireId {
+        let mut level = a.0.to_vec();
+        while level.len() > 1 {
+            level = level.chunks(2)
+                .map(|pair| match *pair {
+                    [x, y] => self.and(x, y),
+                    [x] => x,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level.pop().unwrap_or_else(|| self.constant(true))
+    }
+
+    /// Single wire that is set if any bit of `a` is set.
+    pub fn nonzero<const N: usize>(&mut self, a: &Word<N>) -> WireId {
+        let inverted = self.not_word(a);
+        let all_clear = self.all_set(&inverted);
+        self.not(all_clear)
+    }
+
+    /// Single wire that is set if `a` and `b` carry the same value.
+    pub fn eq_words<const N: usize>(&mut self, a: &Word<N>, b: &Word<N>) -> WireId {
+        let diff = self.xor_words(a, b);
+        let same = self.not_word(&diff);
+        self.all_set(&same)
+    }
+}
@@ -15,6 +15,7 @@ pub struct TxOutput {
     pub script_pubkey: String,// ScriptPubKey (hex format)
 }
 
+#[derive(Default)]
 pub struct BristolEncoder;
 
 impl BristolEncoder {
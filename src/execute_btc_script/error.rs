@@ -7,6 +7,8 @@ use bitcoin::blockdata::script;
 /// Equivalent to Bitcoin Core's `ScriptError_t`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExecError {
+	EvalFalse,
+	ScriptSize,
 	DisabledOpcode,
 	OpCodeseparator,
 	BadOpcode,
@@ -23,6 +25,7 @@ pub enum ExecError {
 	EqualVerify,
 	NumEqualVerify,
 	CheckSigVerify,
+	CheckMultiSigVerify,
 	TapscriptValidationWeight,
 	PubkeyType,
 	SchnorrSigSize,
@@ -30,6 +33,18 @@ pub enum ExecError {
 	SchnorrSig,
 	TapscriptCheckMultiSig,
 	PubkeyCount,
+	SigCount,
+	SigPushOnly,
+	SigNullDummy,
+	CleanStack,
+	DiscourageUpgradableWitnessProgram,
+	WitnessProgramWrongLength,
+	WitnessProgramWitnessEmpty,
+	WitnessProgramMismatch,
+	WitnessMalleated,
+	WitnessMalleatedP2SH,
+	WitnessUnexpected,
+	TaprootWrongControlSize,
 	StackSize,
 	WitnessPubkeyType,
 
@@ -42,4 +57,10 @@ pub enum Error {
 	Exec(ExecError),
 	InvalidScript(script::Error),
 	Other(&'static str),
-}
\ No newline at end of file
+}
+
+impl From<ExecError> for Error {
+	fn from(e: ExecError) -> Error {
+		Error::Exec(e)
+	}
+}
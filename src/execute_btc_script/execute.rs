@@ -4,9 +4,8 @@ use std::io::{self, Write};
 use std::path::PathBuf;
 
 use bitcoin::{ScriptBuf, Transaction};
-use bitcoin::hashes::Hash;
 use bitcoin::hex::DisplayHex;
-use bitcoin::taproot::TapLeafHash;
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
 use clap::Parser;
 
 use bitcoin_scriptexec::*;
@@ -50,6 +49,7 @@ fn inner_main() -> Result<(), String> {
 	println!("Script in hex: {}", script.as_bytes().to_lower_hex_string());
 	println!("Script size: {} bytes", script.as_bytes().len());
 
+	let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
 	let start = std::time::Instant::now();
 	let mut exec = Exec::new(
 		ExecCtx::Tapscript,
@@ -63,7 +63,7 @@ fn inner_main() -> Result<(), String> {
 			},
 			prevouts: vec![],
 			input_idx: 0,
-			taproot_annex_scriptleaf: Some((TapLeafHash::all_zeros(), None)),
+			taproot_annex_scriptleaf: Some((leaf_hash, None)),
 		},
 		script,
 		vec![],
@@ -6,7 +6,7 @@ use bitcoin::{Opcode, Script};
 use bitcoin::hex::DisplayHex;
 use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
 
-use crate::ExecStats;
+use super::ExecStats;
 
 
 /// Simple utility wrapper to serde-serialize using [fmt::Display].
@@ -83,4 +83,4 @@ impl<'a> Serialize for RunResult<'a> {
 		}
 		m.end()
 	}
-}
\ No newline at end of file
+}
\ No newline at end of file
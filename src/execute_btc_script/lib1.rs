@@ -1,10 +1,14 @@
 
+// Vendored code is kept as upstream wrote it; `verify` lints again.
+#![allow(clippy::all)]
+
 use std::{cmp, io};
 use std::borrow::Cow;
+use std::convert::{TryFrom, TryInto};
 
 use bitcoin::consensus::Encodable;
 use bitcoin::hashes::{Hash, ripemd160, sha1, sha256, hash160, sha256d};
-use bitcoin::opcodes::{self, all::*, Opcode};
+use bitcoin::opcodes::{all::*, Opcode};
 use bitcoin::script::{self, Instruction, Instructions, Script, ScriptBuf};
 use bitcoin::sighash::SighashCache;
 use bitcoin::taproot::{self, TapLeafHash};
@@ -21,14 +25,20 @@ use utils::ConditionStack;
 
 mod signatures;
 
+mod verify;
+pub use verify::{verify_input, VerifyFlags};
+
 mod error;
 pub use error::{Error, ExecError};
 
 #[cfg(feature = "json")]
 pub mod json;
-#[cfg(feature = "wasm")]
-mod wasm;
+// `wasm.rs` and `execute.rs` are the WebAssembly and command line front-ends
+// of the upstream bitcoin-scriptexec crate and are not built as part of bitvm.
+
 
+/// Maximum size of a legacy or segwit v0 script in bytes
+const MAX_SCRIPT_SIZE: usize = 10_000;
 
 /// Maximum number of non-push operations per script
 const MAX_OPS_PER_SCRIPT: usize = 201;
@@ -86,6 +96,8 @@ pub struct Options {
 	pub verify_csv: bool,
 	/// Verify conditionals are minimally encoded.
 	pub verify_minimal_if: bool,
+	/// Require the extra element consumed by OP_CHECKMULTISIG to be empty.
+	pub verify_null_dummy: bool,
 
 	pub experimental: Experimental,
 }
@@ -97,6 +109,7 @@ impl Default for Options {
 			verify_cltv: true,
 			verify_csv: true,
 			verify_minimal_if: true,
+			verify_null_dummy: true,
 			experimental: Experimental {
 				op_cat: true,
 			},
@@ -129,7 +142,8 @@ pub struct ExecutionResult {
 impl ExecutionResult {
 	fn from_final_stack(final_stack: Vec<Vec<u8>>) -> ExecutionResult {
 		ExecutionResult {
-			success: final_stack == vec![vec![opcodes::OP_TRUE.to_u8()]],
+			// A single true element, as required by the clean stack rule.
+			success: final_stack.len() == 1 && script::read_scriptbool(&final_stack[0]),
 			final_stack: final_stack,
 			error: None,
 			opcode: None,
@@ -182,7 +196,7 @@ trait StackExt: AsRef<Vec<Vec<u8>>> + AsMut<Vec<Vec<u8>>> {
 
 	/// Push the number on the stack, encoded as a scriptint.
 	fn pushnum(&mut self, num: i64) {
-		self.as_mut().push(script::scriptint_vec(num));
+		self.as_mut().push(scriptint_vec(num));
 	}
 
 	/// Ensure there are at least [min_nb_items] on the stack, otherwise
@@ -320,6 +334,18 @@ impl Exec {
 		Ok(ret)
 	}
 
+	/// Seeds the Tapscript validation weight budget with [witness_size], the
+	/// serialized size of the whole witness including the script, the
+	/// control block and the annex, where [Exec::new] only counts the stack.
+	///
+	/// Has to be called before executing.
+	pub(crate) fn set_witness_size(&mut self, witness_size: usize) {
+		let start_validation_weight = VALIDATION_WEIGHT_OFFSET + witness_size as i64;
+		self.validation_weight = start_validation_weight;
+		self.stats.start_validation_weight = start_validation_weight;
+		self.stats.validation_weight = start_validation_weight;
+	}
+
 	//////////////////
 	// SOME GETTERS //
 	//////////////////
@@ -412,7 +438,9 @@ impl Exec {
 			None => return false,
 		};
 
-		let lock_time = match LockTime::from_num(sequence) {
+		// Only the type flag and the low 16 bits carry the lock time (BIP 68).
+		let masked = (sequence & 0x0040_ffff) as u32;
+		let lock_time = match transaction::Sequence::from_consensus(masked).to_relative_lock_time() {
 			Some(lt) => lt,
 			None => return false,
 		};
@@ -428,21 +456,24 @@ impl Exec {
 		true
 	}
 
-	fn check_sig_pre_tap(&mut self, sig: &[u8], pk: &[u8]) -> Result<bool, ExecError> {
-		//TODO(stevenroose) somehow sigops limit should be checked somewhere
-
-		// Drop the signature in pre-segwit scripts but not segwit scripts
-		let mut scriptcode = Cow::Borrowed(self.script_code.clone().as_bytes());
+	/// The script code signatures commit to.
+	///
+	/// Pre-segwit scripts drop every push of the signatures being checked,
+	/// like Core's `FindAndDelete`; segwit scripts keep it as is.
+	fn signed_script_code(&self, sigs: &[&[u8]]) -> Cow<'static, [u8]> {
+		let mut scriptcode = Cow::Borrowed(self.script_code.as_bytes());
 		if self.ctx == ExecCtx::Legacy {
-			let mut i = 0;
-			while i < scriptcode.len() - sig.len() {
-				if &scriptcode[i..i+sig.len()] == sig {
-					scriptcode.to_mut().drain(i..i+sig.len());
-				} else {
-					i += 1;
-				}
+			for sig in sigs {
+				let push = <&script::PushBytes>::try_from(*sig).expect("stack items are at most 520 bytes");
+				let push = script::Builder::new().push_slice(push).into_script();
+				scriptcode = Cow::Owned(find_and_delete(&scriptcode, push.as_bytes()));
 			}
 		}
+		scriptcode
+	}
+
+	fn check_sig_pre_tap(&mut self, sig: &[u8], pk: &[u8], scriptcode: &[u8]) -> Result<bool, ExecError> {
+		//TODO(stevenroose) somehow sigops limit should be checked somewhere
 
 		//TODO(stevenroose) the signature and pk encoding checks we use here
 		// might not be exactly identical to Core's
@@ -451,7 +482,7 @@ impl Exec {
 			return Err(ExecError::WitnessPubkeyType);
 		}
 
-		Ok(self.check_sig_ecdsa(sig, pk, &scriptcode))
+		Ok(self.check_sig_ecdsa(sig, pk, scriptcode))
 	}
 
 	fn check_sig_tap(&mut self, sig: &[u8], pk: &[u8]) -> Result<bool, ExecError> {
@@ -478,7 +509,10 @@ impl Exec {
 
 	fn check_sig(&mut self, sig: &[u8], pk: &[u8]) -> Result<bool, ExecError> {
 		match self.ctx {
-			ExecCtx::Legacy | ExecCtx::SegwitV0 => self.check_sig_pre_tap(sig, pk),
+			ExecCtx::Legacy | ExecCtx::SegwitV0 => {
+				let scriptcode = self.signed_script_code(&[sig]);
+				self.check_sig_pre_tap(sig, pk, &scriptcode)
+			}
 			ExecCtx::Tapscript => self.check_sig_tap(sig, pk),
 		}
 	}
@@ -497,6 +531,9 @@ impl Exec {
 		let instruction = match self.instructions.next() {
 			Some(Ok(i)) => i,
 			None => {
+				if !self.cond_stack.is_empty() {
+					return self.fail(ExecError::UnbalancedConditional);
+				}
 				let res = ExecutionResult::from_final_stack(self.stack.clone());
 				self.result = Some(res);
 				return Err(self.result.as_ref().unwrap())
@@ -999,10 +1036,70 @@ impl Exec {
 			}
 
 			OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
-				unimplemented!();
-			}
+				if self.ctx == ExecCtx::Tapscript {
+					return Err(ExecError::TapscriptCheckMultiSig);
+				}
 
-			_ => unimplemented!(),
+				// From the top: the key count, the keys, the signature count,
+				// the signatures and one extra element, the dummy.
+				let mut i = 1;
+				self.stack.needn(i)?;
+				let mut nb_keys = self.stack.topnum(-(i as isize), self.opt.require_minimal)?;
+				if nb_keys < 0 || nb_keys > MAX_PUBKEYS_PER_MULTISIG {
+					return Err(ExecError::PubkeyCount);
+				}
+				self.opcode_count += nb_keys as usize;
+				if self.opcode_count > MAX_OPS_PER_SCRIPT {
+					return Err(ExecError::OpCount);
+				}
+				i += 1;
+				let mut ikey = i;
+				i += nb_keys as usize;
+				self.stack.needn(i)?;
+
+				let mut nb_sigs = self.stack.topnum(-(i as isize), self.opt.require_minimal)?;
+				if nb_sigs < 0 || nb_sigs > nb_keys {
+					return Err(ExecError::SigCount);
+				}
+				i += 1;
+				let mut isig = i;
+				i += nb_sigs as usize;
+				self.stack.needn(i)?;
+
+				let sigs = (isig..i).map(|j| self.stack.top(-(j as isize)).map(|sig| &sig[..]))
+					.collect::<Result<Vec<_>, _>>()?;
+				let scriptcode = self.signed_script_code(&sigs);
+
+				let mut success = true;
+				while success && nb_sigs > 0 {
+					let sig = self.stack.top(-(isig as isize))?.clone();
+					let pk = self.stack.top(-(ikey as isize))?.clone();
+					if self.check_sig_pre_tap(&sig, &pk, &scriptcode)? {
+						isig += 1;
+						nb_sigs -= 1;
+					}
+					ikey += 1;
+					nb_keys -= 1;
+
+					// There are more signatures left than keys to match them.
+					if nb_sigs > nb_keys {
+						success = false;
+					}
+				}
+
+				if self.opt.verify_null_dummy && !self.stack.top(-(i as isize))?.is_empty() {
+					return Err(ExecError::SigNullDummy);
+				}
+				self.stack.popn(i).unwrap();
+
+				if op == OP_CHECKMULTISIGVERIFY && !success {
+					return Err(ExecError::CheckMultiSigVerify);
+				}
+				if op == OP_CHECKMULTISIG {
+					let ret = if success { item_true() } else { item_false() };
+					self.stack.push(ret);
+				}
+			}
 
 			// remainder
 			_ => return Err(ExecError::BadOpcode),
@@ -1036,11 +1133,63 @@ impl Exec {
 	}
 }
 
+/// Removes every occurrence of [pattern] that starts at an opcode boundary
+/// of [script], like Core's `FindAndDelete`.
+fn find_and_delete(script: &[u8], pattern: &[u8]) -> Vec<u8> {
+	let mut result = Vec::with_capacity(script.len());
+	let mut pos = 0;
+	loop {
+		while script[pos..].starts_with(pattern) {
+			pos += pattern.len();
+		}
+		let mut instructions = Script::from_bytes(&script[pos..]).instructions();
+		match instructions.next() {
+			Some(Ok(_)) => {
+				let next = script.len() - instructions.as_script().len();
+				result.extend_from_slice(&script[pos..next]);
+				pos = next;
+			}
+			_ => {
+				result.extend_from_slice(&script[pos..]);
+				return result;
+			}
+		}
+	}
+}
+
+/// Encodes a number as a minimal scriptint.
+fn scriptint_vec(num: i64) -> Vec<u8> {
+	let mut buf = [0u8; 8];
+	let len = script::write_scriptint(&mut buf, num);
+	buf[..len].to_vec()
+}
+
+/// Decodes a scriptint of at most [size] bytes (at most 8).
+///
+/// This is `CScriptNum`'s decoding; rust-bitcoin 0.31 only exposes it for
+/// 4-byte numbers.
 fn read_scriptint(item: &[u8], size: usize, minimal: bool) -> Result<i64, ExecError> {
-	script::read_scriptint_size(item, size, minimal).map_err(|e| match e {
-		script::ScriptIntError::NonMinimalPush => ExecError::MinimalData,
-		// only possible if size is 4 or lower
-		script::ScriptIntError::NumericOverflow => ExecError::ScriptIntNumericOverflow,
-		_ => unreachable!("not possible"),
-	})
-}
\ No newline at end of file
+	debug_assert!(size <= 8);
+	let last = match item.last() {
+		Some(last) => *last,
+		None => return Ok(0),
+	};
+	if item.len() > size {
+		return Err(ExecError::ScriptIntNumericOverflow);
+	}
+	// The most significant byte may only be 0x00 or 0x80 if the sign bit
+	// would otherwise clash with the preceding byte.
+	if minimal && last & 0x7f == 0 && (item.len() == 1 || item[item.len() - 2] & 0x80 == 0) {
+		return Err(ExecError::MinimalData);
+	}
+
+	let mut ret: i64 = 0;
+	for (i, byte) in item.iter().enumerate() {
+		ret |= (*byte as i64) << (8 * i);
+	}
+	if last & 0x80 != 0 {
+		ret &= !(0x80 << (8 * (item.len() - 1)));
+		ret = -ret;
+	}
+	Ok(ret)
+}
\ No newline at end of file
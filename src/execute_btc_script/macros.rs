@@ -1,7 +1,7 @@
 
 
 
-#[macro_export]
+#[allow(unused_macros)]
 macro_rules! or_else {
 	($e:expr, $($else:tt)+) => {
 		if let Some(v) = $e {
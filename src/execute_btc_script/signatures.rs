@@ -3,10 +3,10 @@
 use bitcoin::secp256k1::{self, PublicKey, XOnlyPublicKey};
 use bitcoin::sighash::{Annex, EcdsaSighashType, TapSighashType, Prevouts};
 
-use crate::*;
+use super::*;
 
 lazy_static::lazy_static! {
-	static ref SECP: secp256k1::Secp256k1<secp256k1::All> = secp256k1::Secp256k1::new();
+	pub(super) static ref SECP: secp256k1::Secp256k1<secp256k1::All> = secp256k1::Secp256k1::new();
 }
 
 impl Exec {
@@ -26,10 +26,12 @@ impl Exec {
 		}
 
 		let hashtype = *sig.last().unwrap();
-		let sig = match secp256k1::ecdsa::Signature::from_der(&sig[0..sig.len()-1]) {
+		let mut sig = match secp256k1::ecdsa::Signature::from_der(&sig[0..sig.len()-1]) {
 			Ok(s) => s,
 			Err(_) => return false,
 		};
+		// Core accepts high-S signatures by consensus, libsecp256k1 does not.
+		sig.normalize_s();
 
 		let sighash = if self.ctx == ExecCtx::SegwitV0 {
 			self.sighashcache.p2wsh_signature_hash(
@@ -64,10 +66,11 @@ impl Exec {
 			return Err(ExecError::SchnorrSigSize);
 		}
 
-		let pk = XOnlyPublicKey::from_slice(pk).expect("TODO(stevenroose) what to do here?");
+		// Core fails the signature check for keys that are not on the curve.
+		let pk = XOnlyPublicKey::from_slice(pk).map_err(|_| ExecError::SchnorrSig)?;
 		let (sig, hashtype) = if sig.len() == 65 {
 			let b = *sig.last().unwrap();
-			let sig = secp256k1::schnorr::Signature::from_slice(&sig[0..sig.len()-2])
+			let sig = secp256k1::schnorr::Signature::from_slice(&sig[0..64])
 				.map_err(|_| ExecError::SchnorrSig)?;
 
 			if b == TapSighashType::Default as u8 {
@@ -90,7 +93,7 @@ impl Exec {
 			annex.as_ref().map(|a| Annex::new(a).expect("we checked annex prefix before")),
 			Some((*leaf_hash, self.last_codeseparator_pos.unwrap_or(u32::MAX))),
 			hashtype,
-		).expect("TODO(stevenroose) seems to only happen if prevout index out of bound");
+		).map_err(|_| ExecError::SchnorrSigHashtype)?;
 
 		if SECP.verify_schnorr(&sig, &sighash.into(), &pk) != Ok(()) {
 			return Err(ExecError::SchnorrSig);
@@ -98,4 +101,4 @@ impl Exec {
 
 		Ok(())
 	}
-}
\ No newline at end of file
+}
\ No newline at end of file
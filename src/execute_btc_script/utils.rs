@@ -34,6 +34,10 @@ impl ConditionStack {
 		}
 	}
 
+	pub fn is_empty(&self) -> bool {
+		self.size == 0
+	}
+
 	pub fn all_true(&self) -> bool {
 		self.first_false_pos == Self::NO_FALSE
 	}
@@ -0,0 +1,384 @@
+#![warn(clippy::all)]
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::opcodes::{Class, ClassifyContext};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::secp256k1::{schnorr, XOnlyPublicKey};
+use bitcoin::sighash::{Annex, Prevouts, TapSighashType};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TAPROOT_ANNEX_PREFIX};
+use bitcoin::WitnessVersion;
+
+use super::*;
+use super::signatures::SECP;
+
+/// Size of a control block without any merkle path element.
+const TAPROOT_CONTROL_BASE_SIZE: usize = 33;
+
+/// Size of every merkle path element of a control block.
+const TAPROOT_CONTROL_NODE_SIZE: usize = 32;
+
+/// Maximum depth of a taproot script tree.
+const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+
+/// Script verification flags.
+///
+/// Equivalent to Bitcoin Core's `SCRIPT_VERIFY_*` flags, with the flags
+/// that only affect the interpreter living in [Options].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyFlags {
+	/// Evaluate P2SH subscripts (BIP 16).
+	pub p2sh: bool,
+	/// Evaluate segwit v0 programs (BIP 141).
+	pub witness: bool,
+	/// Evaluate taproot programs (BIPs 341 and 342).
+	pub taproot: bool,
+	/// Require the scriptSig to only push data.
+	pub sig_push_only: bool,
+	/// Require a single element on the stack after a legacy or P2SH spend.
+	/// Only applied together with [VerifyFlags::p2sh] and
+	/// [VerifyFlags::witness], like in Core.
+	pub clean_stack: bool,
+	/// Fail spends of witness programs of unknown versions instead of
+	/// letting them succeed.
+	pub discourage_upgradable_witness_program: bool,
+	/// Interpreter options used for every script of the spend.
+	pub options: Options,
+}
+
+impl VerifyFlags {
+	/// The consensus rules after the taproot soft fork.
+	pub fn consensus() -> VerifyFlags {
+		VerifyFlags {
+			p2sh: true,
+			witness: true,
+			taproot: true,
+			sig_push_only: false,
+			clean_stack: false,
+			discourage_upgradable_witness_program: false,
+			options: Options {
+				require_minimal: false,
+				verify_cltv: true,
+				verify_csv: true,
+				verify_minimal_if: false,
+				verify_null_dummy: true,
+				experimental: Experimental {
+					op_cat: false,
+				},
+			},
+		}
+	}
+}
+
+impl Default for VerifyFlags {
+	/// Core's standardness rules, with the default interpreter [Options].
+	fn default() -> Self {
+		VerifyFlags {
+			p2sh: true,
+			witness: true,
+			taproot: true,
+			sig_push_only: true,
+			clean_stack: true,
+			discourage_upgradable_witness_program: true,
+			options: Options::default(),
+		}
+	}
+}
+
+/// Verifies the spend of input [input_idx] of [tx], executing its scriptSig,
+/// the scriptPubKey of its prevout and, if any, its P2SH redeem script and
+/// witness program.
+///
+/// Equivalent to Bitcoin Core's `VerifyScript`. [prevouts] holds the output
+/// spent by every input of [tx], in input order.
+pub fn verify_input(
+	tx: &Transaction,
+	input_idx: usize,
+	prevouts: &[TxOut],
+	flags: &VerifyFlags,
+) -> Result<(), Error> {
+	if input_idx >= tx.input.len() {
+		return Err(Error::Other("input index out of range"));
+	}
+	if prevouts.len() != tx.input.len() {
+		return Err(Error::Other("number of prevouts does not match the inputs"));
+	}
+
+	let verifier = Verifier { tx, input_idx, prevouts, flags };
+	let script_sig = &tx.input[input_idx].script_sig;
+	let script_pubkey = &prevouts[input_idx].script_pubkey;
+	let witness = tx.input[input_idx].witness.to_vec();
+
+	if flags.sig_push_only && !script_sig.is_push_only() {
+		return Err(ExecError::SigPushOnly.into());
+	}
+
+	let stack = verifier.execute(ExecCtx::Legacy, script_sig, Vec::new(), None, None)?;
+	let p2sh_stack = stack.clone();
+	let mut stack = verifier.execute(ExecCtx::Legacy, script_pubkey, stack, None, None)?;
+	require_true(&stack)?;
+
+	let mut had_witness = false;
+	if flags.witness {
+		if let Some((version, program)) = witness_program(script_pubkey) {
+			had_witness = true;
+			if !script_sig.is_empty() {
+				// The scriptSig must be empty, or we introduce malleability.
+				return Err(ExecError::WitnessMalleated.into());
+			}
+			verifier.verify_witness_program(version, program, witness.clone(), false)?;
+			// Only the witness program was checked; keep the single true
+			// element it left behind for the clean stack check.
+			stack.truncate(1);
+		}
+	}
+
+	if flags.p2sh && script_pubkey.is_p2sh() {
+		// The scriptSig must be literals only, or the redeem script could
+		// be computed instead of committed to.
+		if !script_sig.is_push_only() {
+			return Err(ExecError::SigPushOnly.into());
+		}
+
+		stack = p2sh_stack;
+		let redeem_script = ScriptBuf::from(stack.pop().expect("the scriptPubKey hashed the top element"));
+		stack = verifier.execute(ExecCtx::Legacy, &redeem_script, stack, None, None)?;
+		require_true(&stack)?;
+
+		if flags.witness {
+			if let Some((version, program)) = witness_program(&redeem_script) {
+				had_witness = true;
+				// The scriptSig must be exactly the push of the redeem script.
+				let push = PushBytesBuf::try_from(redeem_script.to_bytes()).expect("stack items are at most 520 bytes");
+				if *script_sig != Builder::new().push_slice(push).into_script() {
+					return Err(ExecError::WitnessMalleatedP2SH.into());
+				}
+				verifier.verify_witness_program(version, program, witness.clone(), true)?;
+				stack.truncate(1);
+			}
+		}
+	}
+
+	if flags.clean_stack && flags.p2sh && flags.witness && stack.len() != 1 {
+		return Err(ExecError::CleanStack.into());
+	}
+
+	if flags.witness && !had_witness && !witness.is_empty() {
+		// Non-witness spends must not carry a witness.
+		return Err(ExecError::WitnessUnexpected.into());
+	}
+
+	Ok(())
+}
+
+/// The version and program of [script] if it is a witness program.
+fn witness_program(script: &Script) -> Option<(WitnessVersion, &[u8])> {
+	if script.is_witness_program() {
+		Some((script.witness_version()?, &script.as_bytes()[2..]))
+	} else {
+		None
+	}
+}
+
+/// Fails unless the top of [stack] is true.
+fn require_true(stack: &[Vec<u8>]) -> Result<(), ExecError> {
+	match stack.last() {
+		Some(top) if script::read_scriptbool(top) => Ok(()),
+		_ => Err(ExecError::EvalFalse),
+	}
+}
+
+struct Verifier<'a> {
+	tx: &'a Transaction,
+	input_idx: usize,
+	prevouts: &'a [TxOut],
+	flags: &'a VerifyFlags,
+}
+
+impl<'a> Verifier<'a> {
+	/// Executes [script] on [stack] and returns the final stack.
+	///
+	/// Tapscripts also get the serialized size of the whole witness, which
+	/// seeds their validation weight budget.
+	fn execute(
+		&self,
+		ctx: ExecCtx,
+		script: &Script,
+		stack: Vec<Vec<u8>>,
+		taproot_annex_scriptleaf: Option<(TapLeafHash, Option<Vec<u8>>)>,
+		witness_size: Option<usize>,
+	) -> Result<Vec<Vec<u8>>, Error> {
+		if ctx != ExecCtx::Tapscript && script.len() > MAX_SCRIPT_SIZE {
+			return Err(ExecError::ScriptSize.into());
+		}
+
+		let tx = TxTemplate {
+			tx: self.tx.clone(),
+			prevouts: self.prevouts.to_vec(),
+			input_idx: self.input_idx,
+			taproot_annex_scriptleaf,
+		};
+		let mut exec = Exec::new(ctx, self.flags.options.clone(), tx, script.to_owned(), stack)?;
+		if let Some(witness_size) = witness_size {
+			exec.set_witness_size(witness_size);
+		}
+		while exec.exec_next().is_ok() {}
+		let result = exec.result().expect("execution finished");
+		match result.error {
+			Some(ref err) => Err(err.clone().into()),
+			None => Ok(result.final_stack.clone()),
+		}
+	}
+
+	/// Executes a segwit v0 or tapscript witness script, which implicitly
+	/// requires a clean stack.
+	fn execute_witness_script(
+		&self,
+		ctx: ExecCtx,
+		script: &Script,
+		stack: Vec<Vec<u8>>,
+		taproot_annex_scriptleaf: Option<(TapLeafHash, Option<Vec<u8>>)>,
+		witness_size: Option<usize>,
+	) -> Result<(), Error> {
+		if ctx == ExecCtx::Tapscript {
+			// Any OP_SUCCESSx makes the spend valid without executing it.
+			for instruction in script.instructions() {
+				match instruction {
+					Err(_) => return Err(ExecError::BadOpcode.into()),
+					Ok(Instruction::Op(op)) if self.is_op_success(op) => return Ok(()),
+					Ok(_) => {},
+				}
+			}
+
+			if stack.len() > MAX_STACK_SIZE {
+				return Err(ExecError::StackSize.into());
+			}
+		}
+
+		if stack.iter().any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+			return Err(ExecError::PushSize.into());
+		}
+
+		let stack = self.execute(ctx, script, stack, taproot_annex_scriptleaf, witness_size)?;
+		if stack.len() != 1 {
+			return Err(ExecError::CleanStack.into());
+		}
+		require_true(&stack)?;
+		Ok(())
+	}
+
+	fn is_op_success(&self, op: Opcode) -> bool {
+		if op == OP_CAT && self.flags.options.experimental.op_cat {
+			return false;
+		}
+		op.classify(ClassifyContext::TapScript) == Class::SuccessOp
+	}
+
+	fn verify_witness_program(
+		&self,
+		version: WitnessVersion,
+		program: &[u8],
+		mut witness: Vec<Vec<u8>>,
+		is_p2sh: bool,
+	) -> Result<(), Error> {
+		match version {
+			WitnessVersion::V0 if program.len() == 32 => {
+				// P2WSH: the last witness element is the script.
+				let script = ScriptBuf::from(witness.pop().ok_or(ExecError::WitnessProgramWitnessEmpty)?);
+				if sha256::Hash::hash(script.as_bytes()).as_byte_array()[..] != *program {
+					return Err(ExecError::WitnessProgramMismatch.into());
+				}
+				self.execute_witness_script(ExecCtx::SegwitV0, &script, witness, None, None)
+			}
+			WitnessVersion::V0 if program.len() == 20 => {
+				// P2WPKH: a signature and a public key.
+				if witness.len() != 2 {
+					return Err(ExecError::WitnessProgramMismatch.into());
+				}
+				let pubkey_hash = <&script::PushBytes>::try_from(program).expect("20 bytes");
+				let script = Builder::new()
+					.push_opcode(OP_DUP)
+					.push_opcode(OP_HASH160)
+					.push_slice(pubkey_hash)
+					.push_opcode(OP_EQUALVERIFY)
+					.push_opcode(OP_CHECKSIG)
+					.into_script();
+				self.execute_witness_script(ExecCtx::SegwitV0, &script, witness, None, None)
+			}
+			WitnessVersion::V0 => Err(ExecError::WitnessProgramWrongLength.into()),
+			WitnessVersion::V1 if program.len() == 32 && !is_p2sh && self.flags.taproot => {
+				self.verify_taproot(program, witness)
+			}
+			_ if self.flags.discourage_upgradable_witness_program => {
+				Err(ExecError::DiscourageUpgradableWitnessProgram.into())
+			}
+			// Higher versions are reserved for future soft forks.
+			_ => Ok(()),
+		}
+	}
+
+	fn verify_taproot(&self, program: &[u8], mut witness: Vec<Vec<u8>>) -> Result<(), Error> {
+		if witness.is_empty() {
+			return Err(ExecError::WitnessProgramWitnessEmpty.into());
+		}
+		// BIP 342 budgets the whole witness, script, control block and annex included.
+		let witness_size = Encodable::consensus_encode(&witness, &mut io::sink()).expect("writing to a sink does not fail");
+		let annex = if witness.len() >= 2 && witness.last().unwrap().first() == Some(&TAPROOT_ANNEX_PREFIX) {
+			witness.pop()
+		} else {
+			None
+		};
+
+		if witness.len() == 1 {
+			// Key path spend: the program is the key.
+			return Ok(self.verify_key_spend(program, &witness[0], annex.as_deref())?);
+		}
+
+		// Script path spend: the script and its control block.
+		let control = witness.pop().unwrap();
+		let script = ScriptBuf::from(witness.pop().unwrap());
+		if control.len() < TAPROOT_CONTROL_BASE_SIZE
+			|| control.len() > TAPROOT_CONTROL_BASE_SIZE + TAPROOT_CONTROL_MAX_NODE_COUNT * TAPROOT_CONTROL_NODE_SIZE
+			|| !(control.len() - TAPROOT_CONTROL_BASE_SIZE).is_multiple_of(TAPROOT_CONTROL_NODE_SIZE)
+		{
+			return Err(ExecError::TaprootWrongControlSize.into());
+		}
+		let control_block = ControlBlock::decode(&control).map_err(|_| ExecError::WitnessProgramMismatch)?;
+		let output_key = XOnlyPublicKey::from_slice(program).map_err(|_| ExecError::WitnessProgramMismatch)?;
+		if !control_block.verify_taproot_commitment(&SECP, output_key, &script) {
+			return Err(ExecError::WitnessProgramMismatch.into());
+		}
+
+		if control_block.leaf_version != LeafVersion::TapScript {
+			// Unknown leaf versions are reserved for future soft forks.
+			return Ok(());
+		}
+		let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+		self.execute_witness_script(ExecCtx::Tapscript, &script, witness, Some((leaf_hash, annex)), Some(witness_size))
+	}
+
+	fn verify_key_spend(&self, program: &[u8], sig: &[u8], annex: Option<&[u8]>) -> Result<(), ExecError> {
+		let (sig, hashtype) = match sig.len() {
+			64 => (sig, TapSighashType::Default),
+			65 if sig[64] != TapSighashType::Default as u8 => {
+				let hashtype = TapSighashType::from_consensus_u8(sig[64])
+					.map_err(|_| ExecError::SchnorrSigHashtype)?;
+				(&sig[0..64], hashtype)
+			}
+			65 => return Err(ExecError::SchnorrSigHashtype),
+			_ => return Err(ExecError::SchnorrSigSize),
+		};
+		let sig = schnorr::Signature::from_slice(sig).map_err(|_| ExecError::SchnorrSig)?;
+		let key = XOnlyPublicKey::from_slice(program).map_err(|_| ExecError::SchnorrSig)?;
+
+		let annex = annex.map(|annex| Annex::new(annex).expect("we checked the annex prefix"));
+		let sighash = SighashCache::new(self.tx).taproot_signature_hash(
+			self.input_idx,
+			&Prevouts::All(self.prevouts),
+			annex,
+			None,
+			hashtype,
+		).map_err(|_| ExecError::SchnorrSigHashtype)?;
+
+		SECP.verify_schnorr(&sig, &sighash.into(), &key).map_err(|_| ExecError::SchnorrSig)
+	}
+}
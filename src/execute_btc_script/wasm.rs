@@ -1,9 +1,8 @@
 
 
 use bitcoin::{ScriptBuf, Transaction};
-use bitcoin::hashes::Hash;
 use bitcoin::hex::{DisplayHex, FromHex};
-use bitcoin::taproot::TapLeafHash;
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
 use serde_json::json;
 use wasm_bindgen::prelude::*;
 
@@ -54,6 +53,7 @@ pub fn run_script(script_hex: &str, script_witness: Box<[JsValue]>) -> Result<Js
 		ret
 	};
 
+	let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
 	let mut exec = Exec::new(
 		ExecCtx::Tapscript,
 		Options::default(),
@@ -66,7 +66,7 @@ pub fn run_script(script_hex: &str, script_witness: Box<[JsValue]>) -> Result<Js
 			},
 			prevouts: vec![],
 			input_idx: 0,
-			taproot_annex_scriptleaf: Some((TapLeafHash::all_zeros(), None)),
+			taproot_annex_scriptleaf: Some((leaf_hash, None)),
 		},
 		script,
 		witness,
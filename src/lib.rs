@@ -2,9 +2,17 @@ mod data_encoding;
 mod zk_integration;
 mod circuit_logic;
 mod challenge_response;
+mod scripts;
+mod transactions;
+
+/// Bitcoin Script interpreter, vendored from the bitcoin-scriptexec crate.
+#[path = "execute_btc_script/lib1.rs"]
+pub mod execute_btc_script;
 
 // Re-export main components if needed
 pub use data_encoding::*;
 pub use zk_integration::*;
 pub use circuit_logic::*;
 pub use challenge_response::*;
+pub use scripts::*;
+pub use transactions::*;
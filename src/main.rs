@@ -1,12 +1,7 @@
-mod data_encoding;
-mod zk_integration;
-mod circuit_logic;
-mod challenge_response;
-
-use data_encoding::bristol_encoder::BristolEncoder;
-use zk_integration::zk_proofs::ZkProofs;
-use circuit_logic::{BinaryCircuit, LogicGate, GateType};
-use challenge_response::protocol::{ChallengeResponseProtocol, Challenge, Response};
+use bitvm::bristol_encoder::{BristolEncoder, BitcoinTransaction};
+use bitvm::CircuitBuilder;
+use bitvm::merkle::TraceTree;
+use bitvm::protocol::{ChallengeResponseProtocol, Response};
 
 fn main() {
     println!("Initializing BitVM System");
@@ -17,22 +12,28 @@ fn main() {
     // Encoding the transaction for Bristol Circuit
     let encoder = BristolEncoder::new();
     let encoded_tx = encoder.encode_transaction(&dummy_tx);
+    println!("Encoded transaction: {} bytes", encoded_tx.len());
 
     // Setting up the binary circuit for transaction verification
-    let mut circuit = BinaryCircuit::new();
+    let mut builder = CircuitBuilder::new();
     // Example: Add a NAND gate to the circuit
-    let nand_gate = LogicGate::new(GateType::NAND, vec![true, false]);
-    circuit.add_gate(nand_gate);
+    let a = builder.input();
+    let b = builder.input();
+    let nand = builder.nand(a, b);
+    builder.output(nand);
+    let circuit = builder.build();
+    let inputs = [true, false];
 
-    // Generate zk-SNARKs/zk-STARKs proofs for the circuit
-    let zk_proofs = ZkProofs::new();
-    let proof = zk_proofs.generate_proof(&circuit);
+    // Generating zk proofs needs a compiled RISC Zero guest, see `ZkProofs`.
 
     // Challenge-response protocol (placeholder for demonstration)
-    let challenge_protocol = ChallengeResponseProtocol::new();
-    let challenge = challenge_protocol.issue_challenge();
-    let response = Response { /* ... */ };
-    let is_valid = challenge_protocol.process_response(&response);
+    let mut challenge_protocol = ChallengeResponseProtocol::new();
+    let trace = circuit.evaluate(&inputs).expect("circuit evaluates");
+    let tree = TraceTree::new(&trace);
+    challenge_protocol.commit_trace(tree.root());
+    let challenge = challenge_protocol.issue_challenge(&circuit, 1);
+    let response = Response::new(&challenge, &circuit, &tree).expect("challenged gates exist");
+    let is_valid = challenge_protocol.process_response(&response, &circuit);
 
     println!("Challenge response valid: {}", is_valid);
     println!("BitVM System Initialized Successfully");
@@ -43,11 +44,7 @@ fn create_dummy_transaction() -> BitcoinTransaction {
     // Implementation of dummy transaction creation
     // This should include fields representative of a real Bitcoin transaction
     BitcoinTransaction {
-        // Dummy transaction data
+        inputs: vec![],
+        outputs: vec![],
     }
 }
-
-// Placeholder struct definitions for BitcoinTransaction
-struct BitcoinTransaction {
-    // Fields representative of a Bitcoin transaction
-}
@@ -0,0 +1,125 @@
+//! Hashlock commitments to wire values.
+//!
+//! The prover commits to a wire by publishing the HASH160 of two preimages,
+//! one per bit value. Revealing a preimage opens the wire to that bit, and
+//! revealing both is an equivocation. Preimages are derived from a secret
+//! seed, so the prover only has to keep the seed.
+
+use bitcoin::hashes::{hash160, sha256, Hash, HashEngine};
+use bitcoin::opcodes::all::*;
+use bitcoin::script::{Builder, ScriptBuf};
+
+use crate::binary_circuit::BinaryCircuit;
+use crate::execute_btc_script::{Error, ExecError};
+use crate::logic_gate::WireId;
+use crate::scripts::run::run_tapscript;
+
+/// Length of a wire preimage in bytes.
+pub const PREIMAGE_LEN: usize = 20;
+
+/// Secret the prover derives all wire preimages from.
+#[derive(Clone)]
+pub struct CommitmentKeys {
+    seed: [u8; 32],
+}
+
+impl CommitmentKeys {
+    pub fn from_seed(seed: &[u8]) -> Self {
+        CommitmentKeys { seed: sha256::Hash::hash(seed).to_byte_array() }
+    }
+
+    /// Preimage that opens `wire` to `value`.
+    pub fn preimage(&self, wire: WireId, value: bool) -> [u8; PREIMAGE_LEN] {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.seed);
+        engine.input(&(wire.0 as u64).to_be_bytes());
+        engine.input(&[value as u8]);
+        let digest = sha256::Hash::from_engine(engine);
+        let mut preimage = [0; PREIMAGE_LEN];
+        preimage.copy_from_slice(&digest[..PREIMAGE_LEN]);
+        preimage
+    }
+
+    pub fn commitment(&self, wire: WireId) -> WireCommitment {
+        WireCommitment {
+            wire,
+            hash0: hash160::Hash::hash(&self.preimage(wire, false)),
+            hash1: hash160::Hash::hash(&self.preimage(wire, true)),
+        }
+    }
+
+    /// Commitments to every wire of `circuit`, indexed by wire.
+    pub fn commit_circuit(&self, circuit: &BinaryCircuit) -> Vec<WireCommitment> {
+        (0..circuit.wire_count()).map(|wire| self.commitment(WireId(wire))).collect()
+    }
+
+    /// Preimages opening each wire to its value in `trace`, indexed by wire.
+    pub fn reveal_trace(&self, trace: &[bool]) -> Vec<[u8; PREIMAGE_LEN]> {
+        trace.iter().enumerate().map(|(wire, &value)| self.preimage(WireId(wire), value)).collect()
+    }
+}
+
+/// Public hashlocks of one wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WireCommitment {
+    pub wire: WireId,
+    pub hash0: hash160::Hash,
+    pub hash1: hash160::Hash,
+}
+
+impl WireCommitment {
+    pub fn hash(&self, value: bool) -> hash160::Hash {
+        if value {
+            self.hash1
+        } else {
+            self.hash0
+        }
+    }
+
+    /// Bit that `preimage` opens the wire to, checked without Script.
+    pub fn open(&self, preimage: &[u8]) -> Option<bool> {
+        let hash = hash160::Hash::hash(preimage);
+        match (hash == self.hash0, hash == self.hash1) {
+            (true, _) => Some(false),
+            (_, true) => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Appends the opening snippet: it replaces the preimage on top of the
+    /// stack by the committed bit and fails for any other value.
+    ///
+    /// `OP_DUP OP_HASH160 <h0> OP_EQUAL OP_IF OP_DROP 0 OP_ELSE OP_HASH160 <h1> OP_EQUALVERIFY 1 OP_ENDIF`
+    pub fn push_open(&self, builder: Builder) -> Builder {
+        builder
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice(self.hash0.as_byte_array())
+            .push_opcode(OP_EQUAL)
+            .push_opcode(OP_IF)
+            .push_opcode(OP_DROP)
+            .push_int(0)
+            .push_opcode(OP_ELSE)
+            .push_opcode(OP_HASH160)
+            .push_slice(self.hash1.as_byte_array())
+            .push_opcode(OP_EQUALVERIFY)
+            .push_int(1)
+            .push_opcode(OP_ENDIF)
+    }
+
+    pub fn open_script(&self) -> ScriptBuf {
+        self.push_open(Builder::new()).into_script()
+    }
+
+    /// Runs the opening snippet on `preimage` as a Tapscript and returns the bit it leaves.
+    pub fn verify_opening(&self, preimage: &[u8]) -> Result<bool, Error> {
+        let run = run_tapscript(&self.open_script(), vec![preimage.to_vec()])?;
+        if let Some(error) = run.result.error {
+            return Err(error.into());
+        }
+        match run.final_stack() {
+            [bit] => Ok(!bit.is_empty()),
+            _ => Err(ExecError::InvalidStackOperation.into()),
+        }
+    }
+}
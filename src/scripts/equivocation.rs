@@ -0,0 +1,97 @@
+//! Detection and punishment of wires opened to both bits.
+//!
+//! A prover that reveals both preimages of a wire has contradicted itself.
+//! The two preimages satisfy the wire's punishment script, which lets the
+//! verifier take the funds with its signature:
+//!
+//! `OP_HASH160 <h0> OP_EQUALVERIFY OP_HASH160 <h1> OP_EQUALVERIFY <verifier> OP_CHECKSIG`
+//!
+//! `TxGraph` locks the claim posted by `assert` with one such leaf per wire.
+
+use std::collections::HashMap;
+
+use bitcoin::hashes::{hash160, Hash};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::opcodes::all::*;
+use bitcoin::script::{Builder, ScriptBuf};
+
+use crate::logic_gate::WireId;
+use crate::scripts::bit_commitment::WireCommitment;
+
+/// Both openings of one wire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equivocation {
+    pub wire: WireId,
+    pub preimage0: Vec<u8>,
+    pub preimage1: Vec<u8>,
+}
+
+impl Equivocation {
+    /// Witness for the wire's punishment script, given the verifier's signature.
+    pub fn witness(&self, signature: &[u8]) -> Vec<Vec<u8>> {
+        vec![signature.to_vec(), self.preimage1.clone(), self.preimage0.clone()]
+    }
+}
+
+/// Script the verifier can spend with both preimages of `commitment`.
+pub fn punishment_script(commitment: &WireCommitment, verifier: &XOnlyPublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_opcode(OP_HASH160)
+        .push_slice(commitment.hash0.as_byte_array())
+        .push_opcode(OP_EQUALVERIFY)
+        .push_opcode(OP_HASH160)
+        .push_slice(commitment.hash1.as_byte_array())
+        .push_opcode(OP_EQUALVERIFY)
+        .push_x_only_key(verifier)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+/// Collects revealed preimages of a circuit's commitments.
+#[derive(Debug, Clone)]
+pub struct EquivocationWatcher {
+    /// Wire and bit opened by each hashlock.
+    locks: HashMap<hash160::Hash, (WireId, bool)>,
+    /// First preimage seen for each wire and bit.
+    revealed: HashMap<(WireId, bool), Vec<u8>>,
+}
+
+impl EquivocationWatcher {
+    /// Watches the commitments of a circuit, as from `CommitmentKeys::commit_circuit`.
+    pub fn new(commitments: &[WireCommitment]) -> Self {
+        let mut locks = HashMap::new();
+        for commitment in commitments {
+            locks.insert(commitment.hash0, (commitment.wire, false));
+            locks.insert(commitment.hash1, (commitment.wire, true));
+        }
+        EquivocationWatcher { locks, revealed: HashMap::new() }
+    }
+
+    /// Records `preimage` if it opens a watched wire and returns the
+    /// equivocation if the other bit of that wire was opened before.
+    pub fn observe(&mut self, preimage: &[u8]) -> Option<Equivocation> {
+        let &(wire, bit) = self.locks.get(&hash160::Hash::hash(preimage))?;
+        self.revealed.entry((wire, bit)).or_insert_with(|| preimage.to_vec());
+        let other = self.revealed.get(&(wire, !bit))?;
+        let (preimage0, preimage1) = if bit {
+            (other.clone(), preimage.to_vec())
+        } else {
+            (preimage.to_vec(), other.clone())
+        };
+        Some(Equivocation { wire, preimage0, preimage1 })
+    }
+
+    /// Observes every item of a script witness, returning the equivocations found.
+    pub fn observe_witness(&mut self, witness: &[Vec<u8>]) -> Vec<Equivocation> {
+        witness.iter().filter_map(|item| self.observe(item)).collect()
+    }
+
+    /// Bit a wire has been opened to, if it has been opened to exactly one bit.
+    pub fn opened(&self, wire: WireId) -> Option<bool> {
+        match (self.revealed.contains_key(&(wire, false)), self.revealed.contains_key(&(wire, true))) {
+            (true, false) => Some(false),
+            (false, true) => Some(true),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Tapscript leaves checking single gates against the wire commitments.
+//!
+//! The leaves of a gate with inputs `a`, `b` and output `c` expect the
+//! preimages of `c`, `b` and `a` on the witness stack, `a` on top, and open
+//! each commitment; a preimage that opens neither bit makes them fail. The
+//! check leaf then leaves true only if `c` is the gate applied to `a` and
+//! `b`, the violation leaf only if it is not.
+//!
+//! `ChallengeTree` commits to the violation leaves, so a gate can only be
+//! spent by openings that contradict it. The check leaves are for judging
+//! an opening off chain.
+
+use std::fmt;
+
+use bitcoin::opcodes::all::*;
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::{Builder, ScriptBuf};
+
+use crate::binary_circuit::BinaryCircuit;
+use crate::logic_gate::{GateId, GateType, WireId};
+use crate::scripts::bit_commitment::{CommitmentKeys, WireCommitment};
+
+/// Error raised while generating gate scripts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GateScriptError {
+    /// Only two-input NAND, AND and XOR gates have a script.
+    UnsupportedGate { gate: GateId, gate_type: GateType, operands: usize },
+    /// No commitment was given for a wire the gate uses.
+    MissingCommitment { gate: GateId, wire: WireId },
+}
+
+impl fmt::Display for GateScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GateScriptError::UnsupportedGate { gate, gate_type, operands } => {
+                write!(f, "gate {} ({:?} with {} operands) has no script", gate, gate_type, operands)
+            }
+            GateScriptError::MissingCommitment { gate, wire } => {
+                write!(f, "gate {} uses wire {} which has no commitment", gate, wire)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GateScriptError {}
+
+/// Tapscript leaf of one gate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GateScript {
+    pub gate: GateId,
+    pub gate_type: GateType,
+    pub inputs: [WireId; 2],
+    pub output: WireId,
+    /// Succeeds if the openings satisfy the gate.
+    pub script: ScriptBuf,
+    /// Succeeds if the openings violate the gate.
+    pub violation: ScriptBuf,
+}
+
+impl GateScript {
+    /// Witness opening the gate's wires to their values in `trace`, for either leaf.
+    pub fn witness(&self, keys: &CommitmentKeys, trace: &[bool]) -> Vec<Vec<u8>> {
+        let preimage = |wire: WireId| keys.preimage(wire, trace[wire.0]);
+        opening_witness(&preimage(self.inputs[0]), &preimage(self.inputs[1]), &preimage(self.output))
+    }
+}
+
+/// Orders the preimages of the inputs `a`, `b` and the output as a gate leaf expects them.
+pub fn opening_witness(a: &[u8], b: &[u8], output: &[u8]) -> Vec<Vec<u8>> {
+    vec![output.to_vec(), b.to_vec(), a.to_vec()]
+}
+
+/// Generates the leaf of every gate of `circuit`; `commitments` is indexed by wire.
+pub fn gate_scripts(circuit: &BinaryCircuit, commitments: &[WireCommitment]) -> Result<Vec<GateScript>, GateScriptError> {
+    circuit.gates().iter().enumerate()
+        .map(|(index, gate)| {
+            let id = GateId(index);
+            let unsupported = GateScriptError::UnsupportedGate { gate: id, gate_type: gate.gate_type, operands: gate.inputs.len() };
+            let inputs = match (gate.gate_type, &gate.inputs[..]) {
+                (GateType::NAND | GateType::AND | GateType::XOR, &[a, b]) => [a, b],
+                _ => return Err(unsupported),
+            };
+            let commitment = |wire: WireId| {
+                commitments.get(wire.0).ok_or(GateScriptError::MissingCommitment { gate: id, wire })
+            };
+            let (a, b, output) = (commitment(inputs[0])?, commitment(inputs[1])?, commitment(gate.output)?);
+            let script = gate_script(gate.gate_type, a, b, output).expect("gate type has a script");
+            let violation = violation_script(gate.gate_type, a, b, output).expect("gate type has a script");
+            Ok(GateScript { gate: id, gate_type: gate.gate_type, inputs, output: gate.output, script, violation })
+        })
+        .collect()
+}
+
+/// Leaf checking `output = gate_type(a, b)`, or `None` for gates other than NAND, AND and XOR.
+pub fn gate_script(gate_type: GateType, a: &WireCommitment, b: &WireCommitment, output: &WireCommitment) -> Option<ScriptBuf> {
+    gate_leaf(gate_type, a, b, output, OP_EQUAL)
+}
+
+/// Leaf checking `output != gate_type(a, b)`, or `None` for gates other than NAND, AND and XOR.
+pub fn violation_script(gate_type: GateType, a: &WireCommitment, b: &WireCommitment, output: &WireCommitment) -> Option<ScriptBuf> {
+    gate_leaf(gate_type, a, b, output, OP_NUMNOTEQUAL)
+}
+
+/// Opens the three wires and compares the output with the gate's result using `compare`.
+fn gate_leaf(
+    gate_type: GateType,
+    a: &WireCommitment,
+    b: &WireCommitment,
+    output: &WireCommitment,
+    compare: Opcode,
+) -> Option<ScriptBuf> {
+    let relation: &[_] = match gate_type {
+        GateType::NAND => &[OP_BOOLAND, OP_NOT],
+        GateType::AND => &[OP_BOOLAND],
+        GateType::XOR => &[OP_NUMNOTEQUAL],
+        _ => return None,
+    };
+    let mut builder = a.push_open(Builder::new()).push_opcode(OP_TOALTSTACK);
+    builder = b.push_open(builder).push_opcode(OP_TOALTSTACK);
+    builder = output.push_open(builder).push_opcode(OP_FROMALTSTACK).push_opcode(OP_FROMALTSTACK);
+    for &opcode in relation {
+        builder = builder.push_opcode(opcode);
+    }
+    Some(builder.push_opcode(compare).into_script())
+}
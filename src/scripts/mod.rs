@@ -0,0 +1,14 @@
+pub mod run;
+pub mod bit_commitment;
+pub mod gate_scripts;
+pub mod taproot;
+pub mod equivocation;
+pub mod timelocks;
+pub mod winternitz;
+
+pub use run::{run_script, run_tapscript, ScriptRun};
+pub use bit_commitment::{CommitmentKeys, WireCommitment};
+pub use gate_scripts::{gate_scripts, GateScript, GateScriptError};
+pub use taproot::{ChallengeTree, TreeError, TreeShape};
+pub use equivocation::{Equivocation, EquivocationWatcher};
+pub use winternitz::{ChainHash, WinternitzParams, WinternitzPublicKey, WinternitzSecret, WinternitzSignature};
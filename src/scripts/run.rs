@@ -0,0 +1,64 @@
+//! Running generated scripts through the `execute_btc_script` interpreter.
+
+use bitcoin::absolute::LockTime;
+use bitcoin::script::{Script, ScriptBuf};
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use bitcoin::transaction::Version;
+use bitcoin::Transaction;
+
+use crate::execute_btc_script::{Error, Exec, ExecCtx, ExecStats, ExecutionResult, Options, TxTemplate};
+
+/// Outcome of running a script to completion.
+#[derive(Debug, Clone)]
+pub struct ScriptRun {
+    pub result: ExecutionResult,
+    pub stats: ExecStats,
+}
+
+impl ScriptRun {
+    /// Whether the script ran without error and left exactly `1` on the stack.
+    pub fn success(&self) -> bool {
+        self.result.success
+    }
+
+    pub fn final_stack(&self) -> &[Vec<u8>] {
+        &self.result.final_stack
+    }
+}
+
+/// Executes `script` on `witness`, the last witness item being the top of the stack.
+pub fn run_script(
+    ctx: ExecCtx,
+    options: Options,
+    tx: TxTemplate,
+    script: ScriptBuf,
+    witness: Vec<Vec<u8>>,
+) -> Result<ScriptRun, Error> {
+    let mut exec = Exec::new(ctx, options, tx, script, witness)?;
+    while exec.exec_next().is_ok() {}
+    Ok(ScriptRun {
+        result: exec.result().expect("execution finished").clone(),
+        stats: exec.stats().clone(),
+    })
+}
+
+/// Executes a Tapscript leaf with default options outside of any real spend.
+pub fn run_tapscript(script: &Script, witness: Vec<Vec<u8>>) -> Result<ScriptRun, Error> {
+    run_script(ExecCtx::Tapscript, Options::default(), leaf_template(script), script.to_owned(), witness)
+}
+
+/// Transaction context for running `script` as a Tapscript leaf without
+/// inputs; enough for scripts that check no signatures or timelocks.
+pub fn leaf_template(script: &Script) -> TxTemplate {
+    TxTemplate {
+        tx: Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        },
+        prevouts: vec![],
+        input_idx: 0,
+        taproot_annex_scriptleaf: Some((TapLeafHash::from_script(script, LeafVersion::TapScript), None)),
+    }
+}
@@ -0,0 +1,205 @@
+//! Taproot output holding the gate leaves of a whole circuit.
+//!
+//! The internal key is the BIP 341 NUMS point, so the output can only be
+//! spent through a script: the violation leaf of every gate, a cooperative
+//! leaf that needs signatures of both the prover and the verifier, and a
+//! timeout leaf that lets the prover alone reclaim the funds once nobody
+//! disproved a gate in time.
+
+use std::fmt;
+
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::opcodes::all::*;
+use bitcoin::script::{Builder, ScriptBuf};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::sighash::{self, Prevouts, SighashCache, TapSighash, TapSighashType};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder, TaprootBuilderError, TaprootSpendInfo};
+use bitcoin::{Transaction, TxOut};
+
+use crate::binary_circuit::BinaryCircuit;
+use crate::execute_btc_script::TxTemplate;
+use crate::logic_gate::GateId;
+use crate::scripts::gate_scripts::GateScript;
+use crate::scripts::timelocks::relative_timeout_script;
+
+/// x-only key with no known discrete logarithm, from BIP 341.
+const NUMS_KEY: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+pub fn unspendable_internal_key() -> XOnlyPublicKey {
+    XOnlyPublicKey::from_slice(&NUMS_KEY).expect("NUMS point is a valid key")
+}
+
+/// `<prover> OP_CHECKSIGVERIFY <verifier> OP_CHECKSIG`, spent with the
+/// verifier's signature below the prover's.
+pub fn cooperative_script(prover: &XOnlyPublicKey, verifier: &XOnlyPublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_x_only_key(prover)
+        .push_opcode(OP_CHECKSIGVERIFY)
+        .push_x_only_key(verifier)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+/// How leaves are placed in the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeShape {
+    /// All leaves at (nearly) the same depth.
+    Balanced,
+    /// Huffman tree over one weight per gate leaf, in gate order; likely
+    /// leaves get shorter control blocks. The cooperative and timeout
+    /// leaves get the largest gate weight.
+    Weighted(Vec<u32>),
+}
+
+/// Error raised while assembling the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeError {
+    /// A gate of the circuit has no leaf.
+    MissingLeaf(GateId),
+    /// A leaf does not belong to a gate of the circuit, repeats one or has another gate type.
+    UnexpectedLeaf(GateId),
+    /// The number of weights differs from the number of gates.
+    WeightCount { expected: usize, found: usize },
+    Taproot(TaprootBuilderError),
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TreeError::MissingLeaf(gate) => write!(f, "gate {} has no leaf", gate),
+            TreeError::UnexpectedLeaf(gate) => write!(f, "leaf for gate {} does not match the circuit", gate),
+            TreeError::WeightCount { expected, found } => write!(f, "expected {} leaf weights, found {}", expected, found),
+            TreeError::Taproot(error) => write!(f, "cannot build taproot tree: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for TreeError {}
+
+/// Taproot spend data for the challenge leaves of a circuit.
+#[derive(Debug, Clone)]
+pub struct ChallengeTree {
+    pub spend_info: TaprootSpendInfo,
+    /// `<prover> OP_CHECKSIGVERIFY <verifier> OP_CHECKSIG`
+    pub cooperative: ScriptBuf,
+    /// Blocks after the output confirms before the timeout leaf is valid.
+    pub prover_timeout: u16,
+    /// `<prover_timeout> OP_CSV OP_DROP <prover> OP_CHECKSIG`
+    pub timeout: ScriptBuf,
+    /// Leaves indexed by gate.
+    leaves: Vec<GateScript>,
+}
+
+impl ChallengeTree {
+    /// Commits to one leaf per gate of `circuit`, given in any order, and
+    /// lets the prover reclaim the output after `prover_timeout` blocks.
+    pub fn build(
+        circuit: &BinaryCircuit,
+        prover: XOnlyPublicKey,
+        verifier: XOnlyPublicKey,
+        prover_timeout: u16,
+        leaves: Vec<GateScript>,
+        shape: TreeShape,
+    ) -> Result<ChallengeTree, TreeError> {
+        let mut by_gate: Vec<Option<GateScript>> = vec![None; circuit.gates().len()];
+        for leaf in leaves {
+            match by_gate.get_mut(leaf.gate.0) {
+                Some(slot @ None) if circuit.gate(leaf.gate).gate_type == leaf.gate_type => *slot = Some(leaf),
+                _ => return Err(TreeError::UnexpectedLeaf(leaf.gate)),
+            }
+        }
+        let leaves: Vec<GateScript> = by_gate.into_iter().enumerate()
+            .map(|(index, leaf)| leaf.ok_or(TreeError::MissingLeaf(GateId(index))))
+            .collect::<Result<_, _>>()?;
+
+        let weights = match shape {
+            TreeShape::Balanced => vec![1; leaves.len()],
+            TreeShape::Weighted(weights) if weights.len() == leaves.len() => weights,
+            TreeShape::Weighted(weights) => {
+                return Err(TreeError::WeightCount { expected: leaves.len(), found: weights.len() })
+            }
+        };
+        let cooperative = cooperative_script(&prover, &verifier);
+        let timeout = relative_timeout_script(prover_timeout, &prover);
+        let fallback_weight = weights.iter().copied().max().unwrap_or(1);
+        let scripts = leaves.iter().zip(weights)
+            .map(|(leaf, weight)| (weight, leaf.violation.clone()))
+            .chain(vec![(fallback_weight, cooperative.clone()), (fallback_weight, timeout.clone())]);
+
+        let secp = Secp256k1::verification_only();
+        let spend_info = TaprootBuilder::with_huffman_tree(scripts)
+            .map_err(TreeError::Taproot)?
+            .finalize(&secp, unspendable_internal_key())
+            .expect("huffman trees are complete");
+        Ok(ChallengeTree { spend_info, cooperative, prover_timeout, timeout, leaves })
+    }
+
+    /// The P2TR script of the output.
+    pub fn output_script(&self) -> ScriptBuf {
+        ScriptBuf::new_p2tr_tweaked(self.spend_info.output_key())
+    }
+
+    pub fn leaf(&self, gate: GateId) -> Option<&GateScript> {
+        self.leaves.get(gate.0)
+    }
+
+    pub fn leaves(&self) -> &[GateScript] {
+        &self.leaves
+    }
+
+    pub fn leaf_hash(&self, gate: GateId) -> Option<TapLeafHash> {
+        self.leaf(gate).map(|leaf| TapLeafHash::from_script(&leaf.violation, LeafVersion::TapScript))
+    }
+
+    pub fn control_block(&self, gate: GateId) -> Option<ControlBlock> {
+        let leaf = self.leaf(gate)?;
+        self.spend_info.control_block(&(leaf.violation.clone(), LeafVersion::TapScript))
+    }
+
+    pub fn cooperative_control_block(&self) -> ControlBlock {
+        self.spend_info
+            .control_block(&(self.cooperative.clone(), LeafVersion::TapScript))
+            .expect("cooperative leaf is in the tree")
+    }
+
+    pub fn timeout_control_block(&self) -> ControlBlock {
+        self.spend_info
+            .control_block(&(self.timeout.clone(), LeafVersion::TapScript))
+            .expect("timeout leaf is in the tree")
+    }
+
+    /// Full script-path witness for a violation leaf: the leaf's `opening`
+    /// witness followed by the script and its control block.
+    pub fn spend_witness(&self, gate: GateId, opening: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+        let leaf = self.leaf(gate)?;
+        let control_block = self.control_block(gate)?;
+        let mut witness = opening;
+        witness.push(leaf.violation.to_bytes());
+        witness.push(control_block.serialize());
+        Some(witness)
+    }
+
+    /// Interpreter context for spending the gate's leaf as input `input_idx` of `tx`.
+    pub fn tx_template(&self, gate: GateId, tx: Transaction, prevouts: Vec<TxOut>, input_idx: usize) -> Option<TxTemplate> {
+        Some(TxTemplate {
+            tx,
+            prevouts,
+            input_idx,
+            taproot_annex_scriptleaf: Some((self.leaf_hash(gate)?, None)),
+        })
+    }
+}
+
+/// Message a `SIGHASH_DEFAULT` Schnorr signature commits to when spending
+/// `leaf_hash` as input `input_idx` of `tx`, as checked by `OP_CHECKSIG`.
+pub fn leaf_signature_hash(
+    tx: &Transaction,
+    input_idx: usize,
+    prevouts: &[TxOut],
+    leaf_hash: TapLeafHash,
+) -> Result<TapSighash, sighash::Error> {
+    SighashCache::new(tx).taproot_script_spend_signature_hash(input_idx, &Prevouts::All(prevouts), leaf_hash, TapSighashType::Default)
+}
@@ -0,0 +1,43 @@
+//! Timelocked leaves that let a party claim the funds once the other side
+//! missed a deadline.
+//!
+//! Relative timeouts count blocks from the confirmation of the output being
+//! spent and are enforced with `OP_CHECKSEQUENCEVERIFY`; absolute deadlines
+//! are block heights enforced with `OP_CHECKLOCKTIMEVERIFY`.
+
+use bitcoin::absolute;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::opcodes::all::*;
+use bitcoin::script::{Builder, ScriptBuf};
+use bitcoin::Sequence;
+
+/// `<blocks> OP_CSV OP_DROP <key> OP_CHECKSIG`
+pub fn relative_timeout_script(blocks: u16, key: &XOnlyPublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_sequence(Sequence::from_height(blocks))
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_DROP)
+        .push_x_only_key(key)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+/// `<height> OP_CLTV OP_DROP <key> OP_CHECKSIG`, or `None` if `height` is
+/// not a block height (500 000 000 and above are timestamps).
+pub fn absolute_timeout_script(height: u32, key: &XOnlyPublicKey) -> Option<ScriptBuf> {
+    let lock_time = absolute::LockTime::from_height(height).ok()?;
+    Some(
+        Builder::new()
+            .push_lock_time(lock_time)
+            .push_opcode(OP_CLTV)
+            .push_opcode(OP_DROP)
+            .push_x_only_key(key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script(),
+    )
+}
+
+/// Input sequence that satisfies `relative_timeout_script(blocks, _)`.
+pub fn timeout_sequence(blocks: u16) -> Sequence {
+    Sequence::from_height(blocks)
+}
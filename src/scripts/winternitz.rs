@@ -0,0 +1,340 @@
+//! Winternitz one-time signatures over multi-bit values.
+//!
+//! A value of `bits` bits is split into digits of `log_d` bits, least
+//! significant first, followed by checksum digits encoding the sum of
+//! `d - 1 - digit` over the message digits, `d` being `2^log_d`. Digit `i`
+//! has a secret `s_i` and the public hash `H^(d-1)(s_i)`. Its signature for
+//! value `v` is `H^v(s_i)`, from which anyone can hash forward to larger
+//! values but not back; raising a message digit lowers the checksum, so a
+//! signature cannot be altered.
+//!
+//! The Script verifier expects `<chain> <digit>` per digit on the witness,
+//! digit 0 on top. For each digit it checks `0 <= digit < d`, computes
+//! `chain, H(chain), .., H^(d-1)(chain)`, picks `H^(d-1-digit)(chain)` and
+//! compares it with the public hash. It then checks the checksum and leaves
+//! the message digits on the stack, digit 0 on top.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use bitcoin::hashes::{hash160, sha256, Hash, HashEngine};
+use bitcoin::opcodes::all::*;
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::{Builder, PushBytes, ScriptBuf};
+
+use crate::execute_btc_script::ExecStats;
+use crate::scripts::run::run_tapscript;
+
+/// Hash the chains are built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChainHash {
+    /// 20-byte chain elements, `OP_HASH160`.
+    Hash160,
+    /// 32-byte chain elements, `OP_SHA256`.
+    Sha256,
+}
+
+impl ChainHash {
+    pub fn output_len(self) -> usize {
+        match self {
+            ChainHash::Hash160 => 20,
+            ChainHash::Sha256 => 32,
+        }
+    }
+
+    pub fn hash(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ChainHash::Hash160 => hash160::Hash::hash(data).to_byte_array().to_vec(),
+            ChainHash::Sha256 => sha256::Hash::hash(data).to_byte_array().to_vec(),
+        }
+    }
+
+    fn opcode(self) -> Opcode {
+        match self {
+            ChainHash::Hash160 => OP_HASH160,
+            ChainHash::Sha256 => OP_SHA256,
+        }
+    }
+}
+
+/// Error raised for invalid parameters or values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WinternitzError {
+    /// Values must have at least one bit.
+    ZeroWidth,
+    /// Digits take 1 to 8 bits.
+    DigitWidth(u32),
+    /// The value has bits set above the width of the key.
+    ValueTooWide { bits: usize },
+}
+
+impl fmt::Display for WinternitzError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WinternitzError::ZeroWidth => write!(f, "values must have at least one bit"),
+            WinternitzError::DigitWidth(log_d) => write!(f, "digits of {} bits are not supported, use 1 to 8", log_d),
+            WinternitzError::ValueTooWide { bits } => write!(f, "value does not fit in {} bits", bits),
+        }
+    }
+}
+
+impl std::error::Error for WinternitzError {}
+
+/// Shape of a Winternitz key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WinternitzParams {
+    /// Width of the signed values.
+    pub bits: usize,
+    /// Bits per digit.
+    pub log_d: u32,
+    pub hash: ChainHash,
+}
+
+impl WinternitzParams {
+    pub fn new(bits: usize, log_d: u32, hash: ChainHash) -> Result<Self, WinternitzError> {
+        if bits == 0 {
+            return Err(WinternitzError::ZeroWidth);
+        }
+        if !(1..=8).contains(&log_d) {
+            return Err(WinternitzError::DigitWidth(log_d));
+        }
+        Ok(WinternitzParams { bits, log_d, hash })
+    }
+
+    /// Number of values a digit takes, `d`.
+    pub fn base(&self) -> u32 {
+        1 << self.log_d
+    }
+
+    pub fn message_digits(&self) -> usize {
+        self.bits.div_ceil(self.log_d as usize)
+    }
+
+    pub fn checksum_digits(&self) -> usize {
+        let mut max = self.max_checksum();
+        let mut digits = 1;
+        while max >= self.base() as usize {
+            max >>= self.log_d;
+            digits += 1;
+        }
+        digits
+    }
+
+    pub fn digits(&self) -> usize {
+        self.message_digits() + self.checksum_digits()
+    }
+
+    fn max_checksum(&self) -> usize {
+        self.message_digits() * (self.base() as usize - 1)
+    }
+
+    /// Message and checksum digits of `value`, a big-endian integer of at most `bits` bits.
+    pub fn digits_of(&self, value: &[u8]) -> Result<Vec<u32>, WinternitzError> {
+        let bit = |index: usize| match value.len().checked_sub(1 + index / 8) {
+            Some(byte) => (value[byte] >> (index % 8)) & 1,
+            None => 0,
+        };
+        if (self.bits..value.len() * 8).any(|index| bit(index) == 1) {
+            return Err(WinternitzError::ValueTooWide { bits: self.bits });
+        }
+        let mut digits: Vec<u32> = (0..self.message_digits())
+            .map(|digit| {
+                (0..self.log_d as usize).map(|offset| (bit(digit * self.log_d as usize + offset) as u32) << offset).sum()
+            })
+            .collect();
+        let mut checksum: usize = digits.iter().map(|&digit| (self.base() - 1 - digit) as usize).sum();
+        for _ in 0..self.checksum_digits() {
+            digits.push((checksum % self.base() as usize) as u32);
+            checksum >>= self.log_d;
+        }
+        Ok(digits)
+    }
+
+    /// Measures the verifier script of a key with these parameters.
+    pub fn cost(&self) -> WinternitzCost {
+        let secret = WinternitzSecret::from_seed(*self, b"winternitz cost");
+        let signature = secret.sign(&[]).expect("zero fits in any width");
+        let script = secret.public_key().verify_script();
+        let witness = signature.witness();
+        let run = run_tapscript(&script, witness.clone()).expect("generated scripts are valid");
+        assert!(run.success(), "verifier accepts a valid signature");
+        WinternitzCost {
+            script_size: script.len(),
+            witness_size: witness.iter().map(Vec::len).sum(),
+            stats: run.stats,
+        }
+    }
+}
+
+/// Size and resource use of a verifier script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WinternitzCost {
+    pub script_size: usize,
+    /// Bytes of the witness items, without their length prefixes.
+    pub witness_size: usize,
+    pub stats: ExecStats,
+}
+
+/// Secret the chain secrets of one key are derived from.
+#[derive(Clone)]
+pub struct WinternitzSecret {
+    params: WinternitzParams,
+    seed: [u8; 32],
+}
+
+impl WinternitzSecret {
+    pub fn from_seed(params: WinternitzParams, seed: &[u8]) -> Self {
+        WinternitzSecret { params, seed: sha256::Hash::hash(seed).to_byte_array() }
+    }
+
+    pub fn params(&self) -> WinternitzParams {
+        self.params
+    }
+
+    fn chain_secret(&self, digit: usize) -> Vec<u8> {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.seed);
+        engine.input(&(digit as u64).to_be_bytes());
+        sha256::Hash::from_engine(engine)[..self.params.hash.output_len()].to_vec()
+    }
+
+    pub fn public_key(&self) -> WinternitzPublicKey {
+        let hashes = (0..self.params.digits())
+            .map(|digit| chain(self.params.hash, self.chain_secret(digit), self.params.base() - 1))
+            .collect();
+        WinternitzPublicKey { params: self.params, hashes }
+    }
+
+    /// Signs `value`, a big-endian integer of at most `bits` bits. A key
+    /// must sign a single value: two signatures reveal enough to forge others.
+    pub fn sign(&self, value: &[u8]) -> Result<WinternitzSignature, WinternitzError> {
+        let digits = self.params.digits_of(value)?;
+        let chains = digits.iter().enumerate().map(|(index, &digit)| chain(self.params.hash, self.chain_secret(index), digit)).collect();
+        Ok(WinternitzSignature { digits, chains })
+    }
+}
+
+fn chain(hash: ChainHash, start: Vec<u8>, steps: u32) -> Vec<u8> {
+    (0..steps).fold(start, |element, _| hash.hash(&element))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WinternitzPublicKey {
+    pub params: WinternitzParams,
+    /// End of the hash chain of each digit.
+    pub hashes: Vec<Vec<u8>>,
+}
+
+impl WinternitzPublicKey {
+    /// Message digits of `signature` if it is valid for this key.
+    pub fn verify(&self, signature: &WinternitzSignature) -> Option<Vec<u32>> {
+        let params = &self.params;
+        if signature.digits.len() != params.digits() || signature.chains.len() != params.digits() {
+            return None;
+        }
+        if signature.digits.iter().any(|&digit| digit >= params.base()) {
+            return None;
+        }
+        let chains_valid = signature.digits.iter().zip(&signature.chains).zip(&self.hashes)
+            .all(|((&digit, element), hash)| chain(params.hash, element.clone(), params.base() - 1 - digit) == *hash);
+        let message = &signature.digits[..params.message_digits()];
+        let checksum = signature.digits[params.message_digits()..].iter().rev()
+            .fold(0, |checksum, &digit| (checksum << params.log_d) + digit as usize);
+        let expected: usize = message.iter().map(|&digit| (params.base() - 1 - digit) as usize).sum();
+        if chains_valid && checksum == expected {
+            Some(message.to_vec())
+        } else {
+            None
+        }
+    }
+
+    /// Appends the verifier, which replaces the signature on the stack by the message digits.
+    pub fn push_verify(&self, mut builder: Builder) -> Builder {
+        let params = &self.params;
+        let base = params.base() as i64;
+        for hash in &self.hashes {
+            builder = builder
+                .push_opcode(OP_DUP)
+                .push_int(0)
+                .push_int(base)
+                .push_opcode(OP_WITHIN)
+                .push_opcode(OP_VERIFY)
+                .push_opcode(OP_DUP)
+                .push_opcode(OP_TOALTSTACK)
+                .push_opcode(OP_SWAP);
+            for _ in 1..base {
+                builder = builder.push_opcode(OP_DUP).push_opcode(params.hash.opcode());
+            }
+            builder = builder
+                .push_int(base)
+                .push_opcode(OP_ROLL)
+                .push_opcode(OP_PICK)
+                .push_slice(<&PushBytes>::try_from(&hash[..]).expect("hashes are short"))
+                .push_opcode(OP_EQUALVERIFY);
+            for _ in 0..base / 2 {
+                builder = builder.push_opcode(OP_2DROP);
+            }
+        }
+
+        // The checksum digits come off the altstack most significant first.
+        builder = builder.push_opcode(OP_FROMALTSTACK);
+        for _ in 1..params.checksum_digits() {
+            for _ in 0..params.log_d {
+                builder = builder.push_opcode(OP_DUP).push_opcode(OP_ADD);
+            }
+            builder = builder.push_opcode(OP_FROMALTSTACK).push_opcode(OP_ADD);
+        }
+        // Adding the message digits to the checksum has to give the maximum.
+        for _ in 0..params.message_digits() {
+            builder = builder
+                .push_opcode(OP_FROMALTSTACK)
+                .push_opcode(OP_DUP)
+                .push_opcode(OP_ROT)
+                .push_opcode(OP_ADD);
+        }
+        builder.push_int(params.max_checksum() as i64).push_opcode(OP_EQUALVERIFY)
+    }
+
+    /// Standalone verifier that drops the message digits and leaves true.
+    pub fn verify_script(&self) -> ScriptBuf {
+        let mut builder = self.push_verify(Builder::new());
+        for _ in 0..self.params.message_digits() / 2 {
+            builder = builder.push_opcode(OP_2DROP);
+        }
+        if self.params.message_digits() % 2 == 1 {
+            builder = builder.push_opcode(OP_DROP);
+        }
+        builder.push_int(1).into_script()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WinternitzSignature {
+    /// Message then checksum digits, least significant first.
+    pub digits: Vec<u32>,
+    /// Chain element revealed for each digit.
+    pub chains: Vec<Vec<u8>>,
+}
+
+impl WinternitzSignature {
+    /// Witness for the verifier script, digit 0 on top.
+    pub fn witness(&self) -> Vec<Vec<u8>> {
+        self.digits.iter().zip(&self.chains).rev()
+            .flat_map(|(&digit, element)| vec![element.clone(), script_num(digit)])
+            .collect()
+    }
+}
+
+/// Minimal Script encoding of a small non-negative number.
+fn script_num(value: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut rest = value;
+    while rest > 0 {
+        bytes.push((rest & 0xff) as u8);
+        rest >>= 8;
+    }
+    if bytes.last().is_some_and(|&last| last & 0x80 != 0) {
+        bytes.push(0);
+    }
+    bytes
+}
@@ -0,0 +1,402 @@
+//! Pre-signed transaction graph of an optimistic dispute.
+//!
+//! The prover locks its funding with `kickoff` and posts its claim with
+//! `assert`, whose witness opens the commitment of every wire of the
+//! circuit. If nobody objects within the take timeout, the prover collects
+//! with `take`. A verifier that objects spends the claim with `challenge`
+//! instead, which moves the funds into the circuit's `ChallengeTree`. There
+//! a `disprove` spends them through the violation leaf of a gate that the
+//! openings of `assert` break. If no gate is disproved within the tree's
+//! prover timeout, the prover collects with `reclaim`. As long as the claim
+//! is unspent, the committee can also take it with `punish` by revealing
+//! both preimages of a wire the prover opened to both bits.
+//!
+//! The outputs of `kickoff` and `assert` are locked by leaves ending in
+//! `<prover> OP_CHECKSIGVERIFY <committee> OP_CHECKSIG`, so the verifier
+//! committee signs `assert`, `challenge` and `take` before the prover
+//! broadcasts `kickoff`. Violation leaves need no signature, and the timeout
+//! and punishment leaves only the prover's or the committee's, so `disprove`,
+//! `reclaim` and `punish` are built on demand.
+
+use std::fmt;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::opcodes::all::*;
+use bitcoin::script::{Builder, ScriptBuf};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::sighash::TapSighash;
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo};
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+
+use crate::execute_btc_script::TxTemplate;
+use crate::logic_gate::{GateId, WireId};
+use crate::scripts::bit_commitment::{WireCommitment, PREIMAGE_LEN};
+use crate::scripts::equivocation::punishment_script;
+use crate::scripts::taproot::{cooperative_script, leaf_signature_hash, unspendable_internal_key, ChallengeTree};
+use crate::scripts::timelocks::timeout_sequence;
+
+/// Error raised while building the graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    NoFunding,
+    /// The funding values do not fit in an amount.
+    FundingOverflow,
+    /// The funding does not cover the fees along the longest path.
+    InsufficientFunds { available: Amount, needed: Amount },
+    /// A wire of a gate leaf has no commitment for `assert` to open.
+    MissingCommitment(WireId),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::NoFunding => write!(f, "kickoff has no funding input"),
+            GraphError::FundingOverflow => write!(f, "funding values overflow"),
+            GraphError::InsufficientFunds { available, needed } => {
+                write!(f, "funding of {} does not cover {} of fees", available, needed)
+            }
+            GraphError::MissingCommitment(wire) => write!(f, "wire {} has no commitment", wire),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// Everything the graph is built from.
+#[derive(Debug, Clone)]
+pub struct GraphParams {
+    /// Outputs the prover funds `kickoff` with, signed when it is broadcast.
+    pub funding: Vec<(OutPoint, TxOut)>,
+    pub prover: XOnlyPublicKey,
+    /// Commitments to every wire of the circuit, indexed by wire; `assert`
+    /// opens all of them.
+    pub commitments: Vec<WireCommitment>,
+    /// Key of the verifier committee, which pre-signs the graph.
+    pub committee: XOnlyPublicKey,
+    /// Blocks after `assert` confirms before `take` is valid.
+    pub take_timeout: u16,
+    /// Fee paid by each transaction.
+    pub fee: Amount,
+    pub prover_payout: ScriptBuf,
+    pub committee_payout: ScriptBuf,
+}
+
+/// How an input of the graph is spent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSpend {
+    /// Spent by the owner of the prevout, outside of the graph's scripts.
+    External,
+    Leaf { script: ScriptBuf, control_block: ControlBlock },
+}
+
+/// One transaction of the graph with what is needed to sign and check its inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphTx {
+    pub tx: Transaction,
+    pub prevouts: Vec<TxOut>,
+    /// Spend of each input, in input order.
+    pub spends: Vec<InputSpend>,
+}
+
+impl GraphTx {
+    pub fn txid(&self) -> Txid {
+        self.tx.txid()
+    }
+
+    pub fn outpoint(&self, vout: u32) -> OutPoint {
+        OutPoint::new(self.txid(), vout)
+    }
+
+    pub fn leaf_hash(&self, input_idx: usize) -> Option<TapLeafHash> {
+        match self.spends.get(input_idx)? {
+            InputSpend::External => None,
+            InputSpend::Leaf { script, .. } => Some(TapLeafHash::from_script(script, LeafVersion::TapScript)),
+        }
+    }
+
+    /// Message a `SIGHASH_DEFAULT` signature of a script-path input commits to.
+    pub fn signature_hash(&self, input_idx: usize) -> Option<TapSighash> {
+        leaf_signature_hash(&self.tx, input_idx, &self.prevouts, self.leaf_hash(input_idx)?).ok()
+    }
+
+    /// Interpreter context for executing the leaf of input `input_idx`.
+    pub fn tx_template(&self, input_idx: usize) -> TxTemplate {
+        TxTemplate {
+            tx: self.tx.clone(),
+            prevouts: self.prevouts.clone(),
+            input_idx,
+            taproot_annex_scriptleaf: self.leaf_hash(input_idx).map(|leaf_hash| (leaf_hash, None)),
+        }
+    }
+
+    /// Full script-path witness of input `input_idx`: `stack` followed by the
+    /// leaf and its control block.
+    pub fn witness(&self, input_idx: usize, stack: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+        match self.spends.get(input_idx)? {
+            InputSpend::External => None,
+            InputSpend::Leaf { script, control_block } => {
+                let mut witness = stack;
+                witness.push(script.to_bytes());
+                witness.push(control_block.serialize());
+                Some(witness)
+            }
+        }
+    }
+}
+
+/// Transactions kept in the graph; `disprove` and `punish` depend on the
+/// gate or wire and `reclaim` needs no committee signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TxKind {
+    Kickoff,
+    Assert,
+    Challenge,
+    Take,
+}
+
+/// A signature the committee has to produce before the graph is live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SigningMessage {
+    pub kind: TxKind,
+    pub input_idx: usize,
+    pub leaf_hash: TapLeafHash,
+    pub sighash: TapSighash,
+}
+
+#[derive(Debug, Clone)]
+pub struct TxGraph {
+    pub kickoff: GraphTx,
+    pub assert: GraphTx,
+    pub challenge: GraphTx,
+    pub take: GraphTx,
+    tree: ChallengeTree,
+    assert_info: TaprootSpendInfo,
+    /// Punishment leaves of the claim, indexed by wire.
+    punishments: Vec<ScriptBuf>,
+    fee: Amount,
+    prover_payout: ScriptBuf,
+    committee_payout: ScriptBuf,
+}
+
+impl TxGraph {
+    /// Builds the graph whose `challenge` locks the funds in `tree`.
+    pub fn build(params: GraphParams, tree: ChallengeTree) -> Result<TxGraph, GraphError> {
+        if params.funding.is_empty() {
+            return Err(GraphError::NoFunding);
+        }
+        let available = params.funding.iter()
+            .try_fold(Amount::ZERO, |total, (_, output)| total.checked_add(output.value))
+            .ok_or(GraphError::FundingOverflow)?;
+        let uncommitted = tree.leaves().iter()
+            .flat_map(|leaf| leaf.inputs.iter().copied().chain(Some(leaf.output)))
+            .find(|wire| params.commitments.get(wire.0).map(|commitment| commitment.wire) != Some(*wire));
+        if let Some(wire) = uncommitted {
+            return Err(GraphError::MissingCommitment(wire));
+        }
+        // kickoff, assert, challenge and disprove or reclaim each pay a fee.
+        let needed = params.fee.checked_mul(4).ok_or(GraphError::FundingOverflow)?;
+        if available <= needed {
+            return Err(GraphError::InsufficientFunds { available, needed });
+        }
+
+        let assert_leaf = assert_script(&params.commitments, &params.prover, &params.committee);
+        let kickoff_info = script_tree(std::slice::from_ref(&assert_leaf));
+        let kickoff = GraphTx {
+            tx: transaction(
+                params.funding.iter().map(|(outpoint, _)| (*outpoint, Sequence::MAX)).collect(),
+                TxOut { value: available - params.fee, script_pubkey: p2tr(&kickoff_info) },
+            ),
+            prevouts: params.funding.iter().map(|(_, output)| output.clone()).collect(),
+            spends: vec![InputSpend::External; params.funding.len()],
+        };
+
+        let claim = cooperative_script(&params.prover, &params.committee);
+        let take_leaf = timelocked_cooperative_script(params.take_timeout, &params.prover, &params.committee);
+        let punishments: Vec<ScriptBuf> = params.commitments.iter()
+            .map(|commitment| punishment_script(commitment, &params.committee))
+            .collect();
+        let mut assert_leaves = vec![claim.clone(), take_leaf.clone()];
+        assert_leaves.extend(punishments.iter().cloned());
+        let assert_info = script_tree(&assert_leaves);
+        let assert = spend(&kickoff, &kickoff_info, assert_leaf, Sequence::MAX, p2tr(&assert_info), params.fee);
+        let challenge = spend(&assert, &assert_info, claim, Sequence::MAX, tree.output_script(), params.fee);
+        let take = spend(
+            &assert,
+            &assert_info,
+            take_leaf,
+            timeout_sequence(params.take_timeout),
+            params.prover_payout.clone(),
+            params.fee,
+        );
+        Ok(TxGraph {
+            kickoff,
+            assert,
+            challenge,
+            take,
+            tree,
+            assert_info,
+            punishments,
+            fee: params.fee,
+            prover_payout: params.prover_payout,
+            committee_payout: params.committee_payout,
+        })
+    }
+
+    pub fn tree(&self) -> &ChallengeTree {
+        &self.tree
+    }
+
+    pub fn tx(&self, kind: TxKind) -> &GraphTx {
+        match kind {
+            TxKind::Kickoff => &self.kickoff,
+            TxKind::Assert => &self.assert,
+            TxKind::Challenge => &self.challenge,
+            TxKind::Take => &self.take,
+        }
+    }
+
+    /// Transaction paying the committee through the violation leaf of `gate`, or `None`
+    /// if the tree has no such leaf.
+    pub fn disprove(&self, gate: GateId) -> Option<GraphTx> {
+        let leaf = self.tree.leaf(gate)?;
+        let control_block = self.tree.control_block(gate)?;
+        let prevout = self.challenge.tx.output[0].clone();
+        Some(GraphTx {
+            tx: transaction(
+                vec![(self.challenge.outpoint(0), Sequence::MAX)],
+                TxOut { value: prevout.value - self.fee, script_pubkey: self.committee_payout.clone() },
+            ),
+            prevouts: vec![prevout],
+            spends: vec![InputSpend::Leaf { script: leaf.violation.clone(), control_block }],
+        })
+    }
+
+    /// Transaction paying the prover through the tree's timeout leaf, valid
+    /// `prover_timeout` blocks after `challenge` confirms.
+    pub fn reclaim(&self) -> GraphTx {
+        spend(
+            &self.challenge,
+            &self.tree.spend_info,
+            self.tree.timeout.clone(),
+            timeout_sequence(self.tree.prover_timeout),
+            self.prover_payout.clone(),
+            self.fee,
+        )
+    }
+
+    /// Transaction paying the committee through the punishment leaf of
+    /// `wire`, or `None` if the wire has no commitment.
+    pub fn punish(&self, wire: WireId) -> Option<GraphTx> {
+        let leaf = self.punishments.get(wire.0)?;
+        Some(spend(
+            &self.assert,
+            &self.assert_info,
+            leaf.clone(),
+            Sequence::MAX,
+            self.committee_payout.clone(),
+            self.fee,
+        ))
+    }
+
+    /// Signature hashes of every script-path input of the pre-signed transactions.
+    pub fn signing_messages(&self) -> Vec<SigningMessage> {
+        let kinds = [TxKind::Kickoff, TxKind::Assert, TxKind::Challenge, TxKind::Take];
+        kinds.iter()
+            .flat_map(|&kind| {
+                let graph_tx = self.tx(kind);
+                (0..graph_tx.spends.len()).filter_map(move |input_idx| {
+                    Some(SigningMessage {
+                        kind,
+                        input_idx,
+                        leaf_hash: graph_tx.leaf_hash(input_idx)?,
+                        sighash: graph_tx.signature_hash(input_idx)?,
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+/// Stack spending `assert`: the committee's and the prover's signatures,
+/// then the preimages of every wire, indexed by wire, with wire 0 on top.
+pub fn assert_stack(committee_signature: &[u8], prover_signature: &[u8], preimages: &[[u8; PREIMAGE_LEN]]) -> Vec<Vec<u8>> {
+    let mut stack = vec![committee_signature.to_vec(), prover_signature.to_vec()];
+    stack.extend(preimages.iter().rev().map(|preimage| preimage.to_vec()));
+    stack
+}
+
+/// Opens every commitment, wire 0 first, then checks
+/// `<prover> OP_CHECKSIGVERIFY <committee> OP_CHECKSIG`.
+fn assert_script(commitments: &[WireCommitment], prover: &XOnlyPublicKey, committee: &XOnlyPublicKey) -> ScriptBuf {
+    let mut builder = Builder::new();
+    for commitment in commitments {
+        builder = commitment.push_open(builder).push_opcode(OP_DROP);
+    }
+    builder
+        .push_x_only_key(prover)
+        .push_opcode(OP_CHECKSIGVERIFY)
+        .push_x_only_key(committee)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+/// `<blocks> OP_CSV OP_DROP <prover> OP_CHECKSIGVERIFY <committee> OP_CHECKSIG`
+fn timelocked_cooperative_script(blocks: u16, prover: &XOnlyPublicKey, committee: &XOnlyPublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_sequence(timeout_sequence(blocks))
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_DROP)
+        .push_x_only_key(prover)
+        .push_opcode(OP_CHECKSIGVERIFY)
+        .push_x_only_key(committee)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+fn script_tree(leaves: &[ScriptBuf]) -> TaprootSpendInfo {
+    let secp = Secp256k1::verification_only();
+    TaprootBuilder::with_huffman_tree(leaves.iter().map(|leaf| (1, leaf.clone())))
+        .expect("leaves fit in the tree")
+        .finalize(&secp, unspendable_internal_key())
+        .expect("huffman trees are complete")
+}
+
+fn p2tr(info: &TaprootSpendInfo) -> ScriptBuf {
+    ScriptBuf::new_p2tr_tweaked(info.output_key())
+}
+
+fn transaction(inputs: Vec<(OutPoint, Sequence)>, output: TxOut) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: inputs.into_iter()
+            .map(|(previous_output, sequence)| TxIn {
+                previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: vec![output],
+    }
+}
+
+/// Transaction spending output 0 of `parent` through `leaf` of `info` into `script_pubkey`.
+fn spend(
+    parent: &GraphTx,
+    info: &TaprootSpendInfo,
+    leaf: ScriptBuf,
+    sequence: Sequence,
+    script_pubkey: ScriptBuf,
+    fee: Amount,
+) -> GraphTx {
+    let prevout = parent.tx.output[0].clone();
+    let control_block = info.control_block(&(leaf.clone(), LeafVersion::TapScript)).expect("leaf is in the tree");
+    GraphTx {
+        tx: transaction(vec![(parent.outpoint(0), sequence)], TxOut { value: prevout.value - fee, script_pubkey }),
+        prevouts: vec![prevout],
+        spends: vec![InputSpend::Leaf { script: leaf, control_block }],
+    }
+}
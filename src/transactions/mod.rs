@@ -0,0 +1,7 @@
+pub mod graph;
+pub mod presign;
+pub mod musig;
+
+pub use graph::{GraphError, GraphParams, GraphTx, InputSpend, SigningMessage, TxGraph, TxKind};
+pub use presign::{PartialSignature, PresignedSet, Signer, SigningError, SigningSession, StoreError};
+pub use musig::{KeyAggContext, MusigError, MusigSession};
@@ -0,0 +1,395 @@
+//! MuSig2 (BIP 327) for the n-of-n verifier committee.
+//!
+//! The committee signs as one aggregate key, so leaves and internal keys
+//! carry a single 32-byte key and a spend a single 64-byte BIP 340
+//! signature, which `OP_CHECKSIG` verifies like any other. Signing takes
+//! two rounds: every signer publishes a `PublicNonce`, then a
+//! `PartialSig` over the aggregate nonce. The coordinator checks each
+//! partial signature before summing them into the final signature.
+//!
+//! A `SecretNonce` must never sign twice, so `MusigSession::sign` consumes it.
+
+use std::fmt;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{constants, schnorr, All, Parity, PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::taproot::{TapNodeHash, TapTweakHash};
+
+use crate::transactions::graph::SigningMessage;
+use crate::transactions::presign::PartialSignature;
+
+lazy_static::lazy_static! {
+    static ref SECP: Secp256k1<All> = Secp256k1::new();
+}
+
+/// Error raised while aggregating keys, nonces or signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MusigError {
+    NoKeys,
+    NoNonces,
+    /// The keys or tweaks add up to the point at infinity.
+    InfiniteKey,
+    /// A tweak is not below the curve order.
+    InvalidTweak,
+    /// A public nonce is not two compressed points.
+    InvalidNonce,
+    UnknownSigner(PublicKey),
+    /// The secret nonce was generated for another key.
+    NonceMismatch,
+    /// The partial signature of the signer at this index does not verify.
+    InvalidPartialSig(usize),
+}
+
+impl fmt::Display for MusigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MusigError::NoKeys => write!(f, "no keys to aggregate"),
+            MusigError::NoNonces => write!(f, "no nonces to aggregate"),
+            MusigError::InfiniteKey => write!(f, "aggregate key is the point at infinity"),
+            MusigError::InvalidTweak => write!(f, "tweak is not below the curve order"),
+            MusigError::InvalidNonce => write!(f, "public nonce is not two compressed points"),
+            MusigError::UnknownSigner(key) => write!(f, "{} is not an aggregated key", key),
+            MusigError::NonceMismatch => write!(f, "secret nonce belongs to another key"),
+            MusigError::InvalidPartialSig(index) => write!(f, "partial signature of signer {} is invalid", index),
+        }
+    }
+}
+
+impl std::error::Error for MusigError {}
+
+/// Aggregate of the committee keys, with the tweaks applied to it so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyAggContext {
+    keys: Vec<PublicKey>,
+    list_hash: [u8; 32],
+    second_key: Option<PublicKey>,
+    aggregate: PublicKey,
+    /// Sign flips applied to the aggregate by x-only tweaks.
+    gacc: Scalar,
+    /// Sum of the tweaks.
+    tacc: Scalar,
+}
+
+impl KeyAggContext {
+    pub fn new(keys: Vec<PublicKey>) -> Result<KeyAggContext, MusigError> {
+        let first = *keys.first().ok_or(MusigError::NoKeys)?;
+        let serialized: Vec<u8> = keys.iter().flat_map(|key| key.serialize().to_vec()).collect();
+        let list_hash = tagged_hash("KeyAgg list", &[&serialized]);
+        let second_key = keys.iter().copied().find(|&key| key != first);
+        let mut context =
+            KeyAggContext { keys, list_hash, second_key, aggregate: first, gacc: Scalar::ONE, tacc: Scalar::ZERO };
+        let aggregate = context.keys.iter()
+            .map(|key| point_mul(key, context.coefficient(key)))
+            .fold(None, point_add);
+        context.aggregate = aggregate.ok_or(MusigError::InfiniteKey)?;
+        Ok(context)
+    }
+
+    pub fn keys(&self) -> &[PublicKey] {
+        &self.keys
+    }
+
+    /// The key a `<key> OP_CHECKSIG` or a Taproot output commits to.
+    pub fn aggregate_key(&self) -> XOnlyPublicKey {
+        self.aggregate.x_only_public_key().0
+    }
+
+    /// Factor `key` is weighted with in the aggregate.
+    pub fn coefficient(&self, key: &PublicKey) -> Scalar {
+        if Some(*key) == self.second_key {
+            return Scalar::ONE;
+        }
+        reduce(tagged_hash("KeyAgg coefficient", &[&self.list_hash, &key.serialize()]))
+    }
+
+    /// Adds `tweak` times the generator to the x-only aggregate key.
+    pub fn with_xonly_tweak(self, tweak: [u8; 32]) -> Result<KeyAggContext, MusigError> {
+        let tweak = Scalar::from_be_bytes(tweak).map_err(|_| MusigError::InvalidTweak)?;
+        let g = if has_even_y(&self.aggregate) { Scalar::ONE } else { negate(Scalar::ONE) };
+        let aggregate = point_add(point_mul(&self.aggregate, g), base_mul(tweak)).ok_or(MusigError::InfiniteKey)?;
+        Ok(KeyAggContext {
+            aggregate,
+            gacc: multiply(g, self.gacc),
+            tacc: add(tweak, multiply(g, self.tacc)),
+            ..self
+        })
+    }
+
+    /// Tweaks the aggregate into the output key of a Taproot output with
+    /// this internal key and `merkle_root`, for key-path spends.
+    pub fn with_taproot_tweak(self, merkle_root: Option<TapNodeHash>) -> Result<KeyAggContext, MusigError> {
+        let tweak = TapTweakHash::from_key_and_tweak(self.aggregate_key(), merkle_root).to_scalar();
+        self.with_xonly_tweak(tweak.to_be_bytes())
+    }
+}
+
+/// Nonce a signer keeps for exactly one signature.
+pub struct SecretNonce {
+    k1: Scalar,
+    k2: Scalar,
+    key: PublicKey,
+}
+
+/// First-round message of a signer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicNonce {
+    pub r1: PublicKey,
+    pub r2: PublicKey,
+}
+
+impl PublicNonce {
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut bytes = [0; 66];
+        bytes[..33].copy_from_slice(&self.r1.serialize());
+        bytes[33..].copy_from_slice(&self.r2.serialize());
+        bytes
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<PublicNonce, MusigError> {
+        if bytes.len() != 66 {
+            return Err(MusigError::InvalidNonce);
+        }
+        let point = |bytes: &[u8]| PublicKey::from_slice(bytes).map_err(|_| MusigError::InvalidNonce);
+        Ok(PublicNonce { r1: point(&bytes[..33])?, r2: point(&bytes[33..])? })
+    }
+}
+
+/// Draws a fresh nonce pair for signing `message` under `context` with `secret`.
+pub fn nonce_gen(secret: &SecretKey, context: &KeyAggContext, message: Option<&[u8; 32]>) -> (SecretNonce, PublicNonce) {
+    let key = PublicKey::from_secret_key(&SECP, secret);
+    let aux: [u8; 32] = rand::random();
+    let mut seed = tagged_hash("MuSig/aux", &[&aux]);
+    for (byte, secret_byte) in seed.iter_mut().zip(secret.secret_bytes().iter()) {
+        *byte ^= secret_byte;
+    }
+    let message_prefixed = match message {
+        Some(message) => [&[1][..], &32u64.to_be_bytes(), message].concat(),
+        None => vec![0],
+    };
+    let aggregate = context.aggregate_key().serialize();
+    let draw = |index: u8| {
+        let parts: [&[u8]; 9] =
+            [&seed, &[33], &key.serialize(), &[32], &aggregate, &message_prefixed, &0u32.to_be_bytes(), &[], &[index]];
+        reduce(tagged_hash("MuSig/nonce", &parts))
+    };
+    let (k1, k2) = (draw(0), draw(1));
+    // A zero nonce has negligible probability.
+    let public = PublicNonce {
+        r1: base_mul(k1).expect("nonzero nonce"),
+        r2: base_mul(k2).expect("nonzero nonce"),
+    };
+    (SecretNonce { k1, k2, key }, public)
+}
+
+/// Sum of the signers' public nonces; either point may be at infinity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AggregateNonce {
+    pub r1: Option<PublicKey>,
+    pub r2: Option<PublicKey>,
+}
+
+impl AggregateNonce {
+    pub fn aggregate(nonces: &[PublicNonce]) -> Result<AggregateNonce, MusigError> {
+        if nonces.is_empty() {
+            return Err(MusigError::NoNonces);
+        }
+        Ok(AggregateNonce {
+            r1: nonces.iter().map(|nonce| Some(nonce.r1)).fold(None, point_add),
+            r2: nonces.iter().map(|nonce| Some(nonce.r2)).fold(None, point_add),
+        })
+    }
+
+    /// 66 bytes, with 33 zero bytes for a point at infinity.
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut bytes = [0; 66];
+        if let Some(r1) = self.r1 {
+            bytes[..33].copy_from_slice(&r1.serialize());
+        }
+        if let Some(r2) = self.r2 {
+            bytes[33..].copy_from_slice(&r2.serialize());
+        }
+        bytes
+    }
+}
+
+/// Second-round message of a signer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialSig(pub Scalar);
+
+/// Second round of signing one message.
+#[derive(Debug, Clone)]
+pub struct MusigSession {
+    context: KeyAggContext,
+    message: [u8; 32],
+    /// Nonce coefficient.
+    b: Scalar,
+    /// Final nonce point.
+    r: PublicKey,
+    /// BIP 340 challenge.
+    e: Scalar,
+}
+
+impl MusigSession {
+    pub fn new(context: KeyAggContext, nonce: AggregateNonce, message: [u8; 32]) -> Self {
+        let aggregate = context.aggregate_key().serialize();
+        let b = reduce(tagged_hash("MuSig/noncecoef", &[&nonce.serialize(), &aggregate, &message]));
+        let r = point_add(nonce.r1, nonce.r2.and_then(|r2| point_mul(&r2, b)))
+            .unwrap_or_else(|| base_mul(Scalar::ONE).expect("one is nonzero"));
+        let e = reduce(tagged_hash("BIP0340/challenge", &[&r.x_only_public_key().0.serialize(), &aggregate, &message]));
+        MusigSession { context, message, b, r, e }
+    }
+
+    pub fn message(&self) -> &[u8; 32] {
+        &self.message
+    }
+
+    pub fn sign(&self, nonce: SecretNonce, secret: &SecretKey) -> Result<PartialSig, MusigError> {
+        let key = PublicKey::from_secret_key(&SECP, secret);
+        if key != nonce.key {
+            return Err(MusigError::NonceMismatch);
+        }
+        if !self.context.keys.contains(&key) {
+            return Err(MusigError::UnknownSigner(key));
+        }
+        let (k1, k2) = if has_even_y(&self.r) { (nonce.k1, nonce.k2) } else { (negate(nonce.k1), negate(nonce.k2)) };
+        let d = multiply(multiply(self.key_sign(), self.context.gacc), Scalar::from(*secret));
+        let s = add(add(k1, multiply(self.b, k2)), multiply(multiply(self.e, self.context.coefficient(&key)), d));
+        Ok(PartialSig(s))
+    }
+
+    /// Whether `partial` is the signature of `key` for the nonce it published.
+    pub fn verify_partial(&self, partial: &PartialSig, nonce: &PublicNonce, key: &PublicKey) -> bool {
+        if !self.context.keys.contains(key) {
+            return false;
+        }
+        let mut r = point_add(Some(nonce.r1), point_mul(&nonce.r2, self.b));
+        if !has_even_y(&self.r) {
+            r = r.map(|r| r.negate(&SECP));
+        }
+        let factor = multiply(multiply(self.e, self.context.coefficient(key)), multiply(self.key_sign(), self.context.gacc));
+        base_mul(partial.0) == point_add(r, point_mul(key, factor))
+    }
+
+    /// Final BIP 340 signature under the aggregate key.
+    pub fn aggregate(&self, partials: &[PartialSig]) -> schnorr::Signature {
+        let tweak = multiply(multiply(self.e, self.key_sign()), self.context.tacc);
+        let s = partials.iter().fold(tweak, |sum, partial| add(sum, partial.0));
+        let mut bytes = [0; 64];
+        bytes[..32].copy_from_slice(&self.r.x_only_public_key().0.serialize());
+        bytes[32..].copy_from_slice(&s.to_be_bytes());
+        schnorr::Signature::from_slice(&bytes).expect("64 bytes")
+    }
+
+    /// 1 or -1, making the aggregate key even.
+    fn key_sign(&self) -> Scalar {
+        if has_even_y(&self.context.aggregate) {
+            Scalar::ONE
+        } else {
+            negate(Scalar::ONE)
+        }
+    }
+}
+
+/// Runs both rounds among `secrets` in process and checks every partial signature.
+pub fn simulate(context: &KeyAggContext, secrets: &[SecretKey], message: [u8; 32]) -> Result<schnorr::Signature, MusigError> {
+    let (secret_nonces, public_nonces): (Vec<_>, Vec<_>) =
+        secrets.iter().map(|secret| nonce_gen(secret, context, Some(&message))).unzip();
+    let session = MusigSession::new(context.clone(), AggregateNonce::aggregate(&public_nonces)?, message);
+    let mut partials = Vec::with_capacity(secrets.len());
+    for (index, (secret, nonce)) in secrets.iter().zip(secret_nonces).enumerate() {
+        let partial = session.sign(nonce, secret)?;
+        if !session.verify_partial(&partial, &public_nonces[index], &PublicKey::from_secret_key(&SECP, secret)) {
+            return Err(MusigError::InvalidPartialSig(index));
+        }
+        partials.push(partial);
+    }
+    Ok(session.aggregate(&partials))
+}
+
+/// Committee signatures of `messages` under the aggregate key, ready for a `SigningSession`.
+pub fn sign_messages(
+    context: &KeyAggContext,
+    secrets: &[SecretKey],
+    messages: &[SigningMessage],
+) -> Result<Vec<PartialSignature>, MusigError> {
+    messages.iter()
+        .map(|message| {
+            let signature = simulate(context, secrets, message.sighash.to_byte_array())?;
+            Ok(PartialSignature {
+                kind: message.kind,
+                input_idx: message.input_idx,
+                signer: context.aggregate_key(),
+                signature: signature.as_ref().to_vec(),
+            })
+        })
+        .collect()
+}
+
+fn tagged_hash(tag: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_byte_array());
+    engine.input(tag.as_byte_array());
+    for part in parts {
+        engine.input(part);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Reduces a big-endian 256-bit integer modulo the curve order.
+fn reduce(bytes: [u8; 32]) -> Scalar {
+    Scalar::from_be_bytes(bytes).unwrap_or_else(|_| {
+        // Below 2^256 < 2n, one subtraction is enough.
+        let mut reduced = [0; 32];
+        let mut borrow = 0;
+        for index in (0..32).rev() {
+            let difference = bytes[index] as i16 - constants::CURVE_ORDER[index] as i16 - borrow;
+            reduced[index] = difference.rem_euclid(256) as u8;
+            borrow = (difference < 0) as i16;
+        }
+        Scalar::from_be_bytes(reduced).expect("reduced below the order")
+    })
+}
+
+// Arithmetic modulo the curve order on top of the secret key operations,
+// which reject zero.
+
+fn add(a: Scalar, b: Scalar) -> Scalar {
+    match SecretKey::from_slice(&a.to_be_bytes()) {
+        Ok(a) => a.add_tweak(&b).map(Scalar::from).unwrap_or(Scalar::ZERO),
+        Err(_) => b,
+    }
+}
+
+fn multiply(a: Scalar, b: Scalar) -> Scalar {
+    match SecretKey::from_slice(&a.to_be_bytes()) {
+        Ok(a) => a.mul_tweak(&b).map(Scalar::from).unwrap_or(Scalar::ZERO),
+        Err(_) => Scalar::ZERO,
+    }
+}
+
+fn negate(a: Scalar) -> Scalar {
+    SecretKey::from_slice(&a.to_be_bytes()).map(|a| Scalar::from(a.negate())).unwrap_or(Scalar::ZERO)
+}
+
+// Points, with `None` for the point at infinity.
+
+fn point_add(a: Option<PublicKey>, b: Option<PublicKey>) -> Option<PublicKey> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.combine(&b).ok(),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn point_mul(point: &PublicKey, k: Scalar) -> Option<PublicKey> {
+    point.mul_tweak(&SECP, &k).ok()
+}
+
+fn base_mul(k: Scalar) -> Option<PublicKey> {
+    SecretKey::from_slice(&k.to_be_bytes()).ok().map(|k| PublicKey::from_secret_key(&SECP, &k))
+}
+
+fn has_even_y(point: &PublicKey) -> bool {
+    point.x_only_public_key().1 == Parity::Even
+}
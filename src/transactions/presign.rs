@@ -0,0 +1,364 @@
+//! Pre-signing of a transaction graph by the verifier committee.
+//!
+//! Before the prover funds `kickoff`, each signer of the graph signs every
+//! script-path input listed by `TxGraph::signing_messages`. A
+//! `SigningSession` checks every signature as it arrives by running
+//! `<signer> OP_CHECKSIG` in the input's interpreter context. It only
+//! accepts 64-byte `SIGHASH_DEFAULT` signatures: an explicit sighash type
+//! such as NONE, SINGLE or ANYONECANPAY would let the signed input be
+//! moved into a transaction outside the graph. Once
+//! all signatures are in, the session yields a `PresignedSet`, which is
+//! stored as JSON:
+//!
+//! ```text
+//! {"version": 1, "graph": "<kickoff txid>", "signatures": [
+//!  {"tx": "assert", "input": 0, "signer": "<x-only key>", "signature": "<hex>"}]}
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use bitcoin::key::{Keypair, XOnlyPublicKey};
+use bitcoin::opcodes::all::OP_CHECKSIG;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::Txid;
+use serde_json::{json, Map, Value};
+
+use crate::execute_btc_script::{ExecCtx, ExecError, Options, TxTemplate};
+use crate::scripts::run::run_script;
+use crate::transactions::graph::{GraphTx, SigningMessage, TxGraph, TxKind};
+
+pub const STORE_VERSION: u64 = 1;
+
+/// Error raised while collecting or checking signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SigningError {
+    UnknownSigner(XOnlyPublicKey),
+    /// The input is not one the graph needs signed.
+    UnknownInput { kind: TxKind, input_idx: usize },
+    InvalidSignature { kind: TxKind, input_idx: usize, signer: XOnlyPublicKey, error: ExecError },
+    AlreadySigned { kind: TxKind, input_idx: usize, signer: XOnlyPublicKey },
+    /// Signatures are still missing for this many inputs and signers.
+    Incomplete { missing: usize },
+    /// The set was made for the graph whose kickoff has this txid.
+    WrongGraph(Txid),
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SigningError::UnknownSigner(signer) => write!(f, "{} is not a signer of the graph", signer),
+            SigningError::UnknownInput { kind, input_idx } => write!(f, "input {} of {:?} needs no signature", input_idx, kind),
+            SigningError::InvalidSignature { kind, input_idx, signer, error } => {
+                write!(f, "invalid signature of {} for input {} of {:?}: {:?}", signer, input_idx, kind, error)
+            }
+            SigningError::AlreadySigned { kind, input_idx, signer } => {
+                write!(f, "{} already signed input {} of {:?}", signer, input_idx, kind)
+            }
+            SigningError::Incomplete { missing } => write!(f, "{} signatures are missing", missing),
+            SigningError::WrongGraph(txid) => write!(f, "signatures are for the graph of kickoff {}", txid),
+        }
+    }
+}
+
+impl std::error::Error for SigningError {}
+
+/// Error raised while reading or writing a `PresignedSet`.
+#[derive(Debug)]
+pub enum StoreError {
+    Io(io::Error),
+    Syntax(serde_json::Error),
+    UnsupportedVersion(u64),
+    InvalidField { field: String, reason: String },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "cannot access signature store: {}", e),
+            StoreError::Syntax(e) => write!(f, "invalid JSON: {}", e),
+            StoreError::UnsupportedVersion(version) => write!(f, "unsupported store version {}", version),
+            StoreError::InvalidField { field, reason } => write!(f, "field {}: {}", field, reason),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Syntax(e)
+    }
+}
+
+/// One signer's signature for one input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialSignature {
+    pub kind: TxKind,
+    pub input_idx: usize,
+    pub signer: XOnlyPublicKey,
+    /// 64-byte BIP 340 signature; only `SIGHASH_DEFAULT` signatures are accepted.
+    pub signature: Vec<u8>,
+}
+
+/// Collects the signatures of a fixed set of signers for one graph.
+#[derive(Debug, Clone)]
+pub struct SigningSession {
+    graph: Txid,
+    signers: Vec<XOnlyPublicKey>,
+    messages: Vec<SigningMessage>,
+    /// Transaction of each message.
+    transactions: Vec<GraphTx>,
+    /// Signatures indexed by message, then signer.
+    signatures: Vec<Vec<Option<Vec<u8>>>>,
+}
+
+impl SigningSession {
+    pub fn new(graph: &TxGraph, signers: Vec<XOnlyPublicKey>) -> Self {
+        let messages = graph.signing_messages();
+        let transactions = messages.iter().map(|message| graph.tx(message.kind).clone()).collect();
+        let signatures = vec![vec![None; signers.len()]; messages.len()];
+        SigningSession { graph: graph.kickoff.txid(), signers, messages, transactions, signatures }
+    }
+
+    /// What every signer has to sign.
+    pub fn messages(&self) -> &[SigningMessage] {
+        &self.messages
+    }
+
+    pub fn signers(&self) -> &[XOnlyPublicKey] {
+        &self.signers
+    }
+
+    /// Checks `partial` against the input it signs and records it.
+    pub fn receive(&mut self, partial: PartialSignature) -> Result<(), SigningError> {
+        let signer = self.signers.iter().position(|&key| key == partial.signer)
+            .ok_or(SigningError::UnknownSigner(partial.signer))?;
+        let message = self.messages.iter()
+            .position(|message| message.kind == partial.kind && message.input_idx == partial.input_idx)
+            .ok_or(SigningError::UnknownInput { kind: partial.kind, input_idx: partial.input_idx })?;
+        if self.signatures[message][signer].is_some() {
+            return Err(SigningError::AlreadySigned { kind: partial.kind, input_idx: partial.input_idx, signer: partial.signer });
+        }
+        let template = self.transactions[message].tx_template(partial.input_idx);
+        if let Err(error) = check_signature(template, &partial.signer, &partial.signature) {
+            return Err(SigningError::InvalidSignature {
+                kind: partial.kind,
+                input_idx: partial.input_idx,
+                signer: partial.signer,
+                error,
+            });
+        }
+        self.signatures[message][signer] = Some(partial.signature);
+        Ok(())
+    }
+
+    /// Number of signatures still expected.
+    pub fn missing(&self) -> usize {
+        self.signatures.iter().flatten().filter(|signature| signature.is_none()).count()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing() == 0
+    }
+
+    pub fn finish(&self) -> Result<PresignedSet, SigningError> {
+        let missing = self.missing();
+        if missing > 0 {
+            return Err(SigningError::Incomplete { missing });
+        }
+        let signatures = self.messages.iter().zip(&self.signatures)
+            .flat_map(|(message, signatures)| {
+                self.signers.iter().zip(signatures).map(move |(&signer, signature)| PartialSignature {
+                    kind: message.kind,
+                    input_idx: message.input_idx,
+                    signer,
+                    signature: signature.clone().expect("session is complete"),
+                })
+            })
+            .collect();
+        Ok(PresignedSet { graph: self.graph, signatures })
+    }
+}
+
+/// Runs `<signer> OP_CHECKSIG` on `signature` in the context of `template`,
+/// rejecting signatures with an explicit sighash type.
+fn check_signature(template: TxTemplate, signer: &XOnlyPublicKey, signature: &[u8]) -> Result<(), ExecError> {
+    match signature.len() {
+        64 => {}
+        65 => return Err(ExecError::SchnorrSigHashtype),
+        _ => return Err(ExecError::SchnorrSigSize),
+    }
+    let script = Builder::new().push_x_only_key(signer).push_opcode(OP_CHECKSIG).into_script();
+    let run = run_script(ExecCtx::Tapscript, Options::default(), template, script, vec![signature.to_vec()])
+        .expect("checksig script is valid");
+    match run.result.error {
+        Some(error) => Err(error),
+        // OP_CHECKSIG pushes false for an empty signature.
+        None if !run.success() => Err(ExecError::SchnorrSigSize),
+        None => Ok(()),
+    }
+}
+
+/// Every signature a graph needs, from every signer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresignedSet {
+    /// Txid of the graph's kickoff.
+    pub graph: Txid,
+    pub signatures: Vec<PartialSignature>,
+}
+
+impl PresignedSet {
+    pub fn signature(&self, kind: TxKind, input_idx: usize, signer: &XOnlyPublicKey) -> Option<&[u8]> {
+        self.signatures.iter()
+            .find(|partial| partial.kind == kind && partial.input_idx == input_idx && partial.signer == *signer)
+            .map(|partial| &partial.signature[..])
+    }
+
+    /// Checks that the set holds a valid signature of each of `signers` for every input of `graph`.
+    pub fn verify(&self, graph: &TxGraph, signers: &[XOnlyPublicKey]) -> Result<(), SigningError> {
+        if self.graph != graph.kickoff.txid() {
+            return Err(SigningError::WrongGraph(self.graph));
+        }
+        let mut session = SigningSession::new(graph, signers.to_vec());
+        for partial in &self.signatures {
+            session.receive(partial.clone())?;
+        }
+        session.finish().map(|_| ())
+    }
+
+    pub fn to_json(&self) -> String {
+        let signatures: Vec<Value> = self.signatures.iter()
+            .map(|partial| {
+                json!({
+                    "tx": kind_name(partial.kind),
+                    "input": partial.input_idx,
+                    "signer": partial.signer.to_string(),
+                    "signature": hex::encode(&partial.signature),
+                })
+            })
+            .collect();
+        json!({"version": STORE_VERSION, "graph": self.graph.to_string(), "signatures": signatures}).to_string()
+    }
+
+    pub fn from_json(source: &str) -> Result<PresignedSet, StoreError> {
+        let value: Value = serde_json::from_str(source)?;
+        let store = object(&value, "store", &["version", "graph", "signatures"])?;
+        let version = store["version"].as_u64().ok_or_else(|| invalid("version", "expected a number"))?;
+        if version != STORE_VERSION {
+            return Err(StoreError::UnsupportedVersion(version));
+        }
+        let graph = Txid::from_str(string(&store["graph"], "graph")?).map_err(|_| invalid("graph", "expected a txid"))?;
+        let signatures = store["signatures"].as_array()
+            .ok_or_else(|| invalid("signatures", "expected an array"))?
+            .iter()
+            .map(partial_from_json)
+            .collect::<Result<_, _>>()?;
+        Ok(PresignedSet { graph, signatures })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StoreError> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<PresignedSet, StoreError> {
+        PresignedSet::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+fn kind_name(kind: TxKind) -> &'static str {
+    match kind {
+        TxKind::Kickoff => "kickoff",
+        TxKind::Assert => "assert",
+        TxKind::Challenge => "challenge",
+        TxKind::Take => "take",
+    }
+}
+
+fn partial_from_json(value: &Value) -> Result<PartialSignature, StoreError> {
+    let fields = object(value, "signature entry", &["tx", "input", "signer", "signature"])?;
+    let kind = match string(&fields["tx"], "tx")? {
+        "kickoff" => TxKind::Kickoff,
+        "assert" => TxKind::Assert,
+        "challenge" => TxKind::Challenge,
+        "take" => TxKind::Take,
+        other => return Err(invalid("tx", &format!("unknown transaction {}", other))),
+    };
+    let input_idx = fields["input"].as_u64().ok_or_else(|| invalid("input", "expected a number"))?;
+    let input_idx = usize::try_from(input_idx).map_err(|_| invalid("input", "index out of range"))?;
+    let signer = XOnlyPublicKey::from_str(string(&fields["signer"], "signer")?)
+        .map_err(|_| invalid("signer", "expected an x-only key in hex"))?;
+    let signature = hex::decode(string(&fields["signature"], "signature")?).map_err(|_| invalid("signature", "expected hex"))?;
+    Ok(PartialSignature { kind, input_idx, signer, signature })
+}
+
+fn invalid(field: &str, reason: &str) -> StoreError {
+    StoreError::InvalidField { field: field.to_string(), reason: reason.to_string() }
+}
+
+fn object<'a>(value: &'a Value, field: &str, keys: &[&str]) -> Result<&'a Map<String, Value>, StoreError> {
+    let map = value.as_object().ok_or_else(|| invalid(field, "expected an object"))?;
+    if map.len() != keys.len() || !keys.iter().all(|key| map.contains_key(*key)) {
+        return Err(invalid(field, &format!("expected exactly the fields {}", keys.join(", "))));
+    }
+    Ok(map)
+}
+
+fn string<'a>(value: &'a Value, field: &str) -> Result<&'a str, StoreError> {
+    value.as_str().ok_or_else(|| invalid(field, "expected a string"))
+}
+
+/// A committee member holding its key in process.
+#[derive(Debug, Clone)]
+pub struct Signer {
+    keypair: Keypair,
+}
+
+impl Signer {
+    pub fn new(keypair: Keypair) -> Self {
+        Signer { keypair }
+    }
+
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    /// `SIGHASH_DEFAULT` signatures of all `messages`.
+    pub fn sign(&self, messages: &[SigningMessage]) -> Vec<PartialSignature> {
+        let secp = Secp256k1::signing_only();
+        messages.iter()
+            .map(|message| {
+                let aux_rand: [u8; 32] = rand::random();
+                let signature = secp.sign_schnorr_with_aux_rand(&Message::from(message.sighash), &self.keypair, &aux_rand);
+                PartialSignature {
+                    kind: message.kind,
+                    input_idx: message.input_idx,
+                    signer: self.public_key(),
+                    signature: signature.as_ref().to_vec(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Runs a signing session of `signers` over `graph` in process.
+pub fn simulate(graph: &TxGraph, signers: &[Signer]) -> Result<PresignedSet, SigningError> {
+    let mut session = SigningSession::new(graph, signers.iter().map(Signer::public_key).collect());
+    for signer in signers {
+        for partial in signer.sign(session.messages()) {
+            session.receive(partial)?;
+        }
+    }
+    session.finish()
+}
@@ -1,29 +1,35 @@
-use risc0_zkvm::zkvm::{Prover, Verifier, Receipt};
+use risc0_zkvm::{default_prover, ExecutorEnv, Receipt};
 use crate::circuit_logic::BinaryCircuit;
-use std::io::Cursor;
 
-pub struct ZkProofs;
+/// Proves circuit executions with a RISC Zero guest image.
+pub struct ZkProofs {
+    guest_elf: Vec<u8>,
+    guest_id: [u32; 8],
+}
 
 impl ZkProofs {
-    pub fn new() -> Self {
-        ZkProofs
+    pub fn new(guest_elf: &[u8], guest_id: [u32; 8]) -> Self {
+        ZkProofs {
+            guest_elf: guest_elf.to_vec(),
+            guest_id,
+        }
     }
 
     pub fn generate_proof(&self, circuit: &BinaryCircuit) -> Result<Receipt, String> {
         let mut circuit_bytes = Vec::new();
-        for gate in &circuit.gates {
+        for gate in circuit.gates() {
             // Serialize each gate
-            circuit_bytes.extend_from_slice(&gate.serialize());
+            circuit_bytes.extend_from_slice(&gate.serialize_for_risc0());
         }
 
-        let mut prover = Prover::new();
-        let mut cursor = Cursor::new(circuit_bytes);
-        prover.add_input(&mut cursor).map_err(|e| e.to_string())?;
-        prover.run().map_err(|e| e.to_string())
+        let env = ExecutorEnv::builder()
+            .write_slice(&circuit_bytes)
+            .build()
+            .map_err(|e| e.to_string())?;
+        default_prover().prove_elf(env, &self.guest_elf).map_err(|e| e.to_string())
     }
 
     pub fn verify_proof(&self, receipt: &Receipt) -> Result<(), String> {
-        let mut verifier = Verifier::new();
-        verifier.verify(receipt).map_err(|e| e.to_string())
+        receipt.verify(self.guest_id).map_err(|e| e.to_string())
     }
 }
@@ -0,0 +1,518 @@
+use bitvm::merkle::{GateProof, MerkleProof, TraceTree};
+use bitvm::protocol::{ChallengeResponseProtocol, Response};
+use bitvm::{BinaryCircuit, CircuitBuilder, GateId, WireId};
+
+fn nand_circuit() -> BinaryCircuit {
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input();
+    let b = builder.input();
+    let nand = builder.nand(a, b);
+    let and = builder.not(nand);
+    builder.output(and);
+    builder.build()
+}
+
+#[test]
+fn accepts_responses_matching_the_trace() {
+    let circuit = nand_circuit();
+    let trace = circuit.evaluate(&[true, true]).unwrap();
+    let tree = TraceTree::new(&trace);
+
+    let mut protocol = ChallengeResponseProtocol::new();
+    protocol.commit_trace(tree.root());
+    let challenge = protocol.issue_challenge(&circuit, 4);
+    let honest = Response::new(&challenge, &circuit, &tree).unwrap();
+    assert_eq!(
+        honest.gate_outputs,
+        challenge.gate_indices.iter().map(|&i| trace[circuit.gate(i).output.0]).collect::<Vec<_>>()
+    );
+    assert!(protocol.process_response(&honest, &circuit));
+
+    let cheating = Response {
+        gate_outputs: honest.gate_outputs.iter().map(|v| !v).collect(),
+        ..honest.clone()
+    };
+    assert!(!protocol.process_response(&cheating, &circuit));
+}
+
+#[test]
+fn rejects_responses_without_challenge() {
+    let circuit = nand_circuit();
+    let tree = TraceTree::new(&circuit.evaluate(&[true, true]).unwrap());
+    let mut protocol = ChallengeResponseProtocol::new();
+    let response = Response { gate_outputs: vec![false], proofs: vec![tree.prove_gate(&circuit, GateId(0)).unwrap()] };
+    assert!(!protocol.process_response(&response, &circuit));
+    protocol.commit_trace(tree.root());
+    assert!(!protocol.process_response(&response, &circuit));
+}
+
+#[test]
+fn rejects_responses_from_another_or_broken_trace() {
+    let circuit = nand_circuit();
+    let trace = circuit.evaluate(&[true, false]).unwrap();
+    let tree = TraceTree::new(&trace);
+    let mut protocol = ChallengeResponseProtocol::new();
+    protocol.commit_trace(tree.root());
+    let challenge = protocol.issue_challenge(&circuit, 2);
+
+    // Proofs from the evaluation on other inputs do not match the committed root.
+    let other = TraceTree::new(&circuit.evaluate(&[true, true]).unwrap());
+    assert!(!protocol.process_response(&Response::new(&challenge, &circuit, &other).unwrap(), &circuit));
+
+    // Flipping the NAND output breaks both the NAND and the NOT reading it,
+    // so whichever gates are challenged, the gate check fails.
+    let mut broken = trace.clone();
+    broken[circuit.gate(GateId(0)).output.0] ^= true;
+    let broken = TraceTree::new(&broken);
+    protocol.commit_trace(broken.root());
+    let response = Response::new(&challenge, &circuit, &broken).unwrap();
+    assert!(response.proofs.iter().all(|proof| proof.verify(&circuit, &broken.root())));
+    assert!(!protocol.process_response(&response, &circuit));
+}
+
+#[test]
+fn merkle_proofs_bind_wire_values() {
+    let values: Vec<bool> = (0..11).map(|i| i % 3 == 1).collect();
+    let tree = TraceTree::new(&values);
+    for (wire, &value) in values.iter().enumerate() {
+        let proof = tree.prove(WireId(wire)).unwrap();
+        assert_eq!(proof.value, value);
+        assert_eq!(proof.siblings.len(), 4);
+        assert!(proof.verify(&tree.root()));
+
+        let flipped = MerkleProof { value: !value, ..proof.clone() };
+        assert!(!flipped.verify(&tree.root()));
+        // Moved next to its sibling, the proof only holds if that wire has the same value.
+        let moved = MerkleProof { wire: WireId(wire ^ 1), ..proof.clone() };
+        assert_eq!(moved.verify(&tree.root()), values.get(wire ^ 1) == Some(&value));
+        let outside = MerkleProof { wire: WireId(wire + 16), ..proof.clone() };
+        assert!(!outside.verify(&tree.root()));
+    }
+    assert_eq!(tree.prove(WireId(values.len())), None);
+    assert_ne!(TraceTree::new(&values[..10]).root(), TraceTree::new(&values[..9]).root());
+}
+
+#[test]
+fn merkle_proofs_longer_than_a_wire_index_are_rejected() {
+    let tree = TraceTree::new(&[true, false]);
+    let proof = tree.prove(WireId(1)).unwrap();
+    let with_siblings = |wire: usize, len: usize| {
+        MerkleProof { wire: WireId(wire), siblings: vec![proof.siblings[0]; len], ..proof.clone() }
+    };
+    let deepest = with_siblings(1, 63);
+    assert!(deepest.verify(&deepest.root()));
+    for &(wire, len) in &[(1, 64), (usize::MAX, 64), (1, 100)] {
+        let long = with_siblings(wire, len);
+        assert!(!long.verify(&long.root()));
+    }
+}
+
+#[test]
+fn gate_proofs_cover_the_gate_neighbourhood() {
+    let circuit = nand_circuit();
+    let tree = TraceTree::new(&circuit.evaluate(&[false, true]).unwrap());
+    let proof = tree.prove_gate(&circuit, GateId(0)).unwrap();
+    assert_eq!(proof.inputs.iter().map(|p| p.wire).collect::<Vec<_>>(), circuit.gate(GateId(0)).inputs);
+    assert!(proof.verify(&circuit, &tree.root()));
+    assert!(proof.gate_holds(&circuit));
+
+    let swapped = GateProof { gate: GateId(1), ..proof.clone() };
+    assert!(!swapped.verify(&circuit, &tree.root()));
+    assert_eq!(tree.prove_gate(&circuit, GateId(2)), None);
+}
+
+mod bisection {
+    use bitvm::bisection::{
+        simulate, BisectionError, Challenger, ChallengerMessage, ExecutionTrace, Outcome, Prover, ProverMessage,
+    };
+    use bitvm::op_code_circuits::create_op_code_circuit;
+    use bitvm::{BinaryCircuit, GateId};
+
+    fn adder() -> BinaryCircuit {
+        create_op_code_circuit("OP_ADD").unwrap()
+    }
+
+    fn inputs(circuit: &BinaryCircuit) -> Vec<bool> {
+        (0..circuit.inputs().len()).map(|i| i % 3 == 0).collect()
+    }
+
+    fn max_rounds(steps: usize) -> usize {
+        // One query per halving plus the final opening.
+        (usize::BITS - (steps - 1).leading_zeros()) as usize + 1
+    }
+
+    #[test]
+    fn honest_prover_ends_the_game_at_once() {
+        let circuit = adder();
+        let trace = ExecutionTrace::evaluate(&circuit, &inputs(&circuit)).unwrap();
+        let mut challenger = Challenger::new(trace.clone());
+        let transcript = simulate(&Prover::new(trace), &mut challenger).unwrap();
+        assert_eq!(transcript.outcome, Outcome::Agreed);
+        assert_eq!(transcript.rounds(), 0);
+        assert!(!transcript.outcome.prover_caught());
+    }
+
+    #[test]
+    fn bisection_finds_a_tampered_gate_in_logarithmic_rounds() {
+        let circuit = adder();
+        let honest = ExecutionTrace::evaluate(&circuit, &inputs(&circuit)).unwrap();
+        let steps = honest.steps();
+        for &step in &[0, 1, steps / 3, steps / 2, steps - 1] {
+            let gate = honest.gate_at(step).unwrap();
+            let mut values = honest.values().to_vec();
+            values[circuit.gate(gate).output.0] ^= true;
+            let cheating = Prover::new(ExecutionTrace::new(&circuit, values).unwrap());
+
+            let mut challenger = Challenger::new(honest.clone());
+            let transcript = simulate(&cheating, &mut challenger).unwrap();
+            assert_eq!(transcript.outcome, Outcome::FaultyGate { step, gate });
+            assert!(transcript.outcome.prover_caught());
+            assert!(transcript.rounds() <= max_rounds(steps), "{} rounds for {} steps", transcript.rounds(), steps);
+            assert_eq!(challenger.range(), (step, step + 1));
+        }
+    }
+
+    #[test]
+    fn bisection_catches_tampered_inputs_and_openings() {
+        let circuit = adder();
+        let honest = ExecutionTrace::evaluate(&circuit, &inputs(&circuit)).unwrap();
+
+        let mut values = honest.values().to_vec();
+        values[circuit.inputs()[0].0] ^= true;
+        let cheating = Prover::new(ExecutionTrace::new(&circuit, values).unwrap());
+        let transcript = simulate(&cheating, &mut Challenger::new(honest.clone())).unwrap();
+        assert!(matches!(transcript.outcome, Outcome::InconsistentOpening { step: 0, .. }));
+
+        // An opening that disagrees with the prover's earlier digest.
+        let gate = honest.gate_at(5).unwrap();
+        let mut values = honest.values().to_vec();
+        values[circuit.gate(gate).output.0] ^= true;
+        let cheating = Prover::new(ExecutionTrace::new(&circuit, values).unwrap());
+        let mut challenger = Challenger::new(honest.clone());
+        let mut query = challenger.receive(&cheating.claim()).unwrap();
+        while let Some(ChallengerMessage::Query { .. }) = query {
+            query = challenger.receive(&cheating.respond(query.as_ref().unwrap()).unwrap()).unwrap();
+        }
+        assert_eq!(query, Some(ChallengerMessage::Open { step: 5 }));
+        let (inputs, output) = honest.opening(5).unwrap();
+        assert_eq!(challenger.receive(&ProverMessage::Opening { step: 5, inputs, output }), Ok(None));
+        assert_eq!(challenger.outcome(), Some(&Outcome::InconsistentOpening { step: 5, gate }));
+    }
+
+    #[test]
+    fn wrong_challenger_loses_against_honest_prover() {
+        let circuit = adder();
+        let honest = ExecutionTrace::evaluate(&circuit, &inputs(&circuit)).unwrap();
+        let gate = honest.gate_at(7).unwrap();
+        let mut values = honest.values().to_vec();
+        values[circuit.gate(gate).output.0] ^= true;
+        let mut challenger = Challenger::new(ExecutionTrace::new(&circuit, values).unwrap());
+        let transcript = simulate(&Prover::new(honest), &mut challenger).unwrap();
+        assert_eq!(transcript.outcome, Outcome::GateHolds { step: 7, gate });
+        assert!(!transcript.outcome.prover_caught());
+    }
+
+    #[test]
+    fn rejects_messages_out_of_turn() {
+        let circuit = adder();
+        let honest = ExecutionTrace::evaluate(&circuit, &inputs(&circuit)).unwrap();
+        let prover = Prover::new(honest.clone());
+        let mut challenger = Challenger::new(honest.clone());
+        let digest = honest.state_digest(3);
+        assert_eq!(challenger.receive(&ProverMessage::Digest { step: 3, digest }), Err(BisectionError::UnexpectedMessage));
+        assert_eq!(challenger.receive(&prover.claim()), Ok(None));
+        assert_eq!(challenger.receive(&prover.claim()), Err(BisectionError::UnexpectedMessage));
+        assert_eq!(
+            prover.respond(&ChallengerMessage::Open { step: honest.steps() }),
+            Err(BisectionError::StepOutOfRange(honest.steps()))
+        );
+        assert_eq!(
+            ExecutionTrace::new(&circuit, vec![true]).err(),
+            Some(BisectionError::TraceLength { expected: circuit.wire_count(), found: 1 })
+        );
+        assert_eq!(honest.gate_at(honest.steps()), None::<GateId>);
+    }
+}
+
+mod messages {
+    use bitvm::merkle::TraceTree;
+    use bitvm::messages::{Envelope, MessageError, Payload, ReplayGuard, Session, SessionId, FORMAT_VERSION};
+    use bitvm::protocol::{Challenge, Response};
+    use bitvm::GateId;
+
+    fn payloads() -> Vec<Payload> {
+        let circuit = super::nand_circuit();
+        let tree = TraceTree::new(&circuit.evaluate(&[true, false]).unwrap());
+        let challenge = Challenge { gate_indices: vec![GateId(1), GateId(0)] };
+        let response = Response::new(&challenge, &circuit, &tree).unwrap();
+        vec![
+            Payload::Commitment { root: tree.root() },
+            Payload::Challenge(challenge),
+            Payload::Challenge(Challenge { gate_indices: vec![] }),
+            Payload::Response(response),
+        ]
+    }
+
+    #[test]
+    fn envelopes_round_trip_through_json_and_binary() {
+        let mut session = Session::new(SessionId([7; 16]));
+        for payload in payloads() {
+            let envelope = session.seal(payload);
+            assert_eq!(Envelope::from_json(&envelope.to_json()).unwrap(), envelope);
+            let bytes = envelope.to_bytes().unwrap();
+            assert_eq!(bytes[0], FORMAT_VERSION);
+            assert!(bytes.len() < envelope.to_json().len());
+            assert_eq!(Envelope::from_bytes(&bytes).unwrap(), envelope);
+        }
+    }
+
+    #[test]
+    fn json_decoding_is_strict() {
+        let envelope = Session::new(SessionId([1; 16])).seal(Payload::Challenge(Challenge { gate_indices: vec![GateId(3)] }));
+        let json = envelope.to_json();
+        assert!(json.contains(r#""type":"challenge""#));
+        let decode = |source: &str| Envelope::from_json(source).unwrap_err();
+
+        assert!(matches!(decode(&json.replace(r#""version":1"#, r#""version":2"#)), MessageError::UnsupportedVersion(2)));
+        assert!(matches!(decode(&json.replace("challenge", "query")), MessageError::UnknownType(t) if t == "query"));
+        assert!(matches!(decode(&json.replace(r#""nonce""#, r#""extra":0,"nonce""#)), MessageError::InvalidField { field, .. } if field == "message.extra"));
+        assert!(matches!(decode(&json.replace(r#""gate_indices":[3]"#, r#""gate_indices":[-3]"#)), MessageError::InvalidField { .. }));
+        assert!(matches!(decode(&json.replace("01010101", "0101")), MessageError::InvalidField { field, .. } if field == "session"));
+        let upper = Session::new(SessionId([0xab; 16])).seal(Payload::Challenge(Challenge { gate_indices: vec![] })).to_json();
+        assert!(matches!(decode(&upper.replace("abab", "ABAB")), MessageError::InvalidField { field, .. } if field == "session"));
+        assert!(matches!(decode(&json[1..]), MessageError::Syntax(_)));
+
+        let commitment = Session::new(SessionId([1; 16])).seal(payloads().remove(0)).to_json();
+        let root = commitment.split(r#""root":""#).nth(1).unwrap()[..64].to_string();
+        let upper = commitment.replace(&root, &root.to_uppercase());
+        assert!(matches!(decode(&upper), MessageError::InvalidField { field, .. } if field == "payload.root"));
+        let missing = commitment.replace(&format!(r#""root":"{}","#, root), "");
+        assert!(matches!(decode(&missing), MessageError::InvalidField { field, .. } if field == "payload.root"));
+    }
+
+    #[test]
+    fn binary_decoding_is_strict() {
+        let envelope = Session::new(SessionId([2; 16])).seal(payloads().pop().unwrap());
+        let bytes = envelope.to_bytes().unwrap();
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(Envelope::from_bytes(&trailing), Err(MessageError::TrailingBytes(1))));
+        for end in 0..bytes.len() {
+            assert!(matches!(Envelope::from_bytes(&bytes[..end]), Err(MessageError::Truncated)), "{} bytes", end);
+        }
+        let mut version = bytes.clone();
+        version[0] = 9;
+        assert!(matches!(Envelope::from_bytes(&version), Err(MessageError::UnsupportedVersion(9))));
+        let mut kind = bytes.clone();
+        kind[25] = 7;
+        assert!(matches!(Envelope::from_bytes(&kind), Err(MessageError::UnknownType(_))));
+        // The first gate output follows the tag and the output count.
+        let mut boolean = bytes;
+        boolean[30] = 2;
+        assert!(matches!(Envelope::from_bytes(&boolean), Err(MessageError::InvalidBool(2))));
+    }
+
+    #[test]
+    fn decoding_rejects_proofs_deeper_than_a_wire_index() {
+        let mut response = match payloads().pop().unwrap() {
+            Payload::Response(response) => response,
+            _ => unreachable!(),
+        };
+        let sibling = response.proofs[0].output.siblings[0];
+        response.proofs[0].output.siblings = vec![sibling; 63];
+        let mut session = Session::new(SessionId([4; 16]));
+        let deepest = session.seal(Payload::Response(response.clone()));
+        assert_eq!(Envelope::from_json(&deepest.to_json()).unwrap(), deepest);
+        assert_eq!(Envelope::from_bytes(&deepest.to_bytes().unwrap()).unwrap(), deepest);
+
+        response.proofs[0].output.siblings.push(sibling);
+        let deeper = session.seal(Payload::Response(response));
+        let too_deep = |error| matches!(error, MessageError::InvalidField { field, .. } if field == "proof.siblings");
+        assert!(too_deep(Envelope::from_json(&deeper.to_json()).unwrap_err()));
+        assert!(too_deep(Envelope::from_bytes(&deeper.to_bytes().unwrap()).unwrap_err()));
+    }
+
+    #[test]
+    fn binary_encoding_rejects_wide_indices() {
+        let wide = u32::MAX as usize + 1;
+        let envelope = Session::new(SessionId([3; 16])).seal(Payload::Challenge(Challenge { gate_indices: vec![GateId(wide)] }));
+        assert!(matches!(envelope.to_bytes(), Err(MessageError::IndexOutOfRange(index)) if index == wide));
+    }
+
+    #[test]
+    fn replay_guard_accepts_each_message_once() {
+        let id = SessionId::random();
+        let mut sender = Session::new(id);
+        let first = sender.seal(Payload::Challenge(Challenge { gate_indices: vec![GateId(0)] }));
+        let second = sender.seal(Payload::Challenge(Challenge { gate_indices: vec![GateId(1)] }));
+        assert!(second.nonce > first.nonce);
+
+        let mut guard = ReplayGuard::new(id);
+        assert_eq!(guard.accept(first.clone()).unwrap(), first.payload);
+        assert!(matches!(guard.accept(first.clone()), Err(MessageError::Replayed { nonce: 1, last: 1 })));
+        assert!(guard.accept(second.clone()).is_ok());
+        assert!(matches!(guard.accept(first), Err(MessageError::Replayed { nonce: 1, last: 2 })));
+
+        let foreign = Session::new(SessionId([0; 16])).seal(second.payload);
+        assert!(matches!(guard.accept(foreign), Err(MessageError::WrongSession(session)) if session == SessionId([0; 16])));
+    }
+}
+
+mod deadlines {
+    use bitvm::deadlines::{ClockError, Deadline, DisputeClock, Party};
+    use bitvm::merkle::TraceTree;
+    use bitvm::protocol::{ChallengeResponseProtocol, ProtocolState, Response};
+
+    #[test]
+    fn prover_must_answer_before_the_deadline() {
+        let circuit = super::nand_circuit();
+        let tree = TraceTree::new(&circuit.evaluate(&[true, true]).unwrap());
+
+        let mut protocol = ChallengeResponseProtocol::with_response_timeout(6);
+        protocol.commit_trace(tree.root());
+        assert_eq!(protocol.state(), ProtocolState::Idle);
+        let challenge = protocol.issue_challenge_at(&circuit, 2, 100);
+        assert_eq!(protocol.state(), ProtocolState::AwaitingResponse(Deadline::new(100, 6)));
+        let response = Response::new(&challenge, &circuit, &tree).unwrap();
+        assert!(!protocol.check_timeout(105));
+        assert!(protocol.process_response_at(&response, &circuit, 105));
+        assert_eq!(protocol.state(), ProtocolState::Answered);
+        assert!(!protocol.check_timeout(200));
+
+        let challenge = protocol.issue_challenge_at(&circuit, 2, 200);
+        let response = Response::new(&challenge, &circuit, &tree).unwrap();
+        assert!(!protocol.process_response_at(&response, &circuit, 206));
+        assert_eq!(protocol.state(), ProtocolState::TimedOut);
+        assert!(protocol.check_timeout(206));
+    }
+
+    #[test]
+    fn invalid_responses_leave_the_clock_running() {
+        let circuit = super::nand_circuit();
+        let tree = TraceTree::new(&circuit.evaluate(&[true, true]).unwrap());
+        let mut protocol = ChallengeResponseProtocol::new();
+        protocol.commit_trace(tree.root());
+        let challenge = protocol.issue_challenge_at(&circuit, 1, 10);
+        let mut response = Response::new(&challenge, &circuit, &tree).unwrap();
+        response.gate_outputs[0] ^= true;
+        assert!(!protocol.process_response_at(&response, &circuit, 11));
+        assert_eq!(protocol.state(), ProtocolState::AwaitingResponse(Deadline::new(10, 144)));
+        assert!(protocol.check_timeout(154));
+    }
+
+    #[test]
+    fn dispute_clock_alternates_rounds() {
+        let mut clock = DisputeClock::new(Party::Prover, 1_000, 10);
+        assert_eq!(clock.record_move(Party::Challenger, 1_001), Err(ClockError::NotYourTurn(Party::Challenger)));
+        assert_eq!(clock.record_move(Party::Prover, 1_009), Ok(()));
+        assert_eq!(clock.turn(), Party::Challenger);
+        assert_eq!(clock.deadline().height(), 1_019);
+        assert_eq!(clock.check_timeout(1_018), None);
+        assert_eq!(
+            clock.record_move(Party::Challenger, 1_019),
+            Err(ClockError::Late { party: Party::Challenger, deadline: 1_019, height: 1_019 })
+        );
+        assert_eq!(clock.check_timeout(1_019), Some(Party::Prover));
+        assert_eq!(clock.record_move(Party::Challenger, 1_018), Err(ClockError::Over(Party::Prover)));
+        assert_eq!(clock.deadline().sequence().to_consensus_u32(), 10);
+    }
+}
+
+mod on_chain_dispute {
+    use bitcoin::opcodes::all::*;
+    use bitcoin::script::Builder;
+    use bitvm::gate_scripts::{gate_scripts, opening_witness, GateScript};
+    use bitvm::on_chain_dispute::{ChallengerReason, DisputeEngine, DisputeLimits, Verdict};
+    use bitvm::execute_btc_script::ExecError;
+    use bitvm::{CircuitBuilder, CommitmentKeys};
+
+    fn nand_leaf(keys: &CommitmentKeys) -> GateScript {
+        let mut builder = CircuitBuilder::new();
+        let a = builder.input();
+        let b = builder.input();
+        let nand = builder.nand(a, b);
+        builder.output(nand);
+        let circuit = builder.build();
+        gate_scripts(&circuit, &keys.commit_circuit(&circuit)).unwrap().remove(0)
+    }
+
+    fn openings(keys: &CommitmentKeys, leaf: &GateScript, a: bool, b: bool, output: bool) -> Vec<Vec<u8>> {
+        opening_witness(&keys.preimage(leaf.inputs[0], a), &keys.preimage(leaf.inputs[1], b), &keys.preimage(leaf.output, output))
+    }
+
+    #[test]
+    fn honest_prover_wins() {
+        let keys = CommitmentKeys::from_seed(b"dispute");
+        let leaf = nand_leaf(&keys);
+        let engine = DisputeEngine::default();
+        let honest = openings(&keys, &leaf, true, false, true);
+        assert_eq!(engine.resolve(&leaf.script, &honest, None), Verdict::ProverWins);
+        // Openings that do not match the commitments cannot contradict the prover.
+        let forged = opening_witness(&[1; 20], &[2; 20], &[3; 20]);
+        assert_eq!(engine.resolve(&leaf.script, &honest, Some(&forged)), Verdict::ProverWins);
+        // Nor can honest openings padded with a junk item underneath them.
+        let padded: Vec<Vec<u8>> = Some(vec![7]).into_iter().chain(honest.clone()).collect();
+        assert_eq!(engine.resolve(&leaf.script, &honest, Some(&padded)), Verdict::ProverWins);
+    }
+
+    #[test]
+    fn challenger_wins_on_a_broken_gate() {
+        let keys = CommitmentKeys::from_seed(b"dispute");
+        let leaf = nand_leaf(&keys);
+        let engine = DisputeEngine::default();
+        let wrong = openings(&keys, &leaf, true, true, true);
+        assert_eq!(engine.resolve(&leaf.script, &wrong, None), Verdict::ChallengerWins(ChallengerReason::GateViolated));
+
+        // The prover equivocated: its defence holds but it also revealed a violating output.
+        let honest = openings(&keys, &leaf, true, true, false);
+        assert_eq!(
+            engine.resolve(&leaf.script, &honest, Some(&wrong)),
+            Verdict::ChallengerWins(ChallengerReason::Contradicted)
+        );
+    }
+
+    #[test]
+    fn challenger_wins_on_an_invalid_witness() {
+        let keys = CommitmentKeys::from_seed(b"dispute");
+        let leaf = nand_leaf(&keys);
+        let engine = DisputeEngine::default();
+        let forged = opening_witness(&keys.preimage(leaf.inputs[0], true), &[0; 20], &keys.preimage(leaf.output, true));
+        assert_eq!(
+            engine.resolve(&leaf.script, &forged, None),
+            Verdict::ChallengerWins(ChallengerReason::ScriptFailed(ExecError::EqualVerify))
+        );
+        let oversized = vec![vec![0; 81]];
+        assert_eq!(
+            engine.resolve(&leaf.script, &oversized, None),
+            Verdict::ChallengerWins(ChallengerReason::WitnessItemTooLarge { index: 0, size: 81, limit: 80 })
+        );
+    }
+
+    #[test]
+    fn enforces_resource_limits() {
+        let keys = CommitmentKeys::from_seed(b"dispute");
+        let leaf = nand_leaf(&keys);
+        let honest = openings(&keys, &leaf, false, false, true);
+        let small = DisputeEngine::new(DisputeLimits { max_script_size: leaf.script.len() - 1, ..DisputeLimits::default() });
+        assert_eq!(
+            small.resolve(&leaf.script, &honest, None),
+            Verdict::ChallengerWins(ChallengerReason::ScriptTooLarge { size: leaf.script.len(), limit: leaf.script.len() - 1 })
+        );
+
+        let mut builder = Builder::new();
+        for _ in 0..10 {
+            builder = builder.push_int(1);
+        }
+        for _ in 0..9 {
+            builder = builder.push_opcode(OP_DROP);
+        }
+        let script = builder.into_script();
+        let tight = DisputeEngine::new(DisputeLimits { max_stack_items: 9, ..DisputeLimits::default() });
+        assert!(matches!(
+            tight.resolve(&script, &[], None),
+            Verdict::ChallengerWins(ChallengerReason::StackLimit(stats)) if stats.max_nb_stack_items == 10
+        ));
+        assert_eq!(DisputeEngine::default().resolve(&script, &[], None), Verdict::ProverWins);
+    }
+}
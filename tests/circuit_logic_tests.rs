@@ -0,0 +1,751 @@
+use bitvm::{BinaryCircuit, CircuitBuilder, EvalError, GateId, GateType, LogicGate, WireId};
+
+fn half_adder() -> (BinaryCircuit, WireId, WireId) {
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input();
+    let b = builder.input();
+    let sum = builder.xor(a, b);
+    let carry = builder.and(a, b);
+    builder.output(sum);
+    builder.output(carry);
+    (builder.build(), sum, carry)
+}
+
+#[test]
+fn evaluates_every_wire() {
+    let (circuit, sum, carry) = half_adder();
+    for &(a, b) in &[(false, false), (false, true), (true, false), (true, true)] {
+        let trace = circuit.evaluate(&[a, b]).unwrap();
+        assert_eq!(trace.len(), 4);
+        assert_eq!(trace[sum.0], a ^ b);
+        assert_eq!(trace[carry.0], a && b);
+        assert_eq!(circuit.output_values(&trace), vec![a ^ b, a && b]);
+    }
+}
+
+#[test]
+fn schedules_gates_added_out_of_order() {
+    let mut circuit = BinaryCircuit::new();
+    let a = circuit.add_input();
+    let (one, out) = (WireId(1), WireId(2));
+    // The NAND gate is added before the gate driving one of its inputs.
+    let nand = circuit.add_gate(LogicGate::new(GateType::NAND, vec![a, one], out));
+    let constant = circuit.add_gate(LogicGate::new(GateType::Constant(true), vec![], one));
+    circuit.add_output(out);
+
+    assert_eq!(circuit.topological_order().unwrap(), vec![constant, nand]);
+    assert_eq!(circuit.evaluate(&[true]).unwrap(), vec![true, true, false]);
+    assert_eq!(circuit.evaluate(&[false]).unwrap(), vec![false, true, true]);
+}
+
+#[test]
+fn rejects_cycles() {
+    let mut circuit = BinaryCircuit::new();
+    let a = circuit.add_input();
+    circuit.add_gate(LogicGate::new(GateType::AND, vec![a, WireId(2)], WireId(1)));
+    circuit.add_gate(LogicGate::new(GateType::NOT, vec![WireId(1)], WireId(2)));
+    assert_eq!(circuit.evaluate(&[true]), Err(EvalError::Cycle(vec![GateId(0), GateId(1)])));
+}
+
+#[test]
+fn rejects_dangling_and_doubly_driven_wires() {
+    let mut circuit = BinaryCircuit::new();
+    let a = circuit.add_input();
+    circuit.add_gate(LogicGate::new(GateType::XOR, vec![a, WireId(7)], WireId(1)));
+    assert_eq!(circuit.evaluate(&[true]), Err(EvalError::DanglingWire { gate: GateId(0), wire: WireId(7) }));
+
+    let mut circuit = BinaryCircuit::new();
+    let a = circuit.add_input();
+    circuit.add_gate(LogicGate::new(GateType::NOT, vec![a], WireId(1)));
+    circuit.add_gate(LogicGate::new(GateType::NOT, vec![a], WireId(1)));
+    assert_eq!(circuit.evaluate(&[true]), Err(EvalError::MultipleDrivers(WireId(1))));
+
+    let mut circuit = BinaryCircuit::new();
+    let a = circuit.add_input();
+    circuit.add_gate(LogicGate::new(GateType::NOT, vec![a], a));
+    assert_eq!(circuit.evaluate(&[true]), Err(EvalError::MultipleDrivers(a)));
+
+    let mut circuit = BinaryCircuit::new();
+    circuit.add_input();
+    circuit.add_output(WireId(3));
+    assert_eq!(circuit.evaluate(&[true]), Err(EvalError::UndrivenOutput(WireId(3))));
+}
+
+#[test]
+fn rejects_bad_inputs_and_gates() {
+    let (circuit, _, _) = half_adder();
+    assert_eq!(
+        circuit.evaluate(&[true]),
+        Err(EvalError::InputCountMismatch { expected: 2, found: 1 })
+    );
+
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input();
+    builder.gate(GateType::NOT, &[a, a]);
+    assert_eq!(
+        builder.build().evaluate(&[true]),
+        Err(EvalError::InvalidGate { gate: GateId(0), gate_type: GateType::NOT, operands: 2 })
+    );
+
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input();
+    builder.gate(GateType::SHA256Round, &[a]);
+    assert!(matches!(builder.build().evaluate(&[true]), Err(EvalError::InvalidGate { gate: GateId(0), .. })));
+}
+
+#[test]
+fn builder_declares_value_widths() {
+    let mut builder = CircuitBuilder::new();
+    let x = builder.inputs(3);
+    let y = builder.input();
+    let parity = builder.gate(GateType::XOR, &[x[0], x[1], x[2], y]);
+    let none = builder.gate(GateType::NAND, &[x[0], y]);
+    builder.outputs(&[parity, none]);
+    let circuit = builder.build();
+
+    assert_eq!(circuit.inputs(), &[WireId(0), WireId(1), WireId(2), WireId(3)]);
+    assert_eq!(circuit.input_widths(), &[3, 1]);
+    assert_eq!(circuit.output_widths(), &[2]);
+    let trace = circuit.evaluate(&[true, true, false, true]).unwrap();
+    assert_eq!(circuit.output_values(&trace), vec![true, false]);
+}
+
+const ADDER_32: &str = include_str!("../src/circuit_logic/32bit_sha256.txt.txt");
+
+/// Bristol corpus circuits store values most significant bit first.
+fn to_bits(value: u32) -> Vec<bool> {
+    (0..32).rev().map(|i| (value >> i) & 1 == 1).collect()
+}
+
+fn from_bits(bits: &[bool]) -> u32 {
+    bits.iter().fold(0, |acc, &bit| (acc << 1) | bit as u32)
+}
+
+#[test]
+fn parses_shipped_bristol_adder() {
+    let circuit = BinaryCircuit::from_bristol_format(ADDER_32).unwrap();
+    assert_eq!(circuit.input_widths(), &[32, 32]);
+    assert_eq!(circuit.output_widths(), &[32]);
+    assert_eq!(circuit.outputs().len(), 32);
+
+    for &(a, b) in &[(0u32, 0u32), (1, 1), (0xffff_ffff, 1), (0x1234_5678, 0x9abc_def0)] {
+        let mut inputs = to_bits(a);
+        inputs.extend(to_bits(b));
+        let trace = circuit.evaluate(&inputs).unwrap();
+        assert_eq!(from_bits(&circuit.output_values(&trace)), a.wrapping_add(b));
+    }
+}
+
+#[test]
+fn bristol_round_trip_is_stable() {
+    let circuit = BinaryCircuit::from_bristol_format(ADDER_32).unwrap();
+    let written = circuit.to_bristol_format().unwrap();
+    let reparsed = BinaryCircuit::from_bristol_format(&written).unwrap();
+    assert_eq!(reparsed.to_bristol_format().unwrap(), written);
+
+    let inputs = [to_bits(0xdead_beef), to_bits(0x0bad_f00d)].concat();
+    let expected = circuit.output_values(&circuit.evaluate(&inputs).unwrap());
+    assert_eq!(reparsed.output_values(&reparsed.evaluate(&inputs).unwrap()), expected);
+}
+
+#[test]
+fn parses_every_bristol_gate() {
+    // Inputs a, b, c on wires 0..3, outputs on wires 6..10.
+    let data = "6 10\n2 2 1\n1 4\n\n\
+        1 1 0 3 INV\n\
+        1 1 1 4 EQ\n\
+        4 2 1 2 3 3 5 6 MAND\n\
+        2 1 5 4 7 XOR\n\
+        1 1 0 8 EQW\n\
+        1 1 4 9 EQW\n";
+    let circuit = BinaryCircuit::from_bristol_format(data).unwrap();
+    for &(a, b, c) in &[(false, false, true), (true, false, true), (false, true, false), (true, true, true)] {
+        let trace = circuit.evaluate(&[a, b, c]).unwrap();
+        assert_eq!(circuit.output_values(&trace), vec![c && !a, a || !b, a, true]);
+    }
+
+    let written = circuit.to_bristol_format().unwrap();
+    assert!(written.contains("EQW"));
+    let reparsed = BinaryCircuit::from_bristol_format(&written).unwrap();
+    assert_eq!(reparsed.to_bristol_format().unwrap(), written);
+}
+
+#[test]
+fn reports_bristol_errors() {
+    use bitvm::BristolError;
+
+    assert_eq!(BinaryCircuit::from_bristol_format("").err(), Some(BristolError::MissingHeader));
+    assert!(matches!(
+        BinaryCircuit::from_bristol_format("1 3\n1 2\n1 1\n2 1 0 5 2 AND\n"),
+        Err(BristolError::Malformed { line: 4, .. })
+    ));
+    assert_eq!(
+        BinaryCircuit::from_bristol_format("1 4\n1 2\n1 1\n2 1 0 2 3 AND\n").err(),
+        Some(BristolError::UndefinedWire { line: 4, wire: 2 })
+    );
+    assert_eq!(
+        BinaryCircuit::from_bristol_format("2 3\n1 2\n1 1\n2 1 0 1 2 AND\n2 1 0 1 2 XOR\n").err(),
+        Some(BristolError::RedefinedWire { line: 5, wire: 2 })
+    );
+    assert_eq!(
+        BinaryCircuit::from_bristol_format("1 3\n1 2\n1 1\n2 1 0 1 2 OR\n").err(),
+        Some(BristolError::UnknownGate { line: 4, name: "OR".to_string() })
+    );
+    assert_eq!(
+        BinaryCircuit::from_bristol_format("2 3\n1 2\n1 1\n2 1 0 1 2 AND\n").err(),
+        Some(BristolError::GateCountMismatch { declared: 2, found: 1 })
+    );
+}
+
+#[test]
+fn bristol_wire_counts_are_not_reserved_up_front() {
+    // The header announces 2^60 wires; only the three used ones are stored.
+    let data = "1 1152921504606846976\n1 2\n1 1\n2 1 0 1 1152921504606846975 AND\n";
+    let circuit = BinaryCircuit::from_bristol_format(data).unwrap();
+    assert_eq!(circuit.wire_count(), 3);
+    assert_eq!(circuit.gates().len(), 1);
+    assert_eq!(
+        BinaryCircuit::from_bristol_format("1 1152921504606846976\n1 2\n1 1\n2 1 0 7 9 AND\n").err(),
+        Some(bitvm::BristolError::UndefinedWire { line: 4, wire: 7 })
+    );
+}
+
+#[test]
+fn bristol_headers_are_checked_before_allocating() {
+    let malformed = |data: &str| match BinaryCircuit::from_bristol_format(data) {
+        Err(bitvm::BristolError::Malformed { line, .. }) => line,
+        other => panic!("expected a malformed header, got {:?}", other.map(|c| c.wire_count())),
+    };
+    // The output widths add up past usize::MAX.
+    assert_eq!(malformed("0 5\n2 18446744073709551615 2\n1 1\n"), 2);
+    // 10^11 inputs that no gate reads are refused instead of allocated.
+    assert_eq!(malformed("0 100000000000\n1 100000000000\n0\n"), 2);
+    // The gate arity overflows when added up.
+    assert_eq!(malformed("1 3\n1 1\n1 1\n18446744073709551615 1 0 1 INV\n"), 4);
+}
+
+#[test]
+fn analyzes_shipped_adder() {
+    let adder = BinaryCircuit::from_bristol_format(ADDER_32).unwrap();
+    let analysis = adder.analyze().unwrap();
+    assert_eq!(analysis.gate_count, 154);
+    assert_eq!(analysis.gates_by_type, [(GateType::AND, 31), (GateType::XOR, 123)]);
+    assert_eq!((analysis.and_gates, analysis.free_gates), (31, 123));
+    assert_eq!((analysis.input_wires, analysis.output_wires, analysis.wire_count), (64, 32, 218));
+    assert_eq!(analysis.multiplicative_depth, 31);
+    assert_eq!(analysis.depth, analysis.critical_path.len());
+    assert_eq!(analysis.fan_out.values().sum::<usize>(), 64 + 154);
+    assert_eq!(analysis.max_fan_out(), 3);
+
+    // The critical path is a chain of gates ending at an output.
+    let path = &analysis.critical_path;
+    for pair in path.windows(2) {
+        assert!(adder.gate(pair[1]).inputs.contains(&adder.gate(pair[0]).output));
+    }
+    assert!(adder.outputs().contains(&adder.gate(*path.last().unwrap()).output));
+}
+
+#[test]
+fn analysis_counts_depth_per_gate_kind() {
+    let (circuit, _, _) = half_adder();
+    let analysis = circuit.analyze().unwrap();
+    assert_eq!((analysis.depth, analysis.multiplicative_depth), (1, 1));
+    assert_eq!(analysis.fan_out.get(&2), Some(&2));
+
+    let mut builder = CircuitBuilder::new();
+    let [a, b] = [builder.input(), builder.input()];
+    let mut acc = builder.xor(a, b);
+    for _ in 0..5 {
+        acc = builder.not(acc);
+    }
+    let and = builder.and(acc, a);
+    builder.output(and);
+    let analysis = builder.build().analyze().unwrap();
+    assert_eq!((analysis.depth, analysis.multiplicative_depth), (7, 1));
+    assert!(analysis.to_string().contains("critical path: 7 gates"));
+}
+
+const ADDER_32_JSON: &str = include_str!("../src/circuit_logic/bitcoin_compatible_circuit.json");
+
+#[test]
+fn loads_shipped_json_circuit() {
+    let circuit = BinaryCircuit::from_json(ADDER_32_JSON).unwrap();
+    let bristol = BinaryCircuit::from_bristol_format(ADDER_32).unwrap();
+    assert_eq!(circuit.to_bristol_format().unwrap(), bristol.to_bristol_format().unwrap());
+
+    let inputs = [to_bits(0x8000_0001), to_bits(0x7fff_ffff)].concat();
+    let trace = circuit.evaluate(&inputs).unwrap();
+    assert_eq!(from_bits(&circuit.output_values(&trace)), 0x8000_0001u32.wrapping_add(0x7fff_ffff));
+}
+
+#[test]
+fn json_round_trip() {
+    let circuit = BinaryCircuit::from_json(ADDER_32_JSON).unwrap();
+    let json = circuit.to_json().unwrap();
+    assert!(json.starts_with("[{\"type\": \"154\", \"parameters\": [219]}, {\"type\": \"2\", \"parameters\": [33, 33]}"));
+    let reparsed = BinaryCircuit::from_json(&json).unwrap();
+    assert_eq!(reparsed.to_json().unwrap(), json);
+}
+
+#[test]
+fn json_errors_point_at_components() {
+    use bitvm::{BristolError, JsonError};
+
+    let header = r#"{"type": "1", "parameters": [4]}, {"type": "1", "parameters": [3]}, {"type": "1", "parameters": [2]}"#;
+    let load = |gate: &str| BinaryCircuit::from_json(&format!("[{}, {}]", header, gate));
+
+    assert!(matches!(BinaryCircuit::from_json("{}"), Err(JsonError::NotAnArray)));
+    assert!(matches!(BinaryCircuit::from_json("[{"), Err(JsonError::Syntax(_))));
+    assert!(matches!(
+        load(r#"{"type": 2, "parameters": [2, 1, 2, 3, "AND"]}"#),
+        Err(JsonError::InvalidComponent { index: 3, .. })
+    ));
+    assert!(matches!(
+        load(r#"{"type": "2", "parameters": [2, 1, 0, 3, "AND"]}"#),
+        Err(JsonError::InvalidComponent { index: 3, .. })
+    ));
+    assert!(matches!(
+        load(r#"{"type": "2", "parameters": [2, 1, 3, 3, "AND"]}"#),
+        Err(JsonError::Component { index: 3, error: BristolError::UndefinedWire { wire: 2, .. } })
+    ));
+    assert!(matches!(
+        load(r#"{"type": "2", "parameters": [2, 1, 2, 3, "OR"]}"#),
+        Err(JsonError::Component { index: 3, error: BristolError::UnknownGate { .. } })
+    ));
+    assert!(load(r#"{"type": "2", "parameters": [2, 1, 2, 3, "AND"]}"#).is_ok());
+}
+
+mod words {
+    use bitvm::op_code_circuits::create_op_code_circuit;
+    use bitvm::word::{bits_of, value_of};
+    use bitvm::{BinaryCircuit, CircuitBuilder, Word};
+
+    const SAMPLES: [u32; 6] = [0, 1, 0x7fff_ffff, 0x8000_0000, 0xdead_beef, 0xffff_ffff];
+
+    fn run(circuit: &BinaryCircuit, values: &[u32]) -> Vec<bool> {
+        let inputs: Vec<bool> = values.iter().flat_map(|&v| bits_of(v as u64, 32)).collect();
+        circuit.output_values(&circuit.evaluate(&inputs).unwrap())
+    }
+
+    fn binary_op(op: impl Fn(&mut CircuitBuilder, &Word<32>, &Word<32>) -> Word<32>) -> BinaryCircuit {
+        let mut builder = CircuitBuilder::new();
+        let a = builder.input_word::<32>();
+        let b = builder.input_word::<32>();
+        let result = op(&mut builder, &a, &b);
+        builder.output_word(&result);
+        builder.build()
+    }
+
+    #[test]
+    fn word_arithmetic_matches_u32() {
+        let add = binary_op(|b, x, y| b.add_words(x, y));
+        let sub = binary_op(|b, x, y| b.sub_words(x, y));
+        let or = binary_op(|b, x, y| b.or_words(x, y));
+        let rot = binary_op(|b, x, y| {
+            let r = x.rotate_right(7);
+            let s = b.shr_word(y, 3);
+            b.xor_words(&r, &s)
+        });
+        let shl = binary_op(|b, x, _| b.shl_word(x, 5));
+        for &x in &SAMPLES {
+            for &y in &SAMPLES {
+                assert_eq!(value_of(&run(&add, &[x, y])), x.wrapping_add(y) as u64);
+                assert_eq!(value_of(&run(&sub, &[x, y])), x.wrapping_sub(y) as u64);
+                assert_eq!(value_of(&run(&or, &[x, y])), (x | y) as u64);
+                assert_eq!(value_of(&run(&rot, &[x, y])), (x.rotate_right(7) ^ (y >> 3)) as u64);
+                assert_eq!(value_of(&run(&shl, &[x, y])), (x << 5) as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn word_comparisons_and_mux() {
+        let mut builder = CircuitBuilder::new();
+        let a = builder.input_word::<32>();
+        let b = builder.input_word::<32>();
+        let equal = builder.eq_words(&a, &b);
+        let nonzero = builder.nonzero(&a);
+        let constant = builder.constant_word::<32>(0x0f0f_0000);
+        let picked = builder.mux_words(equal, &constant, &b);
+        builder.output(equal);
+        builder.output(nonzero);
+        builder.output_word(&picked);
+        let circuit = builder.build();
+
+        for &x in &SAMPLES {
+            for &y in &SAMPLES {
+                let out = run(&circuit, &[x, y]);
+                assert_eq!(out[0], x == y);
+                assert_eq!(out[1], x != 0);
+                assert_eq!(value_of(&out[2..]), if x == y { 0x0f0f_0000 } else { y as u64 });
+            }
+        }
+    }
+
+    #[test]
+    fn adder_is_bristol_compatible() {
+        let add = create_op_code_circuit("OP_ADD").unwrap();
+        let written = add.to_bristol_format().unwrap();
+        let header: Vec<&str> = written.lines().take(3).collect();
+        assert_eq!(header[1..], ["2 32 32", "1 32"]);
+        let reparsed = BinaryCircuit::from_bristol_format(&written).unwrap();
+        assert_eq!(value_of(&run(&reparsed, &[0xffff_fff0, 0x21])), 0x11);
+    }
+
+    #[test]
+    fn opcode_circuits() {
+        let value = |op: &str, args: &[u32]| value_of(&run(&create_op_code_circuit(op).unwrap(), args));
+        assert_eq!(value("OP_SUB", &[10, 3]), 7);
+        assert_eq!(value("OP_MULT", &[0x1234, 0x5678]), 0x1234u64 * 0x5678);
+        assert_eq!(value("OP_MULT", &[0xffff_ffff, 3]), 0xffff_ffffu32.wrapping_mul(3) as u64);
+        assert_eq!(value("OP_IF", &[1, 5, 6]), 5);
+        assert_eq!(value("OP_IF", &[0, 5, 6]), 6);
+        assert_eq!(value("OP_VERIFY", &[0]), 0);
+        assert_eq!(value("OP_EQUALVERIFY", &[9, 9]), 1);
+        assert_eq!(value("OP_EQUAL", &[9, 8]), 0);
+
+        let stack = |op: &str, args: &[u32]| -> Vec<u64> {
+            run(&create_op_code_circuit(op).unwrap(), args).chunks(32).map(value_of).collect()
+        };
+        assert_eq!(stack("OP_DUP", &[4]), vec![4, 4]);
+        assert_eq!(stack("OP_ROT", &[1, 2, 3]), vec![2, 3, 1]);
+        assert_eq!(stack("OP_SWAP", &[1, 2]), vec![2, 1]);
+        assert_eq!(stack("OP_2DUP", &[1, 2]), vec![1, 2, 1, 2]);
+        assert_eq!(stack("OP_2DROP", &[1, 2]), Vec::<u64>::new());
+        assert_eq!(stack("OP_OVER", &[1, 2]), vec![1, 2, 1]);
+        assert_eq!(stack("OP_TUCK", &[1, 2]), vec![2, 1, 2]);
+        assert!(create_op_code_circuit("OP_NOP").is_err());
+    }
+}
+
+mod hashes {
+    use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
+    use bitvm::op_code_circuits::create_op_code_circuit;
+    use bitvm::ripemd160::{hash160_circuit, ripemd160_circuit};
+    use bitvm::sha256::{sha256_block_count, sha256_circuit};
+    use bitvm::BinaryCircuit;
+    use bitvm::word::{bits_of, bits_to_bytes, bytes_to_bits};
+
+    fn digest(circuit: &BinaryCircuit, message: &[u8]) -> Vec<u8> {
+        let trace = circuit.evaluate(&bytes_to_bits(message)).unwrap();
+        bits_to_bytes(&circuit.output_values(&trace))
+    }
+
+    fn circuit_sha256(message: &[u8]) -> Vec<u8> {
+        digest(&sha256_circuit(8 * message.len()), message)
+    }
+
+    #[test]
+    fn sha256_matches_reference() {
+        let long: Vec<u8> = (0..=255u8).cycle().take(200).collect();
+        let messages: [&[u8]; 7] = [b"", b"abc", &[0x61; 55], &[0x62; 56], &[0x63; 64], &[0x64; 119], &long];
+        for message in messages {
+            assert_eq!(circuit_sha256(message), sha256::Hash::hash(message).to_byte_array(), "{} bytes", message.len());
+        }
+    }
+
+    #[test]
+    fn sha256_pads_to_whole_blocks() {
+        assert_eq!(sha256_block_count(0), 1);
+        assert_eq!(sha256_block_count(447), 1);
+        assert_eq!(sha256_block_count(448), 2);
+        assert_eq!(sha256_block_count(960), 3);
+
+        let circuit = sha256_circuit(24);
+        assert_eq!(circuit.input_widths(), [24]);
+        assert_eq!(circuit.output_widths(), [256]);
+        let written = circuit.to_bristol_format().unwrap();
+        assert!(written.contains(" AND\n"));
+    }
+
+    /// Items covering every length of the minimal script number encoding.
+    const ITEMS: [i32; 10] = [0, 1, -1, 128, -255, 0x7fff, 0x8000, 0x0403_0201, i32::MAX, i32::MIN];
+
+    /// Minimal script number encoding of `item`, as Script hashes it.
+    fn script_num(item: i32) -> Vec<u8> {
+        let mut buf = [0u8; 8];
+        let len = bitcoin::script::write_scriptint(&mut buf, item as i64);
+        buf[..len].to_vec()
+    }
+
+    fn op_digest(circuit: &bitvm::BinaryCircuit, item: i32) -> Vec<u8> {
+        let trace = circuit.evaluate(&bits_of(item as u32 as u64, 32)).unwrap();
+        bits_to_bytes(&circuit.output_values(&trace))
+    }
+
+    #[test]
+    fn op_sha256_hashes_minimal_items() {
+        let circuit = create_op_code_circuit("OP_SHA256").unwrap();
+        for &item in &ITEMS {
+            assert_eq!(op_digest(&circuit, item), sha256::Hash::hash(&script_num(item)).to_byte_array(), "{}", item);
+        }
+        assert_eq!(script_num(i32::MIN).len(), 5);
+    }
+
+    #[test]
+    fn ripemd160_matches_reference() {
+        let long: Vec<u8> = (0..150u8).collect();
+        let messages: [&[u8]; 6] = [b"", b"abc", &[0x61; 55], &[0x62; 56], &[0x63; 64], &long];
+        for message in messages {
+            let circuit = ripemd160_circuit(8 * message.len());
+            assert_eq!(digest(&circuit, message), ripemd160::Hash::hash(message).to_byte_array(), "{} bytes", message.len());
+        }
+    }
+
+    #[test]
+    fn hash160_matches_reference() {
+        let pubkey = hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
+        let circuit = hash160_circuit(8 * pubkey.len());
+        assert_eq!(circuit.output_widths(), [160]);
+        assert_eq!(digest(&circuit, &pubkey), hash160::Hash::hash(&pubkey).to_byte_array());
+
+        let op_hash160 = create_op_code_circuit("OP_HASH160").unwrap();
+        let op_ripemd160 = create_op_code_circuit("OP_RIPEMD160").unwrap();
+        // The empty item, a sign byte spilling over and the 5-byte item.
+        for &item in &[0, 128, i32::MIN] {
+            let encoded = script_num(item);
+            assert_eq!(op_digest(&op_hash160, item), hash160::Hash::hash(&encoded).to_byte_array(), "{}", item);
+            assert_eq!(op_digest(&op_ripemd160, item), ripemd160::Hash::hash(&encoded).to_byte_array(), "{}", item);
+        }
+    }
+}
+
+mod lowering {
+    use bitvm::sha256::sha256_circuit;
+    use bitvm::word::{bits_to_bytes, bytes_to_bits};
+    use bitvm::{BinaryCircuit, Basis, CircuitBuilder, GateId, GateType, LowerError};
+
+    const BASES: [Basis; 2] = [Basis::AndXorNot, Basis::Nand];
+
+    /// Every high-level gate type over three inputs.
+    fn composite_circuit() -> BinaryCircuit {
+        let mut builder = CircuitBuilder::new();
+        let [a, b, c] = [builder.input(), builder.input(), builder.input()];
+        let gates: [(GateType, Vec<_>); 15] = [
+            (GateType::OR, vec![a, b, c]),
+            (GateType::AND, vec![a, b, c]),
+            (GateType::XOR, vec![a]),
+            (GateType::NAND, vec![a, b, c]),
+            (GateType::Copy, vec![a]),
+            (GateType::Halt, vec![c]),
+            (GateType::Equal, vec![a, b, c]),
+            (GateType::PushConstant(true), vec![]),
+            (GateType::Mux, vec![a, b, c]),
+            (GateType::SHA256Choice, vec![a, b, c]),
+            (GateType::SHA256Majority, vec![a, b, c]),
+            (GateType::SHA256Rotate, vec![b]),
+            (GateType::RIPEMD160Choice, vec![c, a, b]),
+            (GateType::RIPEMD160Majority, vec![c, b, a]),
+            (GateType::RIPEMD160Rotate, vec![c]),
+        ];
+        for (gate_type, operands) in gates {
+            let wire = builder.gate(gate_type, &operands);
+            builder.output(wire);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn composite_gates_have_bit_semantics() {
+        let circuit = composite_circuit();
+        let trace = circuit.evaluate(&[true, false, true]).unwrap();
+        let expected = [
+            true, false, true, true, true, false, false, true, false, false, true, false, true, true, true,
+        ];
+        assert_eq!(circuit.output_values(&trace), expected);
+    }
+
+    #[test]
+    fn lowering_preserves_every_wire() {
+        let circuit = composite_circuit();
+        for basis in BASES {
+            let lowered = circuit.lower(basis).unwrap();
+            assert!(lowered.gates().iter().all(|gate| basis.contains(gate)), "{:?}", basis);
+            assert_eq!(lowered.inputs(), circuit.inputs());
+            assert_eq!(lowered.outputs(), circuit.outputs());
+
+            for row in 0..8 {
+                let inputs = [row & 1 == 1, row & 2 == 2, row & 4 == 4];
+                let original = circuit.evaluate(&inputs).unwrap();
+                let trace = lowered.evaluate(&inputs).unwrap();
+                assert_eq!(trace[..original.len()], original[..], "{:?} on {:?}", basis, inputs);
+            }
+        }
+    }
+
+    #[test]
+    fn lowered_sha256_still_hashes() {
+        let circuit = sha256_circuit(24).lower(Basis::Nand).unwrap();
+        let trace = circuit.evaluate(&bytes_to_bits(b"abc")).unwrap();
+        assert_eq!(
+            hex::encode(bits_to_bytes(&circuit.output_values(&trace))),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn rejects_word_level_and_malformed_gates() {
+        let mut builder = CircuitBuilder::new();
+        let a = builder.input();
+        builder.gate(GateType::Mux, &[a, a]);
+        let bad_mux = builder.build();
+        assert_eq!(
+            bad_mux.lower(Basis::AndXorNot).err(),
+            Some(LowerError::InvalidGate { gate: GateId(0), gate_type: GateType::Mux, operands: 2 })
+        );
+
+        let mut builder = CircuitBuilder::new();
+        let a = builder.input();
+        builder.gate(GateType::SHA256Round, &[a]);
+        let round = builder.build();
+        assert_eq!(
+            round.lower(Basis::Nand).err(),
+            Some(LowerError::WordLevelGate { gate: GateId(0), gate_type: GateType::SHA256Round })
+        );
+    }
+
+    #[test]
+    fn stack_gates_only_evaluate_where_defined() {
+        let single = |gate_type: GateType, operands: usize| {
+            let mut builder = CircuitBuilder::new();
+            let inputs = builder.inputs(operands);
+            builder.gate(gate_type, &inputs);
+            builder.build()
+        };
+
+        let halt = single(GateType::Halt, 1);
+        assert!(halt.evaluate(&[false]).unwrap()[1]);
+        assert!(!halt.evaluate(&[true]).unwrap()[1]);
+
+        for (gate_type, operands) in [(GateType::Halt, 2), (GateType::Drop, 0), (GateType::Drop, 2)] {
+            let circuit = single(gate_type, operands);
+            assert_eq!(
+                circuit.lower(Basis::AndXorNot).err(),
+                Some(LowerError::InvalidGate { gate: GateId(0), gate_type, operands })
+            );
+            assert!(circuit.evaluate(&vec![true; operands]).is_err(), "{:?}", gate_type);
+        }
+
+        let bit_count = single(GateType::BitCount, 3);
+        assert!(bit_count.evaluate(&[true, false, true]).is_err());
+        assert_eq!(
+            bit_count.lower(Basis::Nand).err(),
+            Some(LowerError::WordLevelGate { gate: GateId(0), gate_type: GateType::BitCount })
+        );
+    }
+
+    const ADDER_32_NAND: &str = include_str!("../src/circuit_logic/32bit_sha256_nand.txt.txt");
+
+    #[test]
+    fn nand_adder_adds_like_the_original() {
+        let adder = BinaryCircuit::from_bristol_format(super::ADDER_32).unwrap();
+        let conversion = adder.to_nand_only().unwrap();
+        assert_eq!(conversion.gates_before, 154);
+        assert_eq!(conversion.gates_after, conversion.circuit.gates().len());
+        assert!(conversion.blow_up() > 1.0);
+        assert!(conversion.circuit.gates().iter().all(|gate| gate.gate_type == GateType::NAND));
+
+        let shipped = BinaryCircuit::from_bristol_format(ADDER_32_NAND).unwrap();
+        assert!(shipped.gates().iter().all(|gate| gate.gate_type == GateType::NAND));
+        assert_eq!(shipped.input_widths(), adder.input_widths());
+
+        let mut vectors = vec![(0u32, 0u32), (1, 0xffff_ffff), (0xffff_ffff, 0xffff_ffff), (0x8000_0000, 0x8000_0000)];
+        let mut state = 0x2545_f491_u32;
+        for _ in 0..32 {
+            let mut next = || {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state
+            };
+            vectors.push((next(), next()));
+        }
+
+        for (a, b) in vectors {
+            let inputs = [super::to_bits(a), super::to_bits(b)].concat();
+            let expected = adder.output_values(&adder.evaluate(&inputs).unwrap());
+            assert_eq!(super::from_bits(&expected), a.wrapping_add(b));
+            for nand in [&shipped, &conversion.circuit] {
+                let trace = nand.evaluate(&inputs).unwrap();
+                assert_eq!(nand.output_values(&trace), expected, "{:#x} + {:#x}", a, b);
+            }
+        }
+    }
+}
+
+mod optimizer {
+    use bitvm::sha256::sha256_circuit;
+    use bitvm::word::{bits_of, bits_to_bytes, bytes_to_bits};
+    use bitvm::{BinaryCircuit, CircuitBuilder, GateType};
+
+    fn assert_equivalent(original: &BinaryCircuit, optimized: &BinaryCircuit, inputs: &[bool]) {
+        let expected = original.output_values(&original.evaluate(inputs).unwrap());
+        let found = optimized.output_values(&optimized.evaluate(inputs).unwrap());
+        assert_eq!(found, expected, "on {:?}", inputs);
+    }
+
+    #[test]
+    fn folds_constants_and_simplifies_subtraction() {
+        let mut builder = CircuitBuilder::new();
+        let a = builder.input_word::<8>();
+        let b = builder.input_word::<8>();
+        let difference = builder.sub_words(&a, &b);
+        builder.output_word(&difference);
+        let circuit = builder.build();
+
+        let (optimized, stats) = circuit.optimize().unwrap();
+        assert!(stats.gates_after < stats.gates_before, "{}", stats);
+        assert!(stats.folded > 0);
+        assert!(!optimized.gates().iter().any(|gate| matches!(gate.gate_type, GateType::Constant(_))));
+        assert_eq!(optimized.input_widths(), [8, 8]);
+        for (x, y) in [(0, 0), (5, 3), (3, 5), (255, 1), (128, 127)] {
+            let inputs = [bits_of(x, 8), bits_of(y, 8)].concat();
+            assert_equivalent(&circuit, &optimized, &inputs);
+        }
+    }
+
+    #[test]
+    fn merges_and_cancels_equal_subexpressions() {
+        let mut builder = CircuitBuilder::new();
+        let [a, b, c] = [builder.input(), builder.input(), builder.input()];
+        let first = builder.and(a, b);
+        let second = builder.and(b, a);
+        let same = builder.xor(first, second);
+        let ab = builder.xor(a, b);
+        let just_b = builder.xor(ab, a);
+        let not_c = builder.not(c);
+        let c_again = builder.not(not_c);
+        let unused = builder.or(a, c);
+        builder.not(unused);
+        builder.output(same);
+        builder.output(just_b);
+        builder.output(c_again);
+        builder.output(first);
+        let circuit = builder.build();
+
+        let (optimized, stats) = circuit.optimize().unwrap();
+        assert_eq!(stats.merged, 1);
+        assert!(stats.removed >= 2, "{}", stats);
+        assert_eq!(optimized.gates().len(), 2, "{:?}", optimized.gates());
+        assert_eq!(optimized.outputs()[1], optimized.inputs()[1]);
+        assert_eq!(optimized.outputs()[2], optimized.inputs()[2]);
+        for row in 0..8 {
+            assert_equivalent(&circuit, &optimized, &[row & 1 == 1, row & 2 == 2, row & 4 == 4]);
+        }
+    }
+
+    #[test]
+    fn optimized_sha256_still_hashes() {
+        let circuit = sha256_circuit(24);
+        let (optimized, stats) = circuit.optimize().unwrap();
+        assert!(stats.gates_after < stats.gates_before, "{}", stats);
+        let trace = optimized.evaluate(&bytes_to_bits(b"abc")).unwrap();
+        assert_eq!(
+            hex::encode(bits_to_bytes(&optimized.output_values(&trace))),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let (again, stats) = optimized.optimize().unwrap();
+        assert_eq!(again.gates().len(), optimized.gates().len(), "{}", stats);
+    }
+}
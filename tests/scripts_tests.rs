@@ -0,0 +1,724 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::key::{Keypair, XOnlyPublicKey};
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+
+use bitvm::bit_commitment::CommitmentKeys;
+use bitvm::equivocation::punishment_script;
+use bitvm::execute_btc_script::{ExecCtx, ExecError, Options, TxTemplate};
+use bitvm::gate_scripts::opening_witness;
+use bitvm::run::leaf_template;
+use bitvm::taproot::{leaf_signature_hash, unspendable_internal_key};
+use bitvm::timelocks::{absolute_timeout_script, relative_timeout_script, timeout_sequence};
+use bitvm::winternitz::WinternitzError;
+use bitvm::{
+    gate_scripts, run_script, run_tapscript, BinaryCircuit, ChainHash, ChallengeTree, CircuitBuilder, EquivocationWatcher,
+    GateId, GateScript, GateScriptError, GateType, ScriptRun, TreeError, TreeShape, WinternitzParams, WinternitzSecret,
+    WireId,
+};
+
+fn half_adder() -> BinaryCircuit {
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input();
+    let b = builder.input();
+    let sum = builder.xor(a, b);
+    let carry = builder.and(a, b);
+    builder.output(sum);
+    builder.output(carry);
+    builder.build()
+}
+
+#[test]
+fn derives_distinct_preimages_from_the_seed() {
+    let keys = CommitmentKeys::from_seed(b"prover seed");
+    let again = CommitmentKeys::from_seed(b"prover seed");
+    let other = CommitmentKeys::from_seed(b"other seed");
+    assert_eq!(keys.preimage(WireId(3), true), again.preimage(WireId(3), true));
+    assert_ne!(keys.preimage(WireId(3), true), keys.preimage(WireId(3), false));
+    assert_ne!(keys.preimage(WireId(3), true), keys.preimage(WireId(4), true));
+    assert_ne!(keys.preimage(WireId(3), true), other.preimage(WireId(3), true));
+}
+
+#[test]
+fn opening_scripts_reveal_the_committed_trace() {
+    let circuit = half_adder();
+    let keys = CommitmentKeys::from_seed(b"prover seed");
+    let commitments = keys.commit_circuit(&circuit);
+    assert_eq!(commitments.len(), circuit.wire_count());
+
+    for &(a, b) in &[(false, false), (false, true), (true, false), (true, true)] {
+        let trace = circuit.evaluate(&[a, b]).unwrap();
+        for (wire, preimage) in keys.reveal_trace(&trace).iter().enumerate() {
+            assert_eq!(commitments[wire].open(preimage), Some(trace[wire]));
+            assert_eq!(commitments[wire].verify_opening(preimage), Ok(trace[wire]));
+        }
+    }
+}
+
+#[test]
+fn opening_script_rejects_foreign_preimages() {
+    let keys = CommitmentKeys::from_seed(b"prover seed");
+    let commitment = keys.commitment(WireId(0));
+    let foreign = keys.preimage(WireId(1), false);
+    assert_eq!(commitment.open(&foreign), None);
+    assert_eq!(commitment.verify_opening(&foreign), Err(ExecError::EqualVerify.into()));
+    assert_eq!(commitment.verify_opening(&[]), Err(ExecError::EqualVerify.into()));
+}
+
+fn basic_gates() -> BinaryCircuit {
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input();
+    let b = builder.input();
+    let nand = builder.nand(a, b);
+    let and = builder.and(a, b);
+    let xor = builder.xor(a, b);
+    builder.output(nand);
+    builder.output(and);
+    builder.output(xor);
+    builder.build()
+}
+
+fn run_leaf(script: &ScriptBuf, witness: Vec<Vec<u8>>) -> ScriptRun {
+    run_script(ExecCtx::Tapscript, Options::default(), leaf_template(script), script.clone(), witness).unwrap()
+}
+
+#[test]
+fn gate_leaves_follow_the_truth_table() {
+    let circuit = basic_gates();
+    let keys = CommitmentKeys::from_seed(b"prover seed");
+    let leaves = gate_scripts(&circuit, &keys.commit_circuit(&circuit)).unwrap();
+    let types: Vec<GateType> = leaves.iter().map(|leaf| leaf.gate_type).collect();
+    assert_eq!(types, vec![GateType::NAND, GateType::AND, GateType::XOR]);
+
+    for leaf in &leaves {
+        for &(a, b) in &[(false, false), (false, true), (true, false), (true, true)] {
+            let expected = circuit.gate(leaf.gate).evaluate(&[a, b]).unwrap();
+            for &claimed in &[false, true] {
+                let witness = opening_witness(
+                    &keys.preimage(leaf.inputs[0], a),
+                    &keys.preimage(leaf.inputs[1], b),
+                    &keys.preimage(leaf.output, claimed),
+                );
+                let check = run_leaf(&leaf.script, witness.clone());
+                assert_eq!(check.result.error, None);
+                assert_eq!(check.success(), claimed == expected, "{:?} {} {} -> {}", leaf.gate_type, a, b, claimed);
+
+                let violation = run_leaf(&leaf.violation, witness);
+                assert_eq!(violation.result.error, None);
+                assert_eq!(violation.success(), claimed != expected, "{:?} {} {} -> {}", leaf.gate_type, a, b, claimed);
+            }
+        }
+
+        let trace = circuit.evaluate(&[true, false]).unwrap();
+        assert!(run_leaf(&leaf.script, leaf.witness(&keys, &trace)).success());
+        assert!(!run_leaf(&leaf.violation, leaf.witness(&keys, &trace)).success());
+    }
+}
+
+#[test]
+fn gate_leaves_reject_foreign_openings() {
+    let circuit = basic_gates();
+    let keys = CommitmentKeys::from_seed(b"prover seed");
+    let other = CommitmentKeys::from_seed(b"other seed");
+    let leaves = gate_scripts(&circuit, &keys.commit_circuit(&circuit)).unwrap();
+    let leaf = &leaves[0];
+
+    // An output preimage from another wire or another seed opens nothing.
+    for script in &[&leaf.script, &leaf.violation] {
+        for output in &[keys.preimage(leaf.inputs[0], true), other.preimage(leaf.output, true)] {
+            let witness = opening_witness(&keys.preimage(leaf.inputs[0], true), &keys.preimage(leaf.inputs[1], true), output);
+            let run = run_leaf(script, witness);
+            assert_eq!(run.result.error, Some(ExecError::EqualVerify));
+        }
+
+        // Swapped inputs fail at the first opening.
+        let witness = opening_witness(&keys.preimage(leaf.inputs[1], true), &keys.preimage(leaf.inputs[0], true), &keys.preimage(leaf.output, false));
+        assert_eq!(run_leaf(script, witness).result.error, Some(ExecError::EqualVerify));
+    }
+}
+
+#[test]
+fn gate_scripts_reject_unsupported_gates() {
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input();
+    let b = builder.input();
+    let or = builder.or(a, b);
+    builder.output(or);
+    let circuit = builder.build();
+    let keys = CommitmentKeys::from_seed(b"prover seed");
+    assert_eq!(
+        gate_scripts(&circuit, &keys.commit_circuit(&circuit)).err(),
+        Some(GateScriptError::UnsupportedGate { gate: GateId(0), gate_type: GateType::OR, operands: 2 })
+    );
+
+    let circuit = basic_gates();
+    let commitments = keys.commit_circuit(&circuit);
+    assert_eq!(
+        gate_scripts(&circuit, &commitments[..2]).err(),
+        Some(GateScriptError::MissingCommitment { gate: GateId(0), wire: WireId(2) })
+    );
+}
+
+fn keypair(secret: u8) -> Keypair {
+    Keypair::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[secret; 32]).unwrap())
+}
+
+fn x_only_key(secret: u8) -> XOnlyPublicKey {
+    keypair(secret).x_only_public_key().0
+}
+
+fn spending_tx() -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![],
+    }
+}
+
+fn challenge_tree(shape: TreeShape) -> (BinaryCircuit, CommitmentKeys, ChallengeTree) {
+    let circuit = basic_gates();
+    let keys = CommitmentKeys::from_seed(b"prover seed");
+    let leaves = gate_scripts(&circuit, &keys.commit_circuit(&circuit)).unwrap();
+    let tree = ChallengeTree::build(&circuit, x_only_key(1), x_only_key(2), 12, leaves, shape).unwrap();
+    (circuit, keys, tree)
+}
+
+#[test]
+fn challenge_tree_leaves_are_spendable_with_their_control_blocks() {
+    let (circuit, keys, tree) = challenge_tree(TreeShape::Balanced);
+    let secp = Secp256k1::verification_only();
+    let output_key = tree.spend_info.output_key().to_inner();
+    assert!(tree.output_script().is_p2tr());
+    assert_eq!(tree.spend_info.internal_key(), unspendable_internal_key());
+    assert!(tree.cooperative_control_block().verify_taproot_commitment(&secp, output_key, &tree.cooperative));
+    assert!(tree.timeout_control_block().verify_taproot_commitment(&secp, output_key, &tree.timeout));
+    assert_eq!(tree.timeout, relative_timeout_script(12, &x_only_key(1)));
+
+    let prevout = TxOut { value: Amount::from_sat(10_000), script_pubkey: tree.output_script() };
+    let spend = spending_tx();
+    let trace = circuit.evaluate(&[true, true]).unwrap();
+    for leaf in tree.leaves() {
+        let control_block = tree.control_block(leaf.gate).unwrap();
+        assert!(control_block.verify_taproot_commitment(&secp, output_key, &leaf.violation));
+        assert!(!control_block.verify_taproot_commitment(&secp, output_key, &leaf.script));
+
+        // Only an output that contradicts the gate spends its leaf.
+        let mut wrong = trace.clone();
+        wrong[leaf.output.0] = !wrong[leaf.output.0];
+        let witness = tree.spend_witness(leaf.gate, leaf.witness(&keys, &wrong)).unwrap();
+        assert_eq!(witness.len(), 5);
+        assert_eq!(witness[3], leaf.violation.to_bytes());
+        assert_eq!(witness[4], control_block.serialize());
+
+        let template = || tree.tx_template(leaf.gate, spend.clone(), vec![prevout.clone()], 0).unwrap();
+        assert_eq!(template().taproot_annex_scriptleaf.unwrap().0, tree.leaf_hash(leaf.gate).unwrap());
+        let run = |opening: Vec<Vec<u8>>| {
+            run_script(ExecCtx::Tapscript, Options::default(), template(), leaf.violation.clone(), opening).unwrap()
+        };
+        assert!(run(witness[..3].to_vec()).success());
+        assert!(!run(leaf.witness(&keys, &trace)).success());
+    }
+    assert!(tree.leaf(GateId(3)).is_none());
+}
+
+#[test]
+fn weighted_challenge_tree_shortens_likely_leaves() {
+    let (_, _, balanced) = challenge_tree(TreeShape::Balanced);
+    let (_, _, weighted) = challenge_tree(TreeShape::Weighted(vec![100, 1, 1]));
+    assert_ne!(balanced.output_script(), weighted.output_script());
+    let depth = |tree: &ChallengeTree, gate| tree.control_block(GateId(gate)).unwrap().merkle_branch.len();
+    assert_eq!((depth(&balanced, 0), depth(&balanced, 1), depth(&balanced, 2)), (2, 2, 2));
+    assert!(depth(&weighted, 0) < depth(&weighted, 1));
+}
+
+#[test]
+fn challenge_tree_needs_one_leaf_per_gate() {
+    let circuit = basic_gates();
+    let keys = CommitmentKeys::from_seed(b"prover seed");
+    let leaves = gate_scripts(&circuit, &keys.commit_circuit(&circuit)).unwrap();
+    let build = |leaves: Vec<GateScript>, shape| ChallengeTree::build(&circuit, x_only_key(1), x_only_key(2), 12, leaves, shape).err();
+
+    assert_eq!(build(leaves[1..].to_vec(), TreeShape::Balanced), Some(TreeError::MissingLeaf(GateId(0))));
+    let mut repeated = leaves.clone();
+    repeated.push(leaves[1].clone());
+    assert_eq!(build(repeated, TreeShape::Balanced), Some(TreeError::UnexpectedLeaf(GateId(1))));
+    let mut retyped = leaves.clone();
+    retyped[2].gate_type = GateType::AND;
+    assert_eq!(build(retyped, TreeShape::Balanced), Some(TreeError::UnexpectedLeaf(GateId(2))));
+    assert_eq!(
+        build(leaves, TreeShape::Weighted(vec![1])),
+        Some(TreeError::WeightCount { expected: 3, found: 1 })
+    );
+}
+
+#[test]
+fn equivocating_prover_can_be_punished() {
+    let circuit = basic_gates();
+    let keys = CommitmentKeys::from_seed(b"prover seed");
+    let commitments = keys.commit_circuit(&circuit);
+    let leaves = gate_scripts(&circuit, &commitments).unwrap();
+    let leaf = &leaves[1];
+    let mut watcher = EquivocationWatcher::new(&commitments);
+
+    let trace = circuit.evaluate(&[true, true]).unwrap();
+    assert!(watcher.observe_witness(&leaf.witness(&keys, &trace)).is_empty());
+    assert!(watcher.observe_witness(&leaf.witness(&keys, &trace)).is_empty());
+    assert_eq!(watcher.opened(leaf.output), Some(true));
+    assert!(watcher.observe_witness(&[b"unrelated".to_vec()]).is_empty());
+
+    let mut flipped = trace.clone();
+    flipped[leaf.output.0] = false;
+    let found = watcher.observe_witness(&leaf.witness(&keys, &flipped));
+    assert_eq!(found.len(), 1);
+    let equivocation = &found[0];
+    assert_eq!(equivocation.wire, leaf.output);
+    assert_eq!(equivocation.preimage0, keys.preimage(leaf.output, false).to_vec());
+    assert_eq!(equivocation.preimage1, keys.preimage(leaf.output, true).to_vec());
+    assert_eq!(watcher.opened(leaf.output), None);
+
+    let verifier = keypair(2);
+    let script = punishment_script(&commitments[leaf.output.0], &verifier.x_only_public_key().0);
+    let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+    let prevouts = vec![TxOut { value: Amount::from_sat(10_000), script_pubkey: ScriptBuf::new() }];
+    let tx = spending_tx();
+    let sighash = leaf_signature_hash(&tx, 0, &prevouts, leaf_hash).unwrap();
+    let message = Message::from(sighash);
+    let signature = Secp256k1::new().sign_schnorr_no_aux_rand(&message, &verifier);
+    let run = |witness: Vec<Vec<u8>>| {
+        let template = TxTemplate { tx: tx.clone(), prevouts: prevouts.clone(), input_idx: 0, taproot_annex_scriptleaf: Some((leaf_hash, None)) };
+        run_script(ExecCtx::Tapscript, Options::default(), template, script.clone(), witness).unwrap()
+    };
+
+    assert!(run(equivocation.witness(signature.as_ref())).success());
+    let forged = Secp256k1::new().sign_schnorr_no_aux_rand(&message, &keypair(3));
+    assert_eq!(run(equivocation.witness(forged.as_ref())).result.error, Some(ExecError::SchnorrSig));
+    let single = bitvm::Equivocation { preimage1: equivocation.preimage0.clone(), ..equivocation.clone() };
+    assert_eq!(run(single.witness(signature.as_ref())).result.error, Some(ExecError::EqualVerify));
+}
+
+/// Runs a leaf that ends in a signature check by `signer`, spending input 0 of `tx`.
+fn run_signed_leaf(script: &ScriptBuf, tx: Transaction, signer: &Keypair, options: Options) -> ScriptRun {
+    let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+    let prevouts = vec![TxOut { value: Amount::from_sat(10_000), script_pubkey: ScriptBuf::new() }];
+    let sighash = leaf_signature_hash(&tx, 0, &prevouts, leaf_hash).unwrap();
+    let signature = Secp256k1::new().sign_schnorr_no_aux_rand(&Message::from(sighash), signer);
+    let template = TxTemplate { tx, prevouts, input_idx: 0, taproot_annex_scriptleaf: Some((leaf_hash, None)) };
+    run_script(ExecCtx::Tapscript, options, template, script.clone(), vec![signature.as_ref().to_vec()]).unwrap()
+}
+
+#[test]
+fn relative_timeouts_need_the_input_sequence() {
+    let challenger = keypair(2);
+    let script = relative_timeout_script(144, &challenger.x_only_public_key().0);
+    let with_sequence = |sequence| {
+        let mut tx = spending_tx();
+        tx.input[0].sequence = sequence;
+        tx
+    };
+
+    assert!(run_signed_leaf(&script, with_sequence(timeout_sequence(144)), &challenger, Options::default()).success());
+    assert!(run_signed_leaf(&script, with_sequence(timeout_sequence(200)), &challenger, Options::default()).success());
+    let early = run_signed_leaf(&script, with_sequence(timeout_sequence(143)), &challenger, Options::default());
+    assert_eq!(early.result.error, Some(ExecError::UnsatisfiedLocktime));
+    let disabled = run_signed_leaf(&script, with_sequence(Sequence::MAX), &challenger, Options::default());
+    assert_eq!(disabled.result.error, Some(ExecError::UnsatisfiedLocktime));
+    let mut version_one = with_sequence(timeout_sequence(144));
+    version_one.version = Version::ONE;
+    assert_eq!(run_signed_leaf(&script, version_one, &challenger, Options::default()).result.error, Some(ExecError::UnsatisfiedLocktime));
+
+    let unchecked = Options { verify_csv: false, ..Options::default() };
+    assert!(run_signed_leaf(&script, with_sequence(timeout_sequence(1)), &challenger, unchecked).success());
+}
+
+#[test]
+fn absolute_deadlines_need_the_lock_time() {
+    let challenger = keypair(2);
+    let script = absolute_timeout_script(800_000, &challenger.x_only_public_key().0).unwrap();
+    assert_eq!(absolute_timeout_script(500_000_000, &challenger.x_only_public_key().0), None);
+    let with_lock_time = |height, sequence| {
+        let mut tx = spending_tx();
+        tx.lock_time = LockTime::from_height(height).unwrap();
+        tx.input[0].sequence = sequence;
+        tx
+    };
+
+    assert!(run_signed_leaf(&script, with_lock_time(800_000, Sequence::ENABLE_LOCKTIME_NO_RBF), &challenger, Options::default()).success());
+    let early = run_signed_leaf(&script, with_lock_time(799_999, Sequence::ENABLE_LOCKTIME_NO_RBF), &challenger, Options::default());
+    assert_eq!(early.result.error, Some(ExecError::UnsatisfiedLocktime));
+    let final_input = run_signed_leaf(&script, with_lock_time(800_000, Sequence::MAX), &challenger, Options::default());
+    assert_eq!(final_input.result.error, Some(ExecError::UnsatisfiedLocktime));
+
+    let unchecked = Options { verify_cltv: false, ..Options::default() };
+    assert!(run_signed_leaf(&script, with_lock_time(1, Sequence::MAX), &challenger, unchecked).success());
+}
+
+#[test]
+fn winternitz_parameters() {
+    let params = WinternitzParams::new(32, 4, ChainHash::Hash160).unwrap();
+    assert_eq!((params.message_digits(), params.checksum_digits()), (8, 2));
+    let params = WinternitzParams::new(256, 4, ChainHash::Hash160).unwrap();
+    assert_eq!((params.message_digits(), params.checksum_digits()), (64, 3));
+    let params = WinternitzParams::new(1, 1, ChainHash::Sha256).unwrap();
+    assert_eq!((params.message_digits(), params.checksum_digits()), (1, 1));
+    assert_eq!(WinternitzParams::new(0, 4, ChainHash::Hash160), Err(WinternitzError::ZeroWidth));
+    assert_eq!(WinternitzParams::new(32, 9, ChainHash::Hash160), Err(WinternitzError::DigitWidth(9)));
+
+    let params = WinternitzParams::new(12, 4, ChainHash::Hash160).unwrap();
+    assert_eq!(params.digits_of(&[0x0a, 0xbc]), Ok(vec![0xc, 0xb, 0xa, 0xc, 0x0]));
+    assert_eq!(params.digits_of(&[0x00, 0x0a, 0xbc]), Ok(vec![0xc, 0xb, 0xa, 0xc, 0x0]));
+    assert_eq!(params.digits_of(&[0x1a, 0xbc]), Err(WinternitzError::ValueTooWide { bits: 12 }));
+}
+
+#[test]
+fn winternitz_signatures_verify_in_script() {
+    let cases = [(32, 4, ChainHash::Hash160), (10, 3, ChainHash::Sha256), (256, 4, ChainHash::Hash160), (16, 8, ChainHash::Hash160)];
+    for &(bits, log_d, hash) in &cases {
+        let params = WinternitzParams::new(bits, log_d, hash).unwrap();
+        let secret = WinternitzSecret::from_seed(params, b"winternitz");
+        let public_key = secret.public_key();
+        let mut value: Vec<u8> = (0..bits.div_ceil(8)).map(|byte| (byte * 37 + 5) as u8).collect();
+        value[0] &= (0xffu16 >> ((8 - bits % 8) % 8)) as u8;
+        let signature = secret.sign(&value).unwrap();
+        let digits = public_key.verify(&signature).unwrap();
+        assert_eq!(digits, signature.digits[..params.message_digits()].to_vec());
+
+        let run = run_tapscript(&public_key.verify_script(), signature.witness()).unwrap();
+        assert!(run.success(), "{} bits in digits of {}: {:?}", bits, log_d, run.result.error);
+        // The bare verifier leaves the message digits, digit 0 on top.
+        let bare = public_key.push_verify(bitcoin::script::Builder::new()).into_script();
+        let run = run_tapscript(&bare, signature.witness()).unwrap();
+        let left: Vec<u32> = run.final_stack().iter().rev().map(|item| item.first().copied().unwrap_or(0) as u32).collect();
+        assert_eq!(left, digits);
+    }
+}
+
+#[test]
+fn winternitz_verifier_rejects_forgeries() {
+    let params = WinternitzParams::new(32, 4, ChainHash::Hash160).unwrap();
+    let secret = WinternitzSecret::from_seed(params, b"winternitz");
+    let public_key = secret.public_key();
+    let script = public_key.verify_script();
+    let signature = secret.sign(&[0x12, 0x34, 0x56, 0x78]).unwrap();
+
+    // Hashing a chain forward raises its digit, which the checksum catches.
+    let mut raised = signature.clone();
+    raised.digits[1] += 1;
+    raised.chains[1] = ChainHash::Hash160.hash(&raised.chains[1]);
+    assert_eq!(public_key.verify(&raised), None);
+    assert_eq!(run_tapscript(&script, raised.witness()).unwrap().result.error, Some(ExecError::EqualVerify));
+
+    let mut wrong_chain = signature.clone();
+    wrong_chain.digits[0] += 1;
+    assert_eq!(public_key.verify(&wrong_chain), None);
+    assert_eq!(run_tapscript(&script, wrong_chain.witness()).unwrap().result.error, Some(ExecError::EqualVerify));
+
+    let mut out_of_range = signature.clone();
+    out_of_range.digits[2] = 16;
+    assert_eq!(public_key.verify(&out_of_range), None);
+    assert_eq!(run_tapscript(&script, out_of_range.witness()).unwrap().result.error, Some(ExecError::Verify));
+
+    let other = WinternitzSecret::from_seed(params, b"other key").sign(&[0x12, 0x34, 0x56, 0x78]).unwrap();
+    assert_eq!(public_key.verify(&other), None);
+    assert!(!run_tapscript(&script, other.witness()).unwrap().success());
+}
+
+#[test]
+fn winternitz_commitments_are_smaller_than_hashlocks() {
+    let word = WinternitzParams::new(32, 4, ChainHash::Hash160).unwrap().cost();
+    let hashlock = CommitmentKeys::from_seed(b"prover seed").commitment(WireId(0)).open_script().len();
+    assert!(word.script_size < 32 * hashlock, "{} bytes against {}", word.script_size, 32 * hashlock);
+    assert!(word.witness_size < 32 * bitvm::bit_commitment::PREIMAGE_LEN);
+    assert!(word.stats.max_nb_stack_items <= 1000);
+
+    let digest = WinternitzParams::new(256, 4, ChainHash::Hash160).unwrap().cost();
+    assert!(digest.stats.max_nb_stack_items <= 1000);
+    assert!(digest.stats.max_stack_item_size <= 80);
+    assert!(digest.script_size > word.script_size);
+}
+
+mod verify {
+    use std::convert::TryFrom;
+
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::key::{Keypair, PublicKey, TapTweak, XOnlyPublicKey};
+    use bitcoin::opcodes::all::*;
+    use bitcoin::script::{Builder, PushBytesBuf, Script};
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+    use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+    use bitcoin::taproot::{LeafVersion, TapLeafHash, TaprootBuilder};
+    use bitcoin::transaction::Version;
+    use bitcoin::{ecdsa, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+
+    use bitvm::execute_btc_script::{verify_input, Error, ExecError, VerifyFlags};
+
+    fn secret(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn public_key(byte: u8) -> PublicKey {
+        PublicKey::new(secret(byte).public_key(&Secp256k1::new()))
+    }
+
+    fn prevout(script_pubkey: ScriptBuf) -> TxOut {
+        TxOut { value: Amount::from_sat(100_000), script_pubkey }
+    }
+
+    /// Transaction spending `prevout` with `script_sig` and `witness`.
+    fn spend(prevout: &TxOut, script_sig: ScriptBuf, witness: Vec<Vec<u8>>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig,
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&witness),
+            }],
+            output: vec![TxOut { value: prevout.value - Amount::from_sat(1_000), script_pubkey: ScriptBuf::new() }],
+        }
+    }
+
+    fn pushes(items: &[Vec<u8>]) -> ScriptBuf {
+        items.iter()
+            .fold(Builder::new(), |builder, item| builder.push_slice(PushBytesBuf::try_from(item.clone()).unwrap()))
+            .into_script()
+    }
+
+    fn ecdsa_signature(sighash: Message, signer: u8) -> Vec<u8> {
+        ecdsa::Signature::sighash_all(Secp256k1::new().sign_ecdsa(&sighash, &secret(signer))).to_vec()
+    }
+
+    /// Signature of a pre-segwit spend committing to `script_code`.
+    fn legacy_signature(tx: &Transaction, script_code: &Script, signer: u8) -> Vec<u8> {
+        let sighash = SighashCache::new(tx).legacy_signature_hash(0, script_code, EcdsaSighashType::All.to_u32()).unwrap();
+        ecdsa_signature(Message::from(sighash), signer)
+    }
+
+    /// Signature of a segwit v0 spend of `prevout` committing to `script_code`.
+    fn segwit_signature(tx: &Transaction, prevout: &TxOut, script_code: &Script, signer: u8) -> Vec<u8> {
+        let sighash = SighashCache::new(tx)
+            .p2wsh_signature_hash(0, script_code, prevout.value, EcdsaSighashType::All)
+            .unwrap();
+        ecdsa_signature(Message::from(sighash), signer)
+    }
+
+    fn multisig() -> ScriptBuf {
+        Builder::new()
+            .push_int(2)
+            .push_key(&public_key(1))
+            .push_key(&public_key(2))
+            .push_key(&public_key(3))
+            .push_int(3)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script()
+    }
+
+    fn verify(tx: &Transaction, prevout: &TxOut) -> Result<(), Error> {
+        verify_input(tx, 0, std::slice::from_ref(prevout), &VerifyFlags::default())
+    }
+
+    fn exec_error(err: ExecError) -> Result<(), Error> {
+        Err(Error::Exec(err))
+    }
+
+    #[test]
+    fn legacy_spends_run_the_script_sig_then_the_script_pubkey() {
+        let prevout = prevout(ScriptBuf::new_p2pkh(&public_key(1).pubkey_hash()));
+        let unsigned = spend(&prevout, ScriptBuf::new(), vec![]);
+        let signature = legacy_signature(&unsigned, &prevout.script_pubkey, 1);
+        let signed = spend(&prevout, pushes(&[signature.clone(), public_key(1).to_bytes()]), vec![]);
+        assert_eq!(verify(&signed, &prevout), Ok(()));
+
+        let wrong_key = legacy_signature(&unsigned, &prevout.script_pubkey, 2);
+        let forged = spend(&prevout, pushes(&[wrong_key, public_key(1).to_bytes()]), vec![]);
+        assert_eq!(verify(&forged, &prevout), exec_error(ExecError::EvalFalse));
+
+        let with_witness = spend(&prevout, signed.input[0].script_sig.clone(), vec![vec![1]]);
+        assert_eq!(verify(&with_witness, &prevout), exec_error(ExecError::WitnessUnexpected));
+
+        // An extra element is left below the result; only policy rejects it.
+        let dirty = spend(&prevout, pushes(&[vec![7; 2], signature, public_key(1).to_bytes()]), vec![]);
+        assert_eq!(verify(&dirty, &prevout), exec_error(ExecError::CleanStack));
+        assert_eq!(verify_input(&dirty, 0, std::slice::from_ref(&prevout), &VerifyFlags::consensus()), Ok(()));
+    }
+
+    #[test]
+    fn p2sh_spends_run_the_redeem_script() {
+        let redeem_script = multisig();
+        let prevout = prevout(ScriptBuf::new_p2sh(&redeem_script.script_hash()));
+        let unsigned = spend(&prevout, ScriptBuf::new(), vec![]);
+        let first = legacy_signature(&unsigned, &redeem_script, 1);
+        let third = legacy_signature(&unsigned, &redeem_script, 3);
+
+        let signed = spend(&prevout, pushes(&[vec![], first.clone(), third.clone(), redeem_script.to_bytes()]), vec![]);
+        assert_eq!(verify(&signed, &prevout), Ok(()));
+
+        // CHECKMULTISIG matches signatures to keys in order.
+        let swapped = spend(&prevout, pushes(&[vec![], third.clone(), first.clone(), redeem_script.to_bytes()]), vec![]);
+        assert_eq!(verify(&swapped, &prevout), exec_error(ExecError::EvalFalse));
+
+        let dummy = spend(&prevout, pushes(&[vec![7; 2], first.clone(), third.clone(), redeem_script.to_bytes()]), vec![]);
+        assert_eq!(verify(&dummy, &prevout), exec_error(ExecError::SigNullDummy));
+
+        let missing = spend(&prevout, pushes(&[vec![], first, redeem_script.to_bytes()]), vec![]);
+        assert_eq!(verify(&missing, &prevout), exec_error(ExecError::InvalidStackOperation));
+    }
+
+    #[test]
+    fn segwit_v0_spends_run_the_witness() {
+        let key_hash = public_key(1).wpubkey_hash().unwrap();
+        let p2wpkh = prevout(ScriptBuf::new_p2wpkh(&key_hash));
+        let script_code = ScriptBuf::new_p2pkh(&public_key(1).pubkey_hash());
+        let signature = segwit_signature(&spend(&p2wpkh, ScriptBuf::new(), vec![]), &p2wpkh, &script_code, 1);
+        let witness = vec![signature, public_key(1).to_bytes()];
+        assert_eq!(verify(&spend(&p2wpkh, ScriptBuf::new(), witness.clone()), &p2wpkh), Ok(()));
+        assert_eq!(
+            verify(&spend(&p2wpkh, pushes(&[vec![7; 2]]), witness.clone()), &p2wpkh),
+            exec_error(ExecError::WitnessMalleated),
+        );
+        assert_eq!(
+            verify(&spend(&p2wpkh, ScriptBuf::new(), witness[1..].to_vec()), &p2wpkh),
+            exec_error(ExecError::WitnessProgramMismatch),
+        );
+
+        let witness_script = multisig();
+        let p2wsh = prevout(ScriptBuf::new_p2wsh(&witness_script.wscript_hash()));
+        let unsigned = spend(&p2wsh, ScriptBuf::new(), vec![]);
+        let witness = vec![
+            vec![],
+            segwit_signature(&unsigned, &p2wsh, &witness_script, 2),
+            segwit_signature(&unsigned, &p2wsh, &witness_script, 3),
+            witness_script.to_bytes(),
+        ];
+        assert_eq!(verify(&spend(&p2wsh, ScriptBuf::new(), witness.clone()), &p2wsh), Ok(()));
+        assert_eq!(verify(&spend(&p2wsh, ScriptBuf::new(), vec![]), &p2wsh), exec_error(ExecError::WitnessProgramWitnessEmpty));
+
+        let mut other_script = witness.clone();
+        *other_script.last_mut().unwrap() = Builder::new().push_int(1).into_bytes();
+        assert_eq!(
+            verify(&spend(&p2wsh, ScriptBuf::new(), other_script), &p2wsh),
+            exec_error(ExecError::WitnessProgramMismatch),
+        );
+    }
+
+    #[test]
+    fn nested_segwit_spends_push_only_the_redeem_script() {
+        let redeem_script = ScriptBuf::new_p2wpkh(&public_key(1).wpubkey_hash().unwrap());
+        let prevout = prevout(ScriptBuf::new_p2sh(&redeem_script.script_hash()));
+        let script_code = ScriptBuf::new_p2pkh(&public_key(1).pubkey_hash());
+        let signature = segwit_signature(&spend(&prevout, ScriptBuf::new(), vec![]), &prevout, &script_code, 1);
+        let witness = vec![signature, public_key(1).to_bytes()];
+
+        let nested = spend(&prevout, pushes(&[redeem_script.to_bytes()]), witness.clone());
+        assert_eq!(verify(&nested, &prevout), Ok(()));
+
+        let padded = spend(&prevout, pushes(&[vec![7; 2], redeem_script.to_bytes()]), witness);
+        assert_eq!(verify(&padded, &prevout), exec_error(ExecError::WitnessMalleatedP2SH));
+    }
+
+    #[test]
+    fn taproot_spends_check_the_key_or_the_leaf() {
+        let secp = Secp256k1::new();
+        let internal = Keypair::from_secret_key(&secp, &secret(1));
+        let leaf = Builder::new()
+            .push_x_only_key(&Keypair::from_secret_key(&secp, &secret(2)).x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let info = TaprootBuilder::new()
+            .add_leaf(0, leaf.clone())
+            .unwrap()
+            .finalize(&secp, internal.x_only_public_key().0)
+            .unwrap();
+        let prevout = prevout(ScriptBuf::new_p2tr_tweaked(info.output_key()));
+        let prevouts = [prevout.clone()];
+        let unsigned = spend(&prevout, ScriptBuf::new(), vec![]);
+        let mut cache = SighashCache::new(&unsigned);
+
+        let sighash = cache.taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default).unwrap();
+        let tweaked = internal.tap_tweak(&secp, info.merkle_root()).to_inner();
+        let signature = secp.sign_schnorr_no_aux_rand(&Message::from(sighash), &tweaked);
+        let key_spend = spend(&prevout, ScriptBuf::new(), vec![signature.as_ref().to_vec()]);
+        assert_eq!(verify(&key_spend, &prevout), Ok(()));
+        let untweaked = secp.sign_schnorr_no_aux_rand(&Message::from(sighash), &internal);
+        let forged = spend(&prevout, ScriptBuf::new(), vec![untweaked.as_ref().to_vec()]);
+        assert_eq!(verify(&forged, &prevout), exec_error(ExecError::SchnorrSig));
+
+        let leaf_hash = TapLeafHash::from_script(&leaf, LeafVersion::TapScript);
+        let sighash = cache
+            .taproot_script_spend_signature_hash(0, &Prevouts::All(&prevouts), leaf_hash, TapSighashType::Default)
+            .unwrap();
+        let signer = Keypair::from_secret_key(&secp, &secret(2));
+        let signature = secp.sign_schnorr_no_aux_rand(&Message::from(sighash), &signer);
+        let control_block = info.control_block(&(leaf.clone(), LeafVersion::TapScript)).unwrap();
+        let witness = vec![signature.as_ref().to_vec(), leaf.to_bytes(), control_block.serialize()];
+        assert_eq!(verify(&spend(&prevout, ScriptBuf::new(), witness.clone()), &prevout), Ok(()));
+
+        let mut other_leaf = witness.clone();
+        other_leaf[1] = Builder::new().push_int(1).into_bytes();
+        assert_eq!(
+            verify(&spend(&prevout, ScriptBuf::new(), other_leaf), &prevout),
+            exec_error(ExecError::WitnessProgramMismatch),
+        );
+        let mut short_control = witness;
+        short_control[2].pop();
+        assert_eq!(
+            verify(&spend(&prevout, ScriptBuf::new(), short_control), &prevout),
+            exec_error(ExecError::TaprootWrongControlSize),
+        );
+    }
+
+    #[test]
+    fn tapscript_budget_counts_the_whole_witness() {
+        let secp = Secp256k1::new();
+        let signer = Keypair::from_secret_key(&secp, &secret(2));
+        let key = signer.x_only_public_key().0;
+        // `sigops` checks of the single signature on the stack.
+        let spend_leaf = |sigops: usize| {
+            let mut builder = Builder::new();
+            for _ in 1..sigops {
+                builder = builder.push_opcode(OP_DUP).push_x_only_key(&key).push_opcode(OP_CHECKSIGVERIFY);
+            }
+            let leaf = builder.push_x_only_key(&key).push_opcode(OP_CHECKSIG).into_script();
+            let info = TaprootBuilder::new().add_leaf(0, leaf.clone()).unwrap().finalize(&secp, key).unwrap();
+            let prevout = prevout(ScriptBuf::new_p2tr_tweaked(info.output_key()));
+            let prevouts = [prevout.clone()];
+            let unsigned = spend(&prevout, ScriptBuf::new(), vec![]);
+            let leaf_hash = TapLeafHash::from_script(&leaf, LeafVersion::TapScript);
+            let sighash = SighashCache::new(&unsigned)
+                .taproot_script_spend_signature_hash(0, &Prevouts::All(&prevouts), leaf_hash, TapSighashType::Default)
+                .unwrap();
+            let signature = secp.sign_schnorr_no_aux_rand(&Message::from(sighash), &signer);
+            let control_block = info.control_block(&(leaf.clone(), LeafVersion::TapScript)).unwrap();
+            let witness = vec![signature.as_ref().to_vec(), leaf.to_bytes(), control_block.serialize()];
+            verify(&spend(&prevout, ScriptBuf::new(), witness), &prevout)
+        };
+
+        // The signature alone budgets 50 + 66 weight, two signature checks.
+        // The script and control block pay for the third.
+        assert_eq!(spend_leaf(3), Ok(()));
+        assert_eq!(spend_leaf(12), exec_error(ExecError::TapscriptValidationWeight));
+    }
+
+    #[test]
+    fn tapscript_keys_off_the_curve_fail_the_signature_check() {
+        let secp = Secp256k1::new();
+        let internal = Keypair::from_secret_key(&secp, &secret(2)).x_only_public_key().0;
+        // Half of all x coordinates have no point on the curve.
+        let off_curve = (0u8..).map(|byte| [byte; 32]).find(|x| XOnlyPublicKey::from_slice(x).is_err()).unwrap();
+        let leaf = Builder::new().push_slice(off_curve).push_opcode(OP_CHECKSIG).into_script();
+        let info = TaprootBuilder::new().add_leaf(0, leaf.clone()).unwrap().finalize(&secp, internal).unwrap();
+        let prevout = prevout(ScriptBuf::new_p2tr_tweaked(info.output_key()));
+        let control_block = info.control_block(&(leaf.clone(), LeafVersion::TapScript)).unwrap();
+        let witness = vec![vec![1; 64], leaf.to_bytes(), control_block.serialize()];
+        assert_eq!(verify(&spend(&prevout, ScriptBuf::new(), witness), &prevout), exec_error(ExecError::SchnorrSig));
+    }
+}
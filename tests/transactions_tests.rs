@@ -0,0 +1,595 @@
+use bitcoin::hashes::Hash;
+use bitcoin::key::{Keypair, TapTweak, XOnlyPublicKey};
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, TxOut, Txid, Witness};
+
+use bitvm::execute_btc_script::{verify_input, Error, ExecCtx, ExecError, Options, VerifyFlags};
+use bitvm::gate_scripts::opening_witness;
+use bitvm::graph::assert_stack;
+use bitvm::{
+    gate_scripts, run_script, BinaryCircuit, ChallengeTree, CircuitBuilder, CommitmentKeys, Equivocation, GateId, GraphError,
+    GraphParams, GraphTx, InputSpend, ScriptRun, TreeShape, TxGraph, TxKind, WireId,
+};
+
+const PROVER_TIMEOUT: u16 = 12;
+
+fn keypair(secret: u8) -> Keypair {
+    Keypair::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[secret; 32]).unwrap())
+}
+
+fn x_only_key(secret: u8) -> XOnlyPublicKey {
+    keypair(secret).x_only_public_key().0
+}
+
+fn p2tr(secret: u8) -> ScriptBuf {
+    ScriptBuf::new_p2tr(&Secp256k1::new(), x_only_key(secret), None)
+}
+
+fn nand_circuit() -> BinaryCircuit {
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input();
+    let b = builder.input();
+    let nand = builder.nand(a, b);
+    builder.output(nand);
+    builder.build()
+}
+
+/// Trace of the circuit on `true, false`, whose NAND output is set.
+fn honest_trace() -> Vec<bool> {
+    nand_circuit().evaluate(&[true, false]).unwrap()
+}
+
+fn params(keys: &CommitmentKeys, funding: Vec<u64>) -> GraphParams {
+    GraphParams {
+        funding: funding.into_iter().enumerate()
+            .map(|(index, sats)| {
+                let outpoint = OutPoint::new(Txid::all_zeros(), index as u32);
+                (outpoint, TxOut { value: Amount::from_sat(sats), script_pubkey: p2tr(1) })
+            })
+            .collect(),
+        prover: x_only_key(1),
+        commitments: keys.commit_circuit(&nand_circuit()),
+        committee: x_only_key(2),
+        take_timeout: 6,
+        fee: Amount::from_sat(1_000),
+        prover_payout: p2tr(1),
+        committee_payout: p2tr(2),
+    }
+}
+
+fn graph(keys: &CommitmentKeys) -> TxGraph {
+    let circuit = nand_circuit();
+    let leaves = gate_scripts(&circuit, &keys.commit_circuit(&circuit)).unwrap();
+    let tree = ChallengeTree::build(&circuit, x_only_key(1), x_only_key(2), PROVER_TIMEOUT, leaves, TreeShape::Balanced).unwrap();
+    TxGraph::build(params(keys, vec![60_000, 40_000]), tree).unwrap()
+}
+
+fn leaf(graph_tx: &GraphTx, input_idx: usize) -> ScriptBuf {
+    match &graph_tx.spends[input_idx] {
+        InputSpend::Leaf { script, .. } => script.clone(),
+        InputSpend::External => panic!("input {} is not a script spend", input_idx),
+    }
+}
+
+/// Stack spending input 0 of the `kind` transaction with both signatures;
+/// `assert` also opens the honest trace.
+fn claim_stack(keys: &CommitmentKeys, kind: TxKind, committee: Vec<u8>, prover: Vec<u8>) -> Vec<Vec<u8>> {
+    match kind {
+        TxKind::Assert => assert_stack(&committee, &prover, &keys.reveal_trace(&honest_trace())),
+        _ => vec![committee, prover],
+    }
+}
+
+/// Runs input 0 of `graph_tx` with the prover's and the committee's signatures.
+fn run_cooperative(keys: &CommitmentKeys, kind: TxKind, graph_tx: &GraphTx) -> ScriptRun {
+    let secp = Secp256k1::new();
+    let message = Message::from(graph_tx.signature_hash(0).unwrap());
+    let prover = secp.sign_schnorr_no_aux_rand(&message, &keypair(1));
+    let committee = secp.sign_schnorr_no_aux_rand(&message, &keypair(2));
+    let witness = claim_stack(keys, kind, committee.as_ref().to_vec(), prover.as_ref().to_vec());
+    run_script(ExecCtx::Tapscript, Options::default(), graph_tx.tx_template(0), leaf(graph_tx, 0), witness).unwrap()
+}
+
+#[test]
+fn links_the_transactions_of_the_graph() {
+    let graph = graph(&CommitmentKeys::from_seed(b"graph"));
+    assert_eq!(graph.kickoff.tx.input.len(), 2);
+    assert_eq!(graph.kickoff.spends, vec![InputSpend::External; 2]);
+    assert_eq!(graph.kickoff.tx.output[0].value, Amount::from_sat(99_000));
+    assert_eq!(graph.assert.tx.input[0].previous_output, graph.kickoff.outpoint(0));
+    assert_eq!(graph.assert.prevouts, vec![graph.kickoff.tx.output[0].clone()]);
+    assert_eq!(graph.challenge.tx.input[0].previous_output, graph.assert.outpoint(0));
+    assert_eq!(graph.take.tx.input[0].previous_output, graph.assert.outpoint(0));
+    assert_eq!(graph.take.tx.input[0].sequence, Sequence::from_height(6));
+    assert_eq!(graph.challenge.tx.output[0].script_pubkey, graph.tree().output_script());
+    assert_eq!(graph.take.tx.output[0].value, Amount::from_sat(97_000));
+
+    let disprove = graph.disprove(GateId(0)).unwrap();
+    assert_eq!(disprove.tx.input[0].previous_output, graph.challenge.outpoint(0));
+    assert_eq!(disprove.tx.output[0].value, Amount::from_sat(96_000));
+    assert_eq!(leaf(&disprove, 0), graph.tree().leaf(GateId(0)).unwrap().violation);
+    assert!(graph.disprove(GateId(1)).is_none());
+}
+
+#[test]
+fn control_blocks_commit_to_the_spent_outputs() {
+    let secp = Secp256k1::verification_only();
+    let graph = graph(&CommitmentKeys::from_seed(b"graph"));
+    let disprove = graph.disprove(GateId(0)).unwrap();
+    let reclaim = graph.reclaim();
+    let punish = graph.punish(WireId(2)).unwrap();
+    for graph_tx in &[&graph.assert, &graph.challenge, &graph.take, &disprove, &reclaim, &punish] {
+        let (script, control_block) = match &graph_tx.spends[0] {
+            InputSpend::Leaf { script, control_block } => (script, control_block),
+            InputSpend::External => unreachable!(),
+        };
+        let output_key = XOnlyPublicKey::from_slice(&graph_tx.prevouts[0].script_pubkey.as_bytes()[2..]).unwrap();
+        assert!(control_block.verify_taproot_commitment(&secp, output_key, script));
+
+        let witness = graph_tx.witness(0, vec![vec![1]]).unwrap();
+        assert_eq!(witness, vec![vec![1], script.to_bytes(), control_block.serialize()]);
+    }
+    assert!(graph.kickoff.witness(0, vec![]).is_none());
+}
+
+#[test]
+fn pre_signed_spends_run_in_the_interpreter() {
+    let keys = CommitmentKeys::from_seed(b"graph");
+    let graph = graph(&keys);
+    let messages = graph.signing_messages();
+    let kinds: Vec<TxKind> = messages.iter().map(|message| message.kind).collect();
+    assert_eq!(kinds, vec![TxKind::Assert, TxKind::Challenge, TxKind::Take]);
+    for message in &messages {
+        let graph_tx = graph.tx(message.kind);
+        assert_eq!(Some(message.sighash), graph_tx.signature_hash(message.input_idx));
+        assert!(run_cooperative(&keys, message.kind, graph_tx).success(), "{:?}", message.kind);
+    }
+
+    let mut early = graph.take.clone();
+    early.tx.input[0].sequence = Sequence::from_height(5);
+    assert_eq!(run_cooperative(&keys, TxKind::Take, &early).result.error, Some(ExecError::UnsatisfiedLocktime));
+}
+
+#[test]
+fn finalized_transactions_pass_script_verification() {
+    let secp = Secp256k1::new();
+    let keys = CommitmentKeys::from_seed(b"graph");
+    let graph = graph(&keys);
+    let flags = VerifyFlags::default();
+
+    // The prover funds kickoff from key-path outputs.
+    let mut kickoff = graph.kickoff.tx.clone();
+    let tweaked = keypair(1).tap_tweak(&secp, None).to_inner();
+    let mut cache = SighashCache::new(&graph.kickoff.tx);
+    for (input_idx, input) in kickoff.input.iter_mut().enumerate() {
+        let sighash = cache
+            .taproot_key_spend_signature_hash(input_idx, &Prevouts::All(&graph.kickoff.prevouts), TapSighashType::Default)
+            .unwrap();
+        let signature = secp.sign_schnorr_no_aux_rand(&Message::from(sighash), &tweaked);
+        input.witness = Witness::from_slice(&[signature.as_ref()]);
+    }
+    for input_idx in 0..kickoff.input.len() {
+        assert_eq!(verify_input(&kickoff, input_idx, &graph.kickoff.prevouts, &flags), Ok(()));
+    }
+
+    for &kind in &[TxKind::Assert, TxKind::Challenge, TxKind::Take] {
+        let graph_tx = graph.tx(kind);
+        let message = Message::from(graph_tx.signature_hash(0).unwrap());
+        let prover = secp.sign_schnorr_no_aux_rand(&message, &keypair(1)).as_ref().to_vec();
+        let committee = secp.sign_schnorr_no_aux_rand(&message, &keypair(2)).as_ref().to_vec();
+
+        let mut tx = graph_tx.tx.clone();
+        let stack = claim_stack(&keys, kind, committee.clone(), prover.clone());
+        tx.input[0].witness = Witness::from_slice(&graph_tx.witness(0, stack).unwrap());
+        assert_eq!(verify_input(&tx, 0, &graph_tx.prevouts, &flags), Ok(()), "{:?}", kind);
+
+        let swapped = claim_stack(&keys, kind, prover, committee);
+        tx.input[0].witness = Witness::from_slice(&graph_tx.witness(0, swapped).unwrap());
+        assert_eq!(verify_input(&tx, 0, &graph_tx.prevouts, &flags), Err(Error::Exec(ExecError::SchnorrSig)));
+    }
+}
+
+/// Runs `assert` with both signatures over an opening of `preimages`.
+fn run_assert(graph: &TxGraph, preimages: &[[u8; 20]]) -> ScriptRun {
+    let secp = Secp256k1::new();
+    let message = Message::from(graph.assert.signature_hash(0).unwrap());
+    let prover = secp.sign_schnorr_no_aux_rand(&message, &keypair(1));
+    let committee = secp.sign_schnorr_no_aux_rand(&message, &keypair(2));
+    let stack = assert_stack(committee.as_ref(), prover.as_ref(), preimages);
+    run_script(ExecCtx::Tapscript, Options::default(), graph.assert.tx_template(0), leaf(&graph.assert, 0), stack).unwrap()
+}
+
+#[test]
+fn assert_opens_every_wire() {
+    let keys = CommitmentKeys::from_seed(b"graph");
+    let graph = graph(&keys);
+    let mut preimages = keys.reveal_trace(&honest_trace());
+    assert!(run_assert(&graph, &preimages).success());
+
+    // The openings end up in the witness, wire 0 on top of the signatures.
+    let stack = assert_stack(&[1; 64], &[2; 64], &preimages);
+    assert_eq!(stack.len(), 5);
+    assert_eq!(stack[4], preimages[0].to_vec());
+
+    preimages[1] = CommitmentKeys::from_seed(b"other").preimage(WireId(1), false);
+    assert_eq!(run_assert(&graph, &preimages).result.error, Some(ExecError::EqualVerify));
+    let missing = &keys.reveal_trace(&honest_trace())[..2];
+    assert!(!run_assert(&graph, missing).success());
+}
+
+#[test]
+fn disprove_needs_openings_that_break_the_gate() {
+    let keys = CommitmentKeys::from_seed(b"graph");
+    let graph = graph(&keys);
+    let disprove = graph.disprove(GateId(0)).unwrap();
+    assert!(disprove.signature_hash(0).is_some());
+    let gate = graph.tree().leaf(GateId(0)).unwrap();
+
+    // The verifier takes the gate's openings from what the prover asserted.
+    let run_disprove = |trace: &[bool]| {
+        let preimages = keys.reveal_trace(trace);
+        assert!(run_assert(&graph, &preimages).success());
+        let witness = opening_witness(&preimages[gate.inputs[0].0], &preimages[gate.inputs[1].0], &preimages[gate.output.0]);
+        run_script(ExecCtx::Tapscript, Options::default(), disprove.tx_template(0), leaf(&disprove, 0), witness).unwrap()
+    };
+
+    let honest = run_disprove(&honest_trace());
+    assert_eq!(honest.result.error, None);
+    assert!(!honest.success());
+
+    let mut wrong = honest_trace();
+    wrong[gate.output.0] = false;
+    assert!(run_disprove(&wrong).success());
+}
+
+#[test]
+fn reclaim_pays_the_prover_after_the_timeout() {
+    let graph = graph(&CommitmentKeys::from_seed(b"graph"));
+    let reclaim = graph.reclaim();
+    assert_eq!(reclaim.tx.input[0].previous_output, graph.challenge.outpoint(0));
+    assert_eq!(reclaim.tx.input[0].sequence, Sequence::from_height(PROVER_TIMEOUT));
+    assert_eq!(reclaim.tx.output[0], TxOut { value: Amount::from_sat(96_000), script_pubkey: p2tr(1) });
+    assert_eq!(leaf(&reclaim, 0), graph.tree().timeout);
+
+    let run = |graph_tx: &GraphTx, signer: u8| {
+        let message = Message::from(graph_tx.signature_hash(0).unwrap());
+        let signature = Secp256k1::new().sign_schnorr_no_aux_rand(&message, &keypair(signer));
+        let witness = vec![signature.as_ref().to_vec()];
+        run_script(ExecCtx::Tapscript, Options::default(), graph_tx.tx_template(0), leaf(graph_tx, 0), witness).unwrap()
+    };
+    assert!(run(&reclaim, 1).success());
+    assert_eq!(run(&reclaim, 2).result.error, Some(ExecError::SchnorrSig));
+    let mut early = reclaim.clone();
+    early.tx.input[0].sequence = Sequence::from_height(PROVER_TIMEOUT - 1);
+    assert_eq!(run(&early, 1).result.error, Some(ExecError::UnsatisfiedLocktime));
+}
+
+#[test]
+fn punish_needs_both_openings_of_a_wire() {
+    let keys = CommitmentKeys::from_seed(b"graph");
+    let graph = graph(&keys);
+    let punish = graph.punish(WireId(2)).unwrap();
+    assert_eq!(punish.tx.input[0].previous_output, graph.assert.outpoint(0));
+    assert_eq!(punish.tx.output[0], TxOut { value: Amount::from_sat(97_000), script_pubkey: p2tr(2) });
+    assert!(graph.punish(WireId(3)).is_none());
+
+    let equivocation = Equivocation {
+        wire: WireId(2),
+        preimage0: keys.preimage(WireId(2), false).to_vec(),
+        preimage1: keys.preimage(WireId(2), true).to_vec(),
+    };
+    let run = |equivocation: &Equivocation, signer: u8| {
+        let message = Message::from(punish.signature_hash(0).unwrap());
+        let signature = Secp256k1::new().sign_schnorr_no_aux_rand(&message, &keypair(signer));
+        let witness = equivocation.witness(signature.as_ref());
+        run_script(ExecCtx::Tapscript, Options::default(), punish.tx_template(0), leaf(&punish, 0), witness).unwrap()
+    };
+    assert!(run(&equivocation, 2).success());
+    assert_eq!(run(&equivocation, 1).result.error, Some(ExecError::SchnorrSig));
+    let single = Equivocation { preimage1: equivocation.preimage0.clone(), ..equivocation.clone() };
+    assert_eq!(run(&single, 2).result.error, Some(ExecError::EqualVerify));
+}
+
+#[test]
+fn rejects_underfunded_graphs() {
+    let keys = CommitmentKeys::from_seed(b"graph");
+    let tree = graph(&keys).tree().clone();
+    assert_eq!(TxGraph::build(params(&keys, vec![]), tree.clone()).err(), Some(GraphError::NoFunding));
+    assert_eq!(
+        TxGraph::build(params(&keys, vec![3_000, 1_000]), tree.clone()).err(),
+        Some(GraphError::InsufficientFunds { available: Amount::from_sat(4_000), needed: Amount::from_sat(4_000) })
+    );
+    assert_eq!(TxGraph::build(params(&keys, vec![u64::MAX, 1]), tree.clone()).err(), Some(GraphError::FundingOverflow));
+
+    let mut uncommitted = params(&keys, vec![100_000]);
+    uncommitted.commitments.truncate(2);
+    assert_eq!(TxGraph::build(uncommitted, tree).err(), Some(GraphError::MissingCommitment(WireId(2))));
+}
+
+mod presign {
+    use bitcoin::hashes::Hash;
+    use bitcoin::key::XOnlyPublicKey;
+    use bitcoin::secp256k1::{Message, Secp256k1};
+    use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+    use bitcoin::{Amount, Txid};
+
+    use bitvm::execute_btc_script::{ExecCtx, ExecError, Options};
+    use bitvm::presign::{simulate, StoreError};
+    use bitvm::{
+        run_script, CommitmentKeys, PartialSignature, PresignedSet, Signer, SigningError, SigningSession, TxGraph, TxKind,
+    };
+
+    fn signers() -> Vec<Signer> {
+        vec![Signer::new(super::keypair(1)), Signer::new(super::keypair(2))]
+    }
+
+    fn keys(signers: &[Signer]) -> Vec<XOnlyPublicKey> {
+        signers.iter().map(Signer::public_key).collect()
+    }
+
+    fn commitment_keys() -> CommitmentKeys {
+        CommitmentKeys::from_seed(b"presign")
+    }
+
+    fn graph() -> TxGraph {
+        super::graph(&commitment_keys())
+    }
+
+    #[test]
+    fn collected_signatures_unlock_the_graph() {
+        let graph = graph();
+        let signers = signers();
+        let set = simulate(&graph, &signers).unwrap();
+        assert_eq!(set.graph, graph.kickoff.txid());
+        assert_eq!(set.signatures.len(), 3 * 2);
+        assert_eq!(set.verify(&graph, &keys(&signers)), Ok(()));
+
+        for &kind in &[TxKind::Assert, TxKind::Challenge, TxKind::Take] {
+            let graph_tx = graph.tx(kind);
+            let witness = super::claim_stack(
+                &commitment_keys(),
+                kind,
+                set.signature(kind, 0, &super::x_only_key(2)).unwrap().to_vec(),
+                set.signature(kind, 0, &super::x_only_key(1)).unwrap().to_vec(),
+            );
+            let run = run_script(ExecCtx::Tapscript, Options::default(), graph_tx.tx_template(0), super::leaf(graph_tx, 0), witness);
+            assert!(run.unwrap().success(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_partial_signatures() {
+        let graph = graph();
+        let signers = signers();
+        let mut session = SigningSession::new(&graph, keys(&signers));
+        let partials = signers[0].sign(session.messages());
+        assert_eq!(session.missing(), 6);
+
+        let stranger = Signer::new(super::keypair(9));
+        let foreign = stranger.sign(session.messages()).remove(0);
+        assert_eq!(session.receive(foreign.clone()), Err(SigningError::UnknownSigner(stranger.public_key())));
+
+        let kickoff = PartialSignature { kind: TxKind::Kickoff, ..partials[0].clone() };
+        assert_eq!(session.receive(kickoff), Err(SigningError::UnknownInput { kind: TxKind::Kickoff, input_idx: 0 }));
+
+        // A signature over the assert sighash does not sign the challenge.
+        let swapped = PartialSignature { kind: TxKind::Challenge, ..partials[0].clone() };
+        let signer = signers[0].public_key();
+        assert_eq!(
+            session.receive(swapped),
+            Err(SigningError::InvalidSignature { kind: TxKind::Challenge, input_idx: 0, signer, error: ExecError::SchnorrSig })
+        );
+        let empty = PartialSignature { signature: vec![], ..partials[0].clone() };
+        assert!(matches!(session.receive(empty), Err(SigningError::InvalidSignature { .. })));
+
+        session.receive(partials[0].clone()).unwrap();
+        assert_eq!(
+            session.receive(partials[0].clone()),
+            Err(SigningError::AlreadySigned { kind: TxKind::Assert, input_idx: 0, signer })
+        );
+        assert_eq!(session.finish(), Err(SigningError::Incomplete { missing: 5 }));
+    }
+
+    #[test]
+    fn rejects_signatures_with_an_explicit_sighash_type() {
+        let graph = graph();
+        let signers = signers();
+        let mut session = SigningSession::new(&graph, keys(&signers));
+        let take = &graph.take;
+        let sign = |sighash_type: TapSighashType| {
+            let sighash = SighashCache::new(&take.tx)
+                .taproot_script_spend_signature_hash(0, &Prevouts::All(&take.prevouts), take.leaf_hash(0).unwrap(), sighash_type)
+                .unwrap();
+            let signature = Secp256k1::new().sign_schnorr_no_aux_rand(&Message::from(sighash), &super::keypair(2));
+            let mut bytes = signature.as_ref().to_vec();
+            bytes.push(sighash_type as u8);
+            PartialSignature { kind: TxKind::Take, input_idx: 0, signer: signers[1].public_key(), signature: bytes }
+        };
+
+        for &sighash_type in &[
+            TapSighashType::Default,
+            TapSighashType::All,
+            TapSighashType::None,
+            TapSighashType::Single,
+            TapSighashType::AllPlusAnyoneCanPay,
+        ] {
+            assert!(
+                matches!(
+                    session.receive(sign(sighash_type)),
+                    Err(SigningError::InvalidSignature { error: ExecError::SchnorrSigHashtype, .. })
+                ),
+                "{:?}",
+                sighash_type
+            );
+        }
+        let mut default = sign(TapSighashType::Default);
+        default.signature.pop();
+        session.receive(default).unwrap();
+    }
+
+    #[test]
+    fn persists_the_completed_set() {
+        let graph = graph();
+        let signers = signers();
+        let set = simulate(&graph, &signers).unwrap();
+        assert_eq!(PresignedSet::from_json(&set.to_json()).unwrap(), set);
+
+        let path = std::env::temp_dir().join(format!("presigned-{}.json", graph.kickoff.txid()));
+        set.save(&path).unwrap();
+        let loaded = PresignedSet::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, set);
+        assert_eq!(loaded.verify(&graph, &keys(&signers)), Ok(()));
+
+        let mut tampered = loaded.clone();
+        tampered.signatures[3].signature[0] ^= 1;
+        assert!(matches!(tampered.verify(&graph, &keys(&signers)), Err(SigningError::InvalidSignature { .. })));
+        let mut partial = loaded.clone();
+        partial.signatures.pop();
+        assert_eq!(partial.verify(&graph, &keys(&signers)), Err(SigningError::Incomplete { missing: 1 }));
+
+        let mut params = super::params(&commitment_keys(), vec![100_000]);
+        params.fee = Amount::from_sat(2_000);
+        let other = TxGraph::build(params, graph.tree().clone()).unwrap();
+        assert_eq!(loaded.verify(&other, &keys(&signers)), Err(SigningError::WrongGraph(graph.kickoff.txid())));
+
+        let unsupported = set.to_json().replacen("\"version\":1", "\"version\":2", 1);
+        assert!(matches!(PresignedSet::from_json(&unsupported), Err(StoreError::UnsupportedVersion(2))));
+        let bad_txid = PresignedSet { graph: Txid::all_zeros(), ..set }.to_json().replace(&"0".repeat(64), "zz");
+        assert!(matches!(PresignedSet::from_json(&bad_txid), Err(StoreError::InvalidField { .. })));
+        assert!(matches!(PresignedSet::load(&path), Err(StoreError::Io(_))));
+    }
+}
+
+mod musig {
+    use std::str::FromStr;
+
+    use bitcoin::key::XOnlyPublicKey;
+    use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+    use bitcoin::taproot::TaprootBuilder;
+
+    use bitvm::execute_btc_script::{ExecCtx, Options};
+    use bitvm::musig::{nonce_gen, sign_messages, simulate, AggregateNonce, PublicNonce};
+    use bitvm::{
+        run_script, ChallengeTree, CommitmentKeys, KeyAggContext, MusigError, MusigSession, Signer, SigningSession, TreeShape,
+        TxGraph, TxKind,
+    };
+
+    fn secrets() -> Vec<SecretKey> {
+        (10..13).map(|byte| SecretKey::from_slice(&[byte; 32]).unwrap()).collect()
+    }
+
+    fn context(secrets: &[SecretKey]) -> KeyAggContext {
+        let secp = Secp256k1::new();
+        KeyAggContext::new(secrets.iter().map(|secret| PublicKey::from_secret_key(&secp, secret)).collect()).unwrap()
+    }
+
+    #[test]
+    fn aggregates_keys_like_bip_327() {
+        let key = |hex: &str| PublicKey::from_str(hex).unwrap();
+        let x1 = key("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9");
+        let x2 = key("03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659");
+        let x3 = key("023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66");
+        let aggregate = |keys: Vec<PublicKey>| KeyAggContext::new(keys).unwrap().aggregate_key();
+        let expected = |hex: &str| XOnlyPublicKey::from_str(hex).unwrap();
+        assert_eq!(aggregate(vec![x1, x2, x3]), expected("90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C"));
+        assert_eq!(aggregate(vec![x3, x2, x1]), expected("6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B"));
+        assert_eq!(aggregate(vec![x1, x1, x1]), expected("B436E3BAD62B8CD409969A224731C193D051162D8C5AE8B109306127DA3AA935"));
+        assert_eq!(aggregate(vec![x1, x1, x2, x2]), expected("69BC22BFA5D106306E48A20679DE1D7389386124D07571D0D872686028C26A3E"));
+        assert_eq!(KeyAggContext::new(vec![]), Err(MusigError::NoKeys));
+    }
+
+    #[test]
+    fn aggregate_signatures_verify_under_the_aggregate_key() {
+        let secrets = secrets();
+        let context = context(&secrets);
+        let message = [7; 32];
+        let signature = simulate(&context, &secrets, message).unwrap();
+        let secp = Secp256k1::verification_only();
+        assert!(secp.verify_schnorr(&signature, &Message::from_digest(message), &context.aggregate_key()).is_ok());
+        // Every member has to sign.
+        let incomplete = simulate(&context, &secrets[..2], message).unwrap();
+        assert!(secp.verify_schnorr(&incomplete, &Message::from_digest(message), &context.aggregate_key()).is_err());
+    }
+
+    #[test]
+    fn committee_signs_the_graph_as_one_key() {
+        let secrets = secrets();
+        let context = context(&secrets);
+        let keys = CommitmentKeys::from_seed(b"musig");
+        let circuit = super::nand_circuit();
+        let leaves = bitvm::gate_scripts(&circuit, &keys.commit_circuit(&circuit)).unwrap();
+        let committee = context.aggregate_key();
+        let tree =
+            ChallengeTree::build(&circuit, super::x_only_key(1), committee, super::PROVER_TIMEOUT, leaves, TreeShape::Balanced)
+                .unwrap();
+        let mut params = super::params(&keys, vec![100_000]);
+        params.committee = committee;
+        let graph = TxGraph::build(params, tree).unwrap();
+
+        let prover = Signer::new(super::keypair(1));
+        let mut session = SigningSession::new(&graph, vec![prover.public_key(), committee]);
+        for partial in prover.sign(session.messages()) {
+            session.receive(partial).unwrap();
+        }
+        for partial in sign_messages(&context, &secrets, session.messages()).unwrap() {
+            assert_eq!(partial.signature.len(), 64);
+            session.receive(partial).unwrap();
+        }
+        let set = session.finish().unwrap();
+
+        let take = graph.tx(TxKind::Take);
+        let witness = vec![
+            set.signature(TxKind::Take, 0, &committee).unwrap().to_vec(),
+            set.signature(TxKind::Take, 0, &prover.public_key()).unwrap().to_vec(),
+        ];
+        let run = run_script(ExecCtx::Tapscript, Options::default(), take.tx_template(0), super::leaf(take, 0), witness);
+        assert!(run.unwrap().success());
+    }
+
+    #[test]
+    fn signs_for_a_tweaked_internal_key() {
+        let secrets = secrets();
+        let context = context(&secrets);
+        let secp = Secp256k1::new();
+        let leaf = bitcoin::ScriptBuf::from_bytes(vec![0x51]);
+        let spend_info =
+            TaprootBuilder::new().add_leaf(0, leaf).unwrap().finalize(&secp, context.aggregate_key()).unwrap();
+        let tweaked = context.with_taproot_tweak(spend_info.merkle_root()).unwrap();
+        assert_eq!(tweaked.aggregate_key(), spend_info.output_key().to_inner());
+
+        let message = [9; 32];
+        let signature = simulate(&tweaked, &secrets, message).unwrap();
+        assert!(secp.verify_schnorr(&signature, &Message::from_digest(message), &tweaked.aggregate_key()).is_ok());
+    }
+
+    #[test]
+    fn checks_nonces_and_partial_signatures() {
+        let secrets = secrets();
+        let context = context(&secrets);
+        let secp = Secp256k1::new();
+        let message = [3; 32];
+        let (secret_nonces, public_nonces): (Vec<_>, Vec<_>) =
+            secrets.iter().map(|secret| nonce_gen(secret, &context, Some(&message))).unzip();
+        assert_eq!(PublicNonce::from_slice(&public_nonces[0].serialize()), Ok(public_nonces[0]));
+        assert_eq!(PublicNonce::from_slice(&[2; 65]), Err(MusigError::InvalidNonce));
+
+        let session = MusigSession::new(context.clone(), AggregateNonce::aggregate(&public_nonces).unwrap(), message);
+        let mut nonces = secret_nonces.into_iter();
+        let first = nonces.next().unwrap();
+        assert_eq!(session.sign(first, &secrets[1]).err(), Some(MusigError::NonceMismatch));
+
+        let outsider = SecretKey::from_slice(&[20; 32]).unwrap();
+        let (outsider_nonce, _) = nonce_gen(&outsider, &context, Some(&message));
+        let outsider_key = PublicKey::from_secret_key(&secp, &outsider);
+        assert_eq!(session.sign(outsider_nonce, &outsider).err(), Some(MusigError::UnknownSigner(outsider_key)));
+
+        let second = session.sign(nonces.next().unwrap(), &secrets[1]).unwrap();
+        let second_key = PublicKey::from_secret_key(&secp, &secrets[1]);
+        assert!(session.verify_partial(&second, &public_nonces[1], &second_key));
+        assert!(!session.verify_partial(&second, &public_nonces[2], &second_key));
+        assert!(!session.verify_partial(&second, &public_nonces[1], &PublicKey::from_secret_key(&secp, &secrets[2])));
+        assert_eq!(AggregateNonce::aggregate(&[]), Err(MusigError::NoNonces));
+    }
+}